@@ -188,6 +188,73 @@ fn main() -> Result<()> {
         )
     }
 
+    if command_line_args.storage_backend.is_some() {
+        assert_eq!(command_line_args.storage_backend, config.storage_backend)
+    } else {
+        assert_eq!(file_config.storage_backend, config.storage_backend)
+    }
+
+    assert_eq!(
+        config.compress_chunks,
+        file_config.compress_chunks || command_line_args.compress_chunks
+    );
+
+    assert_eq!(
+        config.erasure_coding,
+        file_config.erasure_coding || command_line_args.erasure_coding
+    );
+
+    if command_line_args.write_ack_policy.is_some() {
+        assert_eq!(command_line_args.write_ack_policy, config.write_ack_policy)
+    } else {
+        assert_eq!(file_config.write_ack_policy, config.write_ack_policy)
+    }
+
+    if command_line_args.replication_bandwidth.is_some() {
+        assert_eq!(
+            command_line_args.replication_bandwidth,
+            config.replication_bandwidth
+        )
+    } else {
+        assert_eq!(
+            file_config.replication_bandwidth,
+            config.replication_bandwidth
+        )
+    }
+
+    if command_line_args.replication_msg_rate.is_some() {
+        assert_eq!(
+            command_line_args.replication_msg_rate,
+            config.replication_msg_rate
+        )
+    } else {
+        assert_eq!(
+            file_config.replication_msg_rate,
+            config.replication_msg_rate
+        )
+    }
+
+    if command_line_args.encryption_passphrase.is_some() {
+        assert_eq!(
+            command_line_args.encryption_passphrase,
+            config.encryption_passphrase
+        )
+    } else {
+        assert_eq!(
+            file_config.encryption_passphrase,
+            config.encryption_passphrase
+        )
+    }
+
+    if command_line_args.encryption_key_file.is_some() {
+        assert_eq!(
+            command_line_args.encryption_key_file,
+            config.encryption_key_file
+        )
+    } else {
+        assert_eq!(file_config.encryption_key_file, config.encryption_key_file)
+    }
+
     clear_disk_config()?;
 
     Ok(())
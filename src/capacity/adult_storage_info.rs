@@ -6,20 +6,57 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
+use serde::{Deserialize, Serialize};
 use sn_routing::XorName;
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
+/// Number of tiers of free space Adults are ranked in when picking chunk holders. Coarse tiers
+/// keep the holders picked for nearby chunks steady as reports come in. Elders don't agree on
+/// levels, so the Elder closest to a new blob picks its holders for the others.
+const FREE_SPACE_TIERS: u64 = 4;
+
+/// How much storage an Adult uses, as it last reported.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StorageLevel {
+    /// Bytes used.
+    pub used: u64,
+    /// Bytes available at most.
+    pub max: u64,
+}
+
+impl StorageLevel {
+    /// Bytes still free.
+    pub fn free(&self) -> u64 {
+        self.max.saturating_sub(self.used)
+    }
+
+    /// Tier of the free space, from 0 for almost none to `FREE_SPACE_TIERS - 1` for most of it.
+    pub fn free_tier(&self) -> u64 {
+        if self.max == 0 {
+            return 0;
+        }
+        let tier = (self.free() as u128 * FREE_SPACE_TIERS as u128 / self.max as u128) as u64;
+        tier.min(FREE_SPACE_TIERS - 1)
+    }
+}
+
 #[derive(Clone, Default)]
 pub struct AdultsStorageInfo {
     pub full_adults: Arc<RwLock<BTreeSet<XorName>>>,
+    /// Storage levels the Adults reported last.
+    pub levels: Arc<RwLock<BTreeMap<XorName, StorageLevel>>>,
 }
 
 impl AdultsStorageInfo {
     ///
     pub fn new() -> Self {
         let full_adults = Arc::new(RwLock::new(BTreeSet::new()));
-        Self { full_adults }
+        let levels = Arc::new(RwLock::new(BTreeMap::new()));
+        Self {
+            full_adults,
+            levels,
+        }
     }
 }
@@ -0,0 +1,220 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::StorageLevel;
+use itertools::Itertools;
+use sn_routing::XorName;
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet},
+};
+
+/// Number of Adults, beyond the holders of a chunk, among which its holders are picked.
+///
+/// Holders are the emptier Adults among the closest ones to a chunk, rather than the closest
+/// ones, so the chunk is still found by looking at a few more Adults than it has copies.
+pub(crate) const HOLDER_CANDIDATE_SLACK: usize = 2;
+
+/// The `count` Adults to store new copies of a chunk at `name`, closest first.
+///
+/// They're picked among the `count + HOLDER_CANDIDATE_SLACK` Adults closest to `name` which aren't
+/// full, those with more free space, as per `levels`, going first. Adults which haven't reported
+/// their storage level yet count as having the least free space.
+pub(crate) fn select_write_holders<'a>(
+    name: &XorName,
+    adults: impl IntoIterator<Item = &'a XorName>,
+    full_adults: &BTreeSet<XorName>,
+    levels: &BTreeMap<XorName, StorageLevel>,
+    count: usize,
+) -> Vec<XorName> {
+    let free_tier = |adult: &XorName| levels.get(adult).map_or(0, StorageLevel::free_tier);
+    adults
+        .into_iter()
+        .filter(|adult| !full_adults.contains(adult))
+        .sorted_by(|lhs, rhs| name.cmp_distance(lhs, rhs))
+        .take(count + HOLDER_CANDIDATE_SLACK)
+        // a stable sort, so the closer of equally free Adults go first
+        .sorted_by_key(|adult| Reverse(free_tier(*adult)))
+        .take(count)
+        .sorted_by(|lhs, rhs| name.cmp_distance(lhs, rhs))
+        .copied()
+        .collect()
+}
+
+/// The Adults which may hold copies of a chunk at `name`, closest first: the
+/// `count + HOLDER_CANDIDATE_SLACK` Adults closest to it, full or not, and as many of the closest
+/// which aren't full.
+///
+/// Full Adults are read-only holders. They keep serving the chunks they stored before filling up,
+/// which count towards their copies, while new copies go to the Adults with room instead.
+/// Elders pick the holders of a chunk among these, and Adults work out with it whether they may
+/// still hold a chunk, so they agree as long as they agree on the Adults and the full ones among
+/// them, whatever storage levels each Elder heard of.
+pub(crate) fn select_read_holders<'a>(
+    name: &XorName,
+    adults: impl IntoIterator<Item = &'a XorName>,
+    full_adults: &BTreeSet<XorName>,
+    count: usize,
+) -> Vec<XorName> {
+    let count = count + HOLDER_CANDIDATE_SLACK;
+    let mut with_room = 0;
+    adults
+        .into_iter()
+        .sorted_by(|lhs, rhs| name.cmp_distance(lhs, rhs))
+        .enumerate()
+        .filter(|(index, adult)| {
+            if full_adults.contains(adult) {
+                *index < count
+            } else {
+                with_room += 1;
+                with_room <= count
+            }
+        })
+        .map(|(_, adult)| *adult)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn name(byte: u8) -> XorName {
+        XorName::from_content(&[&[byte]])
+    }
+
+    // Elders list the Adults in the order routing has them, while Adults keep track of them from
+    // the churn they see.
+    struct Views {
+        elder: Vec<XorName>,
+        adult: BTreeSet<XorName>,
+        full_adults: BTreeSet<XorName>,
+    }
+
+    impl Views {
+        fn churn(&mut self, added: &[u8], lost: &[u8]) {
+            let added = added.iter().copied().map(name).collect::<BTreeSet<_>>();
+            let lost = lost.iter().copied().map(name).collect::<BTreeSet<_>>();
+            self.elder.retain(|adult| !lost.contains(adult));
+            for adult in &added {
+                self.elder.insert(0, *adult);
+            }
+            let remaining = self
+                .adult
+                .difference(&lost)
+                .copied()
+                .collect::<BTreeSet<_>>();
+            self.adult = remaining.union(&added).copied().collect();
+            self.full_adults.retain(|adult| !lost.contains(adult));
+        }
+
+        fn assert_agree(&self) {
+            for chunk in (100..120).map(name) {
+                let levels = BTreeMap::new();
+                let holders =
+                    select_write_holders(&chunk, &self.elder, &self.full_adults, &levels, 4);
+                assert_eq!(
+                    holders,
+                    select_write_holders(&chunk, &self.adult, &self.full_adults, &levels, 4)
+                );
+                assert!(holders
+                    .iter()
+                    .all(|holder| !self.full_adults.contains(holder)));
+                let room = self.adult.difference(&self.full_adults).count();
+                assert_eq!(holders.len(), room.min(4));
+
+                // Full Adults among the closest keep holding their copies.
+                let read_holders = select_read_holders(&chunk, &self.elder, &self.full_adults, 4);
+                assert_eq!(
+                    read_holders,
+                    select_read_holders(&chunk, &self.adult, &self.full_adults, 4)
+                );
+                let zone = 4 + HOLDER_CANDIDATE_SLACK;
+                let candidates =
+                    select_write_holders(&chunk, &self.adult, &self.full_adults, &levels, zone);
+                let closest =
+                    select_write_holders(&chunk, &self.adult, &BTreeSet::new(), &levels, zone);
+                assert!(holders
+                    .iter()
+                    .chain(&candidates)
+                    .chain(&closest)
+                    .all(|holder| read_holders.contains(holder)));
+                assert!(read_holders
+                    .iter()
+                    .all(|holder| candidates.contains(holder) || closest.contains(holder)));
+            }
+        }
+    }
+
+    #[test]
+    fn elders_and_adults_agree_on_holders_under_churn() {
+        let mut views = Views {
+            elder: vec![],
+            adult: BTreeSet::new(),
+            full_adults: BTreeSet::new(),
+        };
+        views.churn(&[0, 1, 2, 3, 4, 5, 6, 7], &[]);
+        views.assert_agree();
+
+        // An Adult fills up.
+        let _ = views.full_adults.insert(name(3));
+        views.assert_agree();
+
+        // Adults join and leave, including a full one.
+        views.churn(&[8, 9], &[1, 3]);
+        views.assert_agree();
+
+        // Most of the Adults are full, leaving fewer holders than copies.
+        views
+            .full_adults
+            .extend([4, 5, 6, 7, 8].iter().copied().map(name));
+        views.assert_agree();
+
+        // They have room again.
+        views.full_adults.clear();
+        views.churn(&[10], &[0]);
+        views.assert_agree();
+    }
+
+    #[test]
+    fn emptier_adults_among_the_closest_are_picked_as_holders() {
+        let chunk = name(100);
+        let adults = (0..10).map(name).collect::<Vec<_>>();
+        let full_adults = BTreeSet::new();
+        let by_distance = select_read_holders(&chunk, &adults, &full_adults, adults.len());
+        let level = |used| StorageLevel { used, max: 100 };
+
+        // Without storage levels, the closest Adults are picked.
+        let mut levels = BTreeMap::new();
+        let holders = select_write_holders(&chunk, &adults, &full_adults, &levels, 4);
+        assert_eq!(holders, by_distance[..4].to_vec());
+
+        // Emptier Adults among the candidates go first, still listed closest first.
+        let _ = levels.insert(by_distance[0], level(90));
+        for adult in &by_distance[1..4] {
+            let _ = levels.insert(*adult, level(50));
+        }
+        let _ = levels.insert(by_distance[4], level(0));
+        let _ = levels.insert(by_distance[5], level(10));
+        // Beyond the candidates, an empty Adult isn't picked.
+        let _ = levels.insert(by_distance[6], level(0));
+        let holders = select_write_holders(&chunk, &adults, &full_adults, &levels, 4);
+        assert_eq!(
+            holders,
+            vec![
+                by_distance[1],
+                by_distance[2],
+                by_distance[4],
+                by_distance[5]
+            ]
+        );
+
+        // Holders are always among the Adults looked at for reads.
+        let read_holders = select_read_holders(&chunk, &adults, &full_adults, 4);
+        assert!(holders.iter().all(|holder| read_holders.contains(holder)));
+    }
+}
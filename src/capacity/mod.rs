@@ -7,9 +7,11 @@
 // permissions and limitations relating to use of the SAFE Network Software.
 
 mod adult_storage_info;
+mod holders;
 mod rate_limit;
 
-pub use adult_storage_info::AdultsStorageInfo;
+pub use adult_storage_info::{AdultsStorageInfo, StorageLevel};
+pub(crate) use holders::{select_read_holders, select_write_holders};
 pub use rate_limit::RateLimit;
 
 pub const MAX_SUPPLY: u64 = u32::MAX as u64 * 1_000_000_000_u64;
@@ -33,4 +35,23 @@ impl Capacity {
     pub async fn full_nodes(&self) -> u8 {
         self.adult_storage_info.full_adults.read().await.len() as u8
     }
+
+    /// Number of chunk storing nodes whose worth of storage is used up, out of `all_nodes`.
+    ///
+    /// It's the share of storage used, across the Adults which reported their storage levels,
+    /// scaled to `all_nodes`. It's never less than the number of full nodes.
+    pub async fn used_nodes(&self, all_nodes: u8) -> u8 {
+        let full_nodes = self.full_nodes().await;
+        let levels = self.adult_storage_info.levels.read().await;
+        let (used, max) = levels
+            .values()
+            .fold((0_u128, 0_u128), |(used, max), level| {
+                (used + level.used as u128, max + level.max as u128)
+            });
+        if max == 0 {
+            return full_nodes;
+        }
+        let used_nodes = (used.min(max) * all_nodes as u128 / max) as u8;
+        used_nodes.max(full_nodes)
+    }
 }
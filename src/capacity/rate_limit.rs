@@ -30,8 +30,9 @@ impl RateLimit {
         let prefix = self.network.our_prefix().await;
         let prefix_len = prefix.bit_count();
 
-        let full_nodes = self.capacity.full_nodes().await;
         let all_nodes = self.network.our_adults().await.len() as u8;
+        // The storage used at Adults which aren't full yet makes storing dearer too.
+        let full_nodes = self.capacity.used_nodes(all_nodes).await;
 
         RateLimit::rate_limit(bytes, full_nodes, all_nodes, prefix_len)
     }
@@ -0,0 +1,334 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::Backend;
+use crate::{chunk_store::chunk::ChunkId, to_db_key::from_db_key, Result};
+use log::{info, warn};
+use std::{
+    collections::HashMap,
+    convert::TryInto,
+    fs::{self, File, OpenOptions},
+    io::{BufReader, ErrorKind, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+const LOG_FILE_NAME: &str = "chunks.log";
+const COMPACTED_LOG_FILE_NAME: &str = "chunks.log.compacted";
+const QUARANTINE_DIR: &str = "quarantine";
+
+const PUT_RECORD: u8 = 0;
+const DELETE_RECORD: u8 = 1;
+
+/// Size of a record header: the record kind, the key length and the value length.
+const HEADER_LEN: u64 = 1 + 4 + 8;
+
+/// Keys are hex encoded chunk addresses, which are far shorter than this. A header with a longer
+/// key is corrupt.
+const MAX_KEY_LEN: u32 = 1024;
+
+/// The log is compacted when it holds more overwritten or deleted bytes than this.
+const MIN_DEAD_BYTES_TO_COMPACT: u64 = 64 * 1024 * 1024;
+
+/// Where a value lives within the log file.
+#[derive(Clone, Copy, Debug)]
+struct Location {
+    offset: u64,
+    len: u64,
+}
+
+/// Holds all chunks of a store in a single append-only log file.
+///
+/// Each record is a header, the chunk key and the chunk value. Deletes are appended as records
+/// without a value. An index of the latest value of every key is rebuilt from the log on open.
+///
+/// The log is compacted whenever it holds more dead records than live ones, past a threshold.
+pub(crate) struct LogBackend {
+    dir: PathBuf,
+    path: PathBuf,
+    file: File,
+    index: HashMap<String, Location>,
+    /// Length of the log file.
+    len: u64,
+    /// Bytes held by the live values.
+    live_bytes: u64,
+    /// Bytes held by records which have since been overwritten or deleted.
+    dead_bytes: u64,
+    min_dead_bytes_to_compact: u64,
+}
+
+impl LogBackend {
+    /// Opens the log at `dir`, creating it if needed.
+    ///
+    /// A record left incomplete by an interrupted write is cut off, and the log is compacted if it
+    /// holds more dead records than live ones. A corrupt record header fails the open.
+    pub(crate) fn open(dir: &Path) -> Result<Self> {
+        Self::open_compacting_at(dir, MIN_DEAD_BYTES_TO_COMPACT)
+    }
+
+    /// Opens the log at `dir` as `open` does, compacting it past `min_dead_bytes_to_compact` dead
+    /// bytes rather than the default.
+    pub(crate) fn open_compacting_at(dir: &Path, min_dead_bytes_to_compact: u64) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(LOG_FILE_NAME);
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)?;
+
+        let (index, valid_len, dead_bytes) = Self::load_index(&path)?;
+        if valid_len < file.metadata()?.len() {
+            warn!(
+                "Cutting off incomplete record at the end of {}",
+                path.display()
+            );
+            file.set_len(valid_len)?;
+            file.sync_all()?;
+        }
+
+        let live_bytes = index.values().map(|location| location.len).sum();
+        let mut backend = Self {
+            dir: dir.to_path_buf(),
+            path,
+            file,
+            index,
+            len: valid_len,
+            live_bytes,
+            dead_bytes,
+            min_dead_bytes_to_compact,
+        };
+        if backend.should_compact() {
+            backend.compact()?;
+        }
+
+        Ok(backend)
+    }
+
+    /// Reads through the log at `path`, returning the index of live values, the length of the log
+    /// up to its last complete record, and the number of dead bytes.
+    fn load_index(path: &Path) -> Result<(HashMap<String, Location>, u64, u64)> {
+        let file = File::open(path)?;
+        let file_len = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+        let mut index = HashMap::new();
+        let mut offset = 0;
+        let mut dead_bytes = 0;
+
+        while offset < file_len {
+            let (kind, key, value_len) = match read_header(&mut reader) {
+                Ok(header) => header,
+                Err(error) if error.kind() == ErrorKind::UnexpectedEof => break,
+                Err(error) => return Err(error.into()),
+            };
+            let record_len = HEADER_LEN + key.len() as u64 + value_len;
+            if offset + record_len > file_len {
+                break;
+            }
+            let _ = reader.seek(SeekFrom::Current(value_len as i64))?;
+
+            let replaced = match kind {
+                PUT_RECORD => index.insert(
+                    key.clone(),
+                    Location {
+                        offset: offset + HEADER_LEN + key.len() as u64,
+                        len: value_len,
+                    },
+                ),
+                _ => {
+                    dead_bytes += record_len;
+                    index.remove(&key)
+                }
+            };
+            if let Some(location) = replaced {
+                dead_bytes += HEADER_LEN + key.len() as u64 + location.len;
+            }
+            offset += record_len;
+        }
+
+        Ok((index, offset, dead_bytes))
+    }
+
+    fn should_compact(&self) -> bool {
+        self.dead_bytes > self.min_dead_bytes_to_compact && self.dead_bytes > self.live_bytes
+    }
+
+    /// Compacts the log if it's due, after a write. The write is done by then, so a failure is
+    /// only logged, for the compaction to be tried again after the next write.
+    fn compact_if_due(&mut self) {
+        if self.should_compact() {
+            if let Err(error) = self.compact() {
+                warn!("Could not compact {}: {:?}", self.path.display(), error);
+            }
+        }
+    }
+
+    /// Rewrites the log with only its live records. The log is left as it was on failure.
+    fn compact(&mut self) -> Result<()> {
+        info!("Compacting {}", self.path.display());
+        let compacted_path = self.dir.join(COMPACTED_LOG_FILE_NAME);
+        let mut compacted = File::create(&compacted_path)?;
+        let mut index = HashMap::new();
+        let mut offset = 0;
+        for (key, location) in &self.index {
+            let value = self.read_at(*location)?;
+            compacted.write_all(&record(PUT_RECORD, key, &value))?;
+            let _ = index.insert(
+                key.clone(),
+                Location {
+                    offset: offset + HEADER_LEN + key.len() as u64,
+                    len: location.len,
+                },
+            );
+            offset += HEADER_LEN + key.len() as u64 + location.len;
+        }
+        compacted.sync_all()?;
+        // Opened before the rename, which is then the last step which can fail.
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .open(&compacted_path)?;
+        fs::rename(&compacted_path, &self.path)?;
+
+        self.file = file;
+        self.index = index;
+        self.len = offset;
+        self.dead_bytes = 0;
+        // So the rename itself survives a crash.
+        File::open(&self.dir)?.sync_all()?;
+        Ok(())
+    }
+
+    fn append(&mut self, kind: u8, key: &str, value: &[u8]) -> Result<u64> {
+        let offset = self.file.metadata()?.len();
+        let record = record(kind, key, value);
+        let written = self
+            .file
+            .write_all(&record)
+            .and_then(|()| self.file.sync_data());
+        if let Err(error) = written {
+            // What was written of the record would be read as the start of the next one.
+            if let Err(truncate_error) = self.file.set_len(offset) {
+                warn!(
+                    "Could not cut off incomplete record at the end of {}: {:?}",
+                    self.path.display(),
+                    truncate_error
+                );
+            }
+            return Err(error.into());
+        }
+        self.len = offset + record.len() as u64;
+        Ok(offset)
+    }
+
+    fn read_at(&self, location: Location) -> Result<Vec<u8>> {
+        let mut file = File::open(&self.path)?;
+        let _ = file.seek(SeekFrom::Start(location.offset))?;
+        let mut value = vec![0; location.len as usize];
+        file.read_exact(&mut value)?;
+        Ok(value)
+    }
+}
+
+impl<Id: ChunkId> Backend<Id> for LogBackend {
+    fn get(&self, id: &Id) -> Result<Option<Vec<u8>>> {
+        match self.index.get(&id.to_db_key()?) {
+            Some(location) => Ok(Some(self.read_at(*location)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn size(&self, id: &Id) -> Result<Option<u64>> {
+        Ok(self
+            .index
+            .get(&id.to_db_key()?)
+            .map(|location| location.len))
+    }
+
+    fn put(&mut self, id: &Id, value: &[u8]) -> Result<()> {
+        let key = id.to_db_key()?;
+        let offset = self.append(PUT_RECORD, &key, value)?;
+        let location = Location {
+            offset: offset + HEADER_LEN + key.len() as u64,
+            len: value.len() as u64,
+        };
+        self.live_bytes += location.len;
+        if let Some(replaced) = self.index.insert(key.clone(), location) {
+            self.live_bytes -= replaced.len;
+            self.dead_bytes += HEADER_LEN + key.len() as u64 + replaced.len;
+        }
+        self.compact_if_due();
+        Ok(())
+    }
+
+    fn delete(&mut self, id: &Id) -> Result<()> {
+        let key = id.to_db_key()?;
+        if let Some(location) = self.index.get(&key).copied() {
+            let _ = self.append(DELETE_RECORD, &key, &[])?;
+            let _ = self.index.remove(&key);
+            self.live_bytes -= location.len;
+            self.dead_bytes += 2 * HEADER_LEN + 2 * key.len() as u64 + location.len;
+            self.compact_if_due();
+        }
+        Ok(())
+    }
+
+    fn keys(&self) -> Vec<Id> {
+        self.index
+            .keys()
+            .filter_map(|key| from_db_key(key).ok())
+            .collect()
+    }
+
+    /// The value is copied to a file of its own in the quarantine directory, and deleted from the
+    /// log.
+    fn quarantine(&mut self, id: &Id) -> Result<()> {
+        if let Some(value) = <Self as Backend<Id>>::get(self, id)? {
+            let quarantine_dir = self.dir.join(QUARANTINE_DIR);
+            fs::create_dir_all(&quarantine_dir)?;
+            fs::write(quarantine_dir.join(id.to_db_key()?), value)?;
+        }
+        <Self as Backend<Id>>::delete(self, id)
+    }
+
+    /// Headers, keys, and the dead records not compacted away yet.
+    fn overhead(&self) -> u64 {
+        self.len - self.live_bytes
+    }
+}
+
+fn read_header<R: Read>(reader: &mut R) -> std::io::Result<(u8, String, u64)> {
+    let mut kind = [0; 1];
+    reader.read_exact(&mut kind)?;
+    let mut key_len = [0; 4];
+    reader.read_exact(&mut key_len)?;
+    let mut value_len = [0; 8];
+    reader.read_exact(&mut value_len)?;
+    let key_len = u32::from_le_bytes(key_len);
+    if key_len > MAX_KEY_LEN {
+        return Err(std::io::Error::new(
+            ErrorKind::InvalidData,
+            format!("record key of {} bytes", key_len),
+        ));
+    }
+    let mut key = vec![0; key_len as usize];
+    reader.read_exact(&mut key)?;
+    let key = String::from_utf8(key)
+        .map_err(|error| std::io::Error::new(ErrorKind::InvalidData, error))?;
+    Ok((kind[0], key, u64::from_le_bytes(value_len)))
+}
+
+fn record(kind: u8, key: &str, value: &[u8]) -> Vec<u8> {
+    let key_len: u32 = key.len().try_into().unwrap_or(u32::MAX);
+    let mut record = Vec::with_capacity(HEADER_LEN as usize + key.len() + value.len());
+    record.push(kind);
+    record.extend_from_slice(&key_len.to_le_bytes());
+    record.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    record.extend_from_slice(key.as_bytes());
+    record.extend_from_slice(value);
+    record
+}
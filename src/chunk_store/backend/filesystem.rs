@@ -0,0 +1,224 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::Backend;
+use crate::{chunk_store::chunk::ChunkId, Result};
+use log::info;
+use std::{
+    fs::{self, DirEntry, File},
+    io::{self, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+};
+
+/// The max name length for a chunk file.
+const MAX_CHUNK_FILE_NAME_LENGTH: usize = 104;
+
+/// Number of directory levels chunk files are fanned out over. Each level is named after one byte
+/// of the chunk's name, e.g. `immutable/ab/cd/<chunk file>`.
+const SHARD_DEPTH: usize = 2;
+
+/// Extension of the file a chunk is written to before being renamed into place.
+pub(crate) const TEMP_FILE_EXTENSION: &str = "tmp";
+
+/// Directory within the store which quarantined chunk files are moved to.
+pub(crate) const QUARANTINE_DIR: &str = "quarantine";
+
+/// Holds each chunk as a file of its own, named by the hex-encoded chunk id.
+pub(crate) struct FsBackend {
+    dir: PathBuf,
+}
+
+impl FsBackend {
+    /// Opens the store at `dir`, creating it if needed.
+    ///
+    /// Chunks left in the flat layout of previous versions are moved into their shard directories,
+    /// and temp files left behind by interrupted writes are removed.
+    pub(crate) fn open<Id: ChunkId>(dir: &Path) -> Result<Self> {
+        if fs::read(dir).is_err() {
+            Self::create_new_root(dir)?
+        }
+
+        let migrated = Self::migrate_flat_layout::<Id>(dir)?;
+        if migrated > 0 {
+            info!(
+                "Migrated {} chunks at {} to the sharded layout",
+                migrated,
+                dir.display()
+            );
+        }
+
+        for entry in chunk_files(dir) {
+            let file_path = entry.path();
+            if file_path.extension().and_then(|ext| ext.to_str()) == Some(TEMP_FILE_EXTENSION) {
+                info!("Removing partially written chunk {}", file_path.display());
+                fs::remove_file(&file_path)?;
+            }
+        }
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    /// Returns the path of the chunk file of `id` within the store at `dir`.
+    pub(crate) fn file_path<Id: ChunkId>(dir: &Path, id: &Id) -> Result<PathBuf> {
+        Ok(shard_dir(dir, id).join(id.to_db_key()?))
+    }
+
+    fn create_new_root(root: &Path) -> Result<()> {
+        fs::create_dir_all(root)?;
+
+        // Verify that chunk files can be created.
+        let temp_file_path = root.join("0".repeat(MAX_CHUNK_FILE_NAME_LENGTH));
+        let _ = File::create(&temp_file_path)?;
+        fs::remove_file(temp_file_path)?;
+
+        Ok(())
+    }
+
+    /// Moves chunk files stored directly under `dir` into their shard directories.
+    ///
+    /// Chunk files keep their size, so the on-disk used space record of the store stays valid.
+    fn migrate_flat_layout<Id: ChunkId>(dir: &Path) -> Result<usize> {
+        let mut migrated = 0;
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_file() {
+                continue;
+            }
+            let id: Id = match to_chunk_id(&entry) {
+                Some(id) => id,
+                None => continue,
+            };
+            let shard_dir = shard_dir(dir, &id);
+            fs::create_dir_all(&shard_dir)?;
+            fs::rename(entry.path(), shard_dir.join(entry.file_name()))?;
+            migrated += 1;
+        }
+        Ok(migrated)
+    }
+}
+
+impl<Id: ChunkId> Backend<Id> for FsBackend {
+    fn get(&self, id: &Id) -> Result<Option<Vec<u8>>> {
+        let mut file = match File::open(Self::file_path(&self.dir, id)?) {
+            Ok(file) => file,
+            Err(_) => return Ok(None),
+        };
+        let mut contents = vec![];
+        let _ = file.read_to_end(&mut contents)?;
+        Ok(Some(contents))
+    }
+
+    fn size(&self, id: &Id) -> Result<Option<u64>> {
+        Ok(fs::metadata(Self::file_path(&self.dir, id)?)
+            .ok()
+            .filter(|metadata| metadata.is_file())
+            .map(|metadata| metadata.len()))
+    }
+
+    /// The chunk is written to a temp file which is then renamed into place, so a crash never
+    /// leaves a partially written chunk behind.
+    fn put(&mut self, id: &Id, value: &[u8]) -> Result<()> {
+        let file_path = Self::file_path(&self.dir, id)?;
+        if let Some(shard_dir) = file_path.parent() {
+            fs::create_dir_all(shard_dir)?;
+        }
+        write_atomically(&file_path, value).map_err(From::from)
+    }
+
+    fn delete(&mut self, id: &Id) -> Result<()> {
+        match fs::remove_file(Self::file_path(&self.dir, id)?) {
+            Err(error) if error.kind() != ErrorKind::NotFound => Err(error.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn keys(&self) -> Vec<Id> {
+        chunk_files(&self.dir)
+            .iter()
+            .filter_map(to_chunk_id)
+            .collect()
+    }
+
+    fn quarantine(&mut self, id: &Id) -> Result<()> {
+        let file_path = Self::file_path(&self.dir, id)?;
+        let quarantine_dir = self.dir.join(QUARANTINE_DIR);
+        fs::create_dir_all(&quarantine_dir)?;
+        if let Some(file_name) = file_path.file_name() {
+            fs::rename(&file_path, quarantine_dir.join(file_name))?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the directory under `dir` which holds the chunk file of `id`, keyed on the leading bytes
+/// of the chunk's name.
+fn shard_dir<Id: ChunkId>(dir: &Path, id: &Id) -> PathBuf {
+    id.to_data_address()
+        .name()
+        .0
+        .iter()
+        .take(SHARD_DEPTH)
+        .fold(dir.to_path_buf(), |path, byte| {
+            path.join(format!("{:02x}", byte))
+        })
+}
+
+/// Lists the files in all shard directories of the store at `dir`.
+fn chunk_files(dir: &Path) -> Vec<DirEntry> {
+    let mut files = Vec::new();
+    collect_files(dir, SHARD_DEPTH, &mut files);
+    files
+}
+
+/// Collects the files found `depth` shard directory levels below `dir`.
+fn collect_files(dir: &Path, depth: usize, files: &mut Vec<DirEntry>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let is_dir = entry.file_type().map(|ft| ft.is_dir()).unwrap_or(false);
+        if depth == 0 {
+            if !is_dir {
+                files.push(entry);
+            }
+        } else if is_dir && entry.file_name().len() == 2 {
+            // Shard directories are named by two hex characters.
+            collect_files(&entry.path(), depth - 1, files);
+        }
+    }
+}
+
+/// Writes `contents` to a temp file next to `file_path`, syncs it and renames it into place, then
+/// syncs the directory so the rename itself survives a crash.
+fn write_atomically(file_path: &Path, contents: &[u8]) -> io::Result<()> {
+    let temp_file_path = file_path.with_extension(TEMP_FILE_EXTENSION);
+    let result = File::create(&temp_file_path)
+        .and_then(|mut file| {
+            file.write_all(contents)?;
+            file.sync_all()
+        })
+        .and_then(|()| fs::rename(&temp_file_path, file_path))
+        .and_then(|()| match file_path.parent() {
+            Some(dir) => File::open(dir)?.sync_all(),
+            None => Ok(()),
+        });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_file_path);
+    }
+    result
+}
+
+fn to_chunk_id<Id: ChunkId>(entry: &DirEntry) -> Option<Id> {
+    let file_name = entry.file_name();
+    let file_name = file_name.into_string().ok()?;
+    let bytes = hex::decode(file_name).ok()?;
+    bincode::deserialize(&bytes).ok()
+}
@@ -0,0 +1,59 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::Backend;
+use crate::{chunk_store::chunk::ChunkId, to_db_key::from_db_key, Result};
+use std::{collections::HashMap, fs, path::Path};
+
+/// Holds chunks in memory only. Only built for tests, as nothing survives a restart.
+#[derive(Default)]
+pub(crate) struct MemoryBackend {
+    chunks: HashMap<String, Vec<u8>>,
+}
+
+impl MemoryBackend {
+    /// The `dir` is still created, as the used space record of the `ChunkStore` lives there.
+    pub(crate) fn open(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(Self::default())
+    }
+}
+
+impl<Id: ChunkId> Backend<Id> for MemoryBackend {
+    fn get(&self, id: &Id) -> Result<Option<Vec<u8>>> {
+        Ok(self.chunks.get(&id.to_db_key()?).cloned())
+    }
+
+    fn size(&self, id: &Id) -> Result<Option<u64>> {
+        Ok(self
+            .chunks
+            .get(&id.to_db_key()?)
+            .map(|value| value.len() as u64))
+    }
+
+    fn put(&mut self, id: &Id, value: &[u8]) -> Result<()> {
+        let _ = self.chunks.insert(id.to_db_key()?, value.to_vec());
+        Ok(())
+    }
+
+    fn delete(&mut self, id: &Id) -> Result<()> {
+        let _ = self.chunks.remove(&id.to_db_key()?);
+        Ok(())
+    }
+
+    fn keys(&self) -> Vec<Id> {
+        self.chunks
+            .keys()
+            .filter_map(|key| from_db_key(key).ok())
+            .collect()
+    }
+
+    fn quarantine(&mut self, id: &Id) -> Result<()> {
+        <Self as Backend<Id>>::delete(self, id)
+    }
+}
@@ -0,0 +1,63 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Storage backends holding the serialised chunks of a `ChunkStore`.
+
+mod append_log;
+mod filesystem;
+#[cfg(test)]
+mod memory;
+
+use super::chunk::ChunkId;
+use crate::{Result, StorageBackend};
+pub(super) use append_log::LogBackend;
+pub(super) use filesystem::FsBackend;
+#[cfg(test)]
+pub(super) use filesystem::{QUARANTINE_DIR, TEMP_FILE_EXTENSION};
+#[cfg(test)]
+pub(super) use memory::MemoryBackend;
+use std::path::Path;
+
+/// A key-value store of serialised chunks, keyed by chunk id.
+///
+/// Backends do blocking I/O, so `ChunkStore` only calls them from the blocking thread pool.
+pub(super) trait Backend<Id: ChunkId>: Send + Sync {
+    /// Returns the bytes stored under `id`, or `None` if there are none.
+    fn get(&self, id: &Id) -> Result<Option<Vec<u8>>>;
+
+    /// Returns the number of bytes stored under `id`, or `None` if there are none.
+    fn size(&self, id: &Id) -> Result<Option<u64>>;
+
+    /// Stores `value` under `id`. Any previous value is only replaced once `value` is persisted.
+    fn put(&mut self, id: &Id, value: &[u8]) -> Result<()>;
+
+    /// Removes the value stored under `id`, if any.
+    fn delete(&mut self, id: &Id) -> Result<()>;
+
+    /// Lists the ids of all stored values.
+    fn keys(&self) -> Vec<Id>;
+
+    /// Moves the value stored under `id` out of the store, keeping it around for inspection
+    /// where the backend allows.
+    fn quarantine(&mut self, id: &Id) -> Result<()>;
+
+    /// Returns the number of bytes the backend takes on top of the stored values.
+    fn overhead(&self) -> u64 {
+        0
+    }
+}
+
+/// Opens the backend of the given kind at `dir`, creating it if it doesn't exist yet.
+pub(super) fn open<Id: ChunkId>(kind: StorageBackend, dir: &Path) -> Result<Box<dyn Backend<Id>>> {
+    Ok(match kind {
+        StorageBackend::Filesystem => Box::new(FsBackend::open::<Id>(dir)?),
+        #[cfg(test)]
+        StorageBackend::Memory => Box::new(MemoryBackend::open(dir)?),
+        StorageBackend::Log => Box::new(LogBackend::open(dir)?),
+    })
+}
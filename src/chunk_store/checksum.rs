@@ -0,0 +1,50 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Framing of stored chunks with a checksum of their contents.
+//!
+//! A framed chunk is `MAGIC`, followed by the SHA3-256 hash of the payload, followed by the
+//! payload. Chunks stored before checksums were introduced don't start with `MAGIC`, and are read
+//! back as they are.
+
+use xor_name::XorName;
+
+const MAGIC: [u8; 4] = *b"snck";
+const CHECKSUM_LEN: usize = 32;
+
+/// Number of bytes framing adds to a chunk.
+pub(super) const HEADER_LEN: usize = MAGIC.len() + CHECKSUM_LEN;
+
+/// Frames `payload` with its checksum.
+pub(super) fn seal(payload: &[u8]) -> Vec<u8> {
+    let mut value = Vec::with_capacity(HEADER_LEN + payload.len());
+    value.extend_from_slice(&MAGIC);
+    value.extend_from_slice(&checksum(payload));
+    value.extend_from_slice(payload);
+    value
+}
+
+/// Returns the payload of a stored `value`, or `None` if it doesn't match its checksum.
+pub(super) fn open(value: &[u8]) -> Option<&[u8]> {
+    if !value.starts_with(&MAGIC) {
+        return Some(value);
+    }
+    if value.len() < HEADER_LEN {
+        return None;
+    }
+    let (header, payload) = value.split_at(HEADER_LEN);
+    if header[MAGIC.len()..] == checksum(payload) {
+        Some(payload)
+    } else {
+        None
+    }
+}
+
+fn checksum(payload: &[u8]) -> [u8; CHECKSUM_LEN] {
+    XorName::from_content(&[payload]).0
+}
@@ -10,11 +10,13 @@ use crate::to_db_key::ToDbKey;
 use serde::{de::DeserializeOwned, Serialize};
 use sn_data_types::DataAddress;
 
-pub(crate) trait Chunk: Serialize + DeserializeOwned {
+pub(crate) trait Chunk: Serialize + DeserializeOwned + Send + 'static {
     type Id: ChunkId;
     fn id(&self) -> &Self::Id;
 }
 
-pub(crate) trait ChunkId: ToDbKey + PartialEq + Eq + DeserializeOwned {
+pub(crate) trait ChunkId:
+    ToDbKey + PartialEq + Eq + DeserializeOwned + Clone + Send + Sync + 'static
+{
     fn to_data_address(&self) -> DataAddress;
 }
@@ -0,0 +1,43 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Compression of stored chunks.
+//!
+//! A compressed chunk is `MAGIC`, followed by the deflate stream of the serialised chunk. Chunks
+//! stored uncompressed don't start with `MAGIC`, so both can be held by the same store.
+
+use crate::Result;
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use std::{
+    borrow::Cow,
+    io::{Read, Write},
+};
+
+const MAGIC: [u8; 4] = *b"sncz";
+
+/// Compresses a serialised chunk, returning `None` if that wouldn't make it any smaller.
+pub(super) fn compress(serialised: &[u8]) -> Result<Option<Vec<u8>>> {
+    let mut encoder = DeflateEncoder::new(MAGIC.to_vec(), Compression::default());
+    encoder.write_all(serialised)?;
+    let compressed = encoder.finish()?;
+    if compressed.len() < serialised.len() {
+        Ok(Some(compressed))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Returns the serialised chunk held in a stored payload, decompressing it if needed.
+pub(super) fn decompress(payload: &[u8]) -> Result<Cow<'_, [u8]>> {
+    if !payload.starts_with(&MAGIC) {
+        return Ok(Cow::Borrowed(payload));
+    }
+    let mut serialised = vec![];
+    let _ = DeflateDecoder::new(&payload[MAGIC.len()..]).read_to_end(&mut serialised)?;
+    Ok(Cow::Owned(serialised))
+}
@@ -6,9 +6,12 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
-//! A simple, persistent, disk-based key-value store.
+//! A simple, persistent key-value store of chunks, over a pluggable storage backend.
 
+mod backend;
+mod checksum;
 mod chunk;
+mod compression;
 mod immutable;
 mod mutable;
 mod register;
@@ -18,36 +21,59 @@ mod tests;
 mod used_space;
 
 use crate::error::{Error, Result};
-use crate::utils;
+use crate::{
+    encryption::{self, EncryptionKey},
+    utils, StorageBackend,
+};
+use backend::Backend;
 use chunk::{Chunk, ChunkId};
-use log::{info, trace};
+use log::{info, trace, warn};
 use sn_data_types::{register::Register, Blob, Map, Sequence};
 use std::{
-    fs::{self, DirEntry, File, Metadata},
-    io::{Read, Write},
     marker::PhantomData,
-    path::{Path, PathBuf},
+    mem,
+    path::Path,
+    sync::{Arc, RwLock},
 };
+use tokio::task;
 use used_space::StoreId;
 pub use used_space::UsedSpace;
 
 const CHUNK_STORE_DIR: &str = "chunks";
 
-/// The max name length for a chunk file.
-const MAX_CHUNK_FILE_NAME_LENGTH: usize = 104;
-
 pub(crate) type BlobChunkStore = ChunkStore<Blob>;
 pub(crate) type MapChunkStore = ChunkStore<Map>;
 pub(crate) type SequenceChunkStore = ChunkStore<Sequence>;
 pub(crate) type RegisterChunkStore = ChunkStore<Register>;
 
-/// `ChunkStore` is a store of data held as serialised files on disk, implementing a maximum disk
-/// usage to restrict storage.
+/// How a `ChunkStore` holds its chunks.
+#[derive(Clone, Debug, Default)]
+pub struct StoreOptions {
+    /// Where the chunks are held.
+    pub backend: StorageBackend,
+    /// Whether chunks are compressed before being stored.
+    pub compress: bool,
+    /// Key chunks are encrypted with before being stored, if any.
+    pub encryption_key: Option<EncryptionKey>,
+}
+
+/// `ChunkStore` is a store of data held as serialised chunks in a storage backend, implementing a
+/// maximum disk usage to restrict storage.
+///
+/// All backend I/O, as well as the encoding and decoding of chunks, runs on the blocking thread
+/// pool, so it doesn't stall the runtime. Reads only take `&self`, and run concurrently.
 pub(crate) struct ChunkStore<T: Chunk> {
-    dir: PathBuf,
+    // Shared with the blocking tasks doing the I/O.
+    backend: Arc<RwLock<Box<dyn Backend<T::Id>>>>,
     // Maximum space allowed for all `ChunkStore`s to consume.
     used_space: UsedSpace,
     id: StoreId,
+    compress: bool,
+    encryption_key: Option<EncryptionKey>,
+    // Chunks found corrupt on startup, not yet handed out for restoring.
+    quarantined: Vec<T::Id>,
+    // Bytes the backend takes on top of the chunks, as last counted towards the used space.
+    overhead: u64,
     _phantom: PhantomData<T>,
 }
 
@@ -56,41 +82,81 @@ where
     T: Chunk,
     Self: Subdir,
 {
-    /// Creates a new `ChunkStore` at location `root/CHUNK_STORE_DIR/<chunk type>`.
+    /// Creates a new `ChunkStore` at location `root/CHUNK_STORE_DIR/<chunk type>`, held as set out
+    /// by `options`.
     ///
     /// If the location specified already exists, the previous ChunkStore there is opened, otherwise
     /// the required folder structure is created.
     ///
     /// The maximum storage space is defined by `max_capacity`.  This specifies the max usable by
     /// _all_ `ChunkStores`, not per `ChunkStore`.
-    pub async fn new<P: AsRef<Path>>(root: P, max_capacity: u64) -> Result<Self> {
+    ///
+    /// Chunks which fail their checksum or fail to deserialise are quarantined, and the used space
+    /// record is rebuilt from the chunks actually stored, and the overhead of the backend. Chunks encrypted with another key than
+    /// the one in `options` are not touched, and `Error::InvalidEncryptionKey` is returned.
+    pub async fn new<P: AsRef<Path>>(
+        root: P,
+        max_capacity: u64,
+        options: &StoreOptions,
+    ) -> Result<Self> {
         let dir = root.as_ref().join(CHUNK_STORE_DIR).join(Self::subdir());
-
-        if fs::read(&dir).is_err() {
-            Self::create_new_root(&dir)?
-        }
+        let kind = options.backend;
+        let backend_dir = dir.clone();
+        let backend = run_blocking(move || backend::open(kind, &backend_dir)).await?;
 
         let used_space = UsedSpace::new(max_capacity);
         let id = used_space.add_local_store(&dir).await?;
+        let encryption_key = options.encryption_key.clone();
+        let (backend, stored, quarantined) = run_blocking(move || {
+            let mut backend = backend;
+            let (stored, quarantined) = Self::reconcile(backend.as_mut(), encryption_key.as_ref())?;
+            Ok((backend, stored, quarantined))
+        })
+        .await?;
+        let overhead = backend.overhead();
+        used_space.set_local(id, stored + overhead).await?;
         Ok(ChunkStore {
-            dir,
+            backend: Arc::new(RwLock::new(backend)),
             used_space,
             id,
+            compress: options.compress,
+            encryption_key: options.encryption_key.clone(),
+            quarantined,
+            overhead,
             _phantom: PhantomData,
         })
     }
 }
 
 impl<T: Chunk> ChunkStore<T> {
-    fn create_new_root(root: &Path) -> Result<()> {
-        fs::create_dir_all(root)?;
-
-        // Verify that chunk files can be created.
-        let temp_file_path = root.join("0".repeat(MAX_CHUNK_FILE_NAME_LENGTH));
-        let _ = File::create(&temp_file_path)?;
-        fs::remove_file(temp_file_path)?;
-
-        Ok(())
+    /// Quarantines chunks which fail their checksum or fail to deserialise.
+    ///
+    /// Returns the space used by the remaining chunks, and the ids of the quarantined ones.
+    fn reconcile(
+        backend: &mut dyn Backend<T::Id>,
+        encryption_key: Option<&EncryptionKey>,
+    ) -> Result<(u64, Vec<T::Id>)> {
+        let mut used_space = 0;
+        let mut quarantined = vec![];
+        for id in backend.keys() {
+            match backend
+                .get(&id)
+                .and_then(|value| to_chunk::<T>(value, &id, encryption_key))
+            {
+                Ok(_) => used_space += backend.size(&id)?.unwrap_or(0),
+                Err(Error::InvalidEncryptionKey) => return Err(Error::InvalidEncryptionKey),
+                Err(error) => {
+                    warn!(
+                        "Quarantining unreadable chunk {:?}: {:?}",
+                        id.to_data_address(),
+                        error
+                    );
+                    backend.quarantine(&id)?;
+                    quarantined.push(id);
+                }
+            }
+        }
+        Ok((used_space, quarantined))
     }
 
     /// Stores a new data chunk.
@@ -99,39 +165,58 @@ impl<T: Chunk> ChunkStore<T> {
     /// an IO error, it returns `Error::Io`.
     ///
     /// If a chunk with the same id already exists, it will be overwritten.
+    ///
+    /// The space used is that of the chunk as stored, i.e. after any compression and encryption,
+    /// plus what the backend takes on top of it, e.g. in a record header.
     pub async fn put(&mut self, chunk: &T) -> Result<()> {
         info!("Writing chunk");
-        let serialised_chunk = utils::serialise(chunk)?;
-        let consumed_space = serialised_chunk.len() as u64;
+        let id = chunk.id().clone();
+        let serialised = utils::serialise(chunk)?;
+        let compress = self.compress;
+        let encryption_key = self.encryption_key.clone();
+        let value =
+            run_blocking(move || to_value(&serialised, compress, encryption_key.as_ref())).await?;
+        let consumed_space = value.len() as u64;
 
         info!("consumed space: {:?}", consumed_space);
         info!("max : {:?}", self.used_space.max_capacity().await);
         info!("use space total : {:?}", self.used_space.total().await);
 
-        let file_path = self.file_path(chunk.id())?;
-        self.do_delete(&file_path).await?;
+        // An existing chunk is only replaced once the new one is fully written.
+        let replaced_space = self.size(&id).await?.unwrap_or(0);
 
         // pre-reserve space
-        self.used_space.increase(self.id, consumed_space).await?;
+        if consumed_space > replaced_space {
+            self.used_space
+                .increase(self.id, consumed_space - replaced_space)
+                .await?;
+        }
         trace!(
             "use space total after add: {:?}",
             self.used_space.total().await
         );
 
-        let res = File::create(&file_path).and_then(|mut file| {
-            file.write_all(&serialised_chunk)?;
-            file.sync_all()
-        });
-
-        match res {
-            Ok(_) => {
+        match self
+            .write_backend(move |backend| backend.put(&id, &value))
+            .await
+        {
+            Ok(()) => {
                 info!("Writing chunk succeeded!");
-                Ok(())
+                if replaced_space > consumed_space {
+                    self.used_space
+                        .decrease(self.id, replaced_space - consumed_space)
+                        .await?;
+                }
+                self.update_overhead().await
             }
             Err(e) => {
                 info!("Writing chunk failed!");
-                self.used_space.decrease(self.id, consumed_space).await?;
-                Err(e.into())
+                if consumed_space > replaced_space {
+                    self.used_space
+                        .decrease(self.id, consumed_space - replaced_space)
+                        .await?;
+                }
+                Err(e)
             }
         }
     }
@@ -141,7 +226,33 @@ impl<T: Chunk> ChunkStore<T> {
     /// If the data doesn't exist, it does nothing and returns `Ok`.  In the case of an IO error, it
     /// returns `Error::Io`.
     pub async fn delete(&mut self, id: &T::Id) -> Result<()> {
-        self.do_delete(&self.file_path(id)?).await
+        if let Some(size) = self.size(id).await? {
+            self.used_space.decrease(self.id, size).await?;
+            let id = id.clone();
+            self.write_backend(move |backend| backend.delete(&id))
+                .await?;
+            self.update_overhead().await
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Moves the data chunk stored under `id` out of the store, e.g. after it was found corrupt.
+    pub async fn quarantine(&mut self, id: &T::Id) -> Result<()> {
+        if let Some(size) = self.size(id).await? {
+            self.used_space.decrease(self.id, size).await?;
+            let id = id.clone();
+            self.write_backend(move |backend| backend.quarantine(&id))
+                .await?;
+            self.update_overhead().await
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns the ids of the chunks quarantined on startup, once.
+    pub fn take_quarantined(&mut self) -> Vec<T::Id> {
+        mem::take(&mut self.quarantined)
     }
 
     /// Used space to max space ratio.
@@ -157,60 +268,87 @@ impl<T: Chunk> ChunkStore<T> {
 
     /// Returns a data chunk previously stored under `id`.
     ///
-    /// If the data file can't be accessed, it returns `Error::NoSuchChunk`.
-    pub fn get(&self, id: &T::Id) -> Result<T> {
-        let mut file = File::open(self.file_path(id)?)
-            .map_err(|_| Error::NoSuchChunk(id.to_data_address()))?;
-        let mut contents = vec![];
-        let _ = file.read_to_end(&mut contents)?;
-        let chunk = bincode::deserialize::<T>(&contents)?;
-        // Check it's the requested chunk variant.
-        if chunk.id() == id {
-            Ok(chunk)
-        } else {
-            Err(Error::NoSuchChunk(id.to_data_address()))
-        }
+    /// If the data can't be accessed, it returns `Error::NoSuchChunk`.  If it doesn't match its
+    /// checksum, it returns `Error::ChunkCorrupted`.  If it can't be decrypted, it returns
+    /// `Error::InvalidEncryptionKey`.
+    pub async fn get(&self, id: &T::Id) -> Result<T> {
+        let id = id.clone();
+        let encryption_key = self.encryption_key.clone();
+        self.read_backend(move |backend| to_chunk(backend.get(&id)?, &id, encryption_key.as_ref()))
+            .await
     }
 
     pub async fn total_used_space(&self) -> u64 {
         self.used_space.total().await
     }
 
+    /// Bytes the store may use at most.
+    pub async fn max_capacity(&self) -> u64 {
+        self.used_space.max_capacity().await
+    }
+
     /// Tests if a data chunk has been previously stored under `id`.
-    pub fn has(&self, id: &T::Id) -> bool {
-        if let Ok(path) = self.file_path(id) {
-            fs::metadata(path)
-                .as_ref()
-                .map(Metadata::is_file)
-                .unwrap_or(false)
-        } else {
-            false
-        }
+    pub async fn has(&self, id: &T::Id) -> bool {
+        matches!(self.size(id).await, Ok(Some(_)))
     }
 
     /// Lists all keys of currently stored data.
     #[cfg_attr(not(test), allow(unused))]
-    pub fn keys(&self) -> Vec<T::Id> {
-        fs::read_dir(&self.dir)
-            .map(|entries| {
-                entries
-                    .filter_map(|entry| to_chunk_id(&entry.ok()?))
-                    .collect()
-            })
-            .unwrap_or_else(|_| Vec::new())
-    }
-
-    async fn do_delete(&mut self, file_path: &Path) -> Result<()> {
-        if let Ok(metadata) = fs::metadata(file_path) {
-            self.used_space.decrease(self.id, metadata.len()).await?;
-            fs::remove_file(file_path).map_err(From::from)
-        } else {
-            Ok(())
+    pub async fn keys(&self) -> Vec<T::Id> {
+        match self.read_backend(|backend| Ok(backend.keys())).await {
+            Ok(keys) => keys,
+            Err(error) => {
+                warn!("Could not list stored chunks: {:?}", error);
+                vec![]
+            }
         }
     }
 
-    fn file_path(&self, id: &T::Id) -> Result<PathBuf> {
-        Ok(self.dir.join(&hex::encode(utils::serialise(id)?)))
+    /// Counts the change in the bytes the backend takes on top of the chunks, e.g. in record
+    /// headers, towards the used space.
+    async fn update_overhead(&mut self) -> Result<()> {
+        let overhead = self.read_backend(|backend| Ok(backend.overhead())).await?;
+        if overhead != self.overhead {
+            let local = self.used_space.local(self.id).await;
+            self.used_space
+                .set_local(self.id, (local + overhead).saturating_sub(self.overhead))
+                .await?;
+            self.overhead = overhead;
+        }
+        Ok(())
+    }
+
+    async fn size(&self, id: &T::Id) -> Result<Option<u64>> {
+        let id = id.clone();
+        self.read_backend(move |backend| backend.size(&id)).await
+    }
+
+    /// Runs `f` on the backend from the blocking thread pool, alongside any other reads.
+    async fn read_backend<F, R>(&self, f: F) -> Result<R>
+    where
+        F: FnOnce(&dyn Backend<T::Id>) -> Result<R> + Send + 'static,
+        R: Send + 'static,
+    {
+        let backend = self.backend.clone();
+        run_blocking(move || {
+            let backend = backend.read().map_err(|_| lock_poisoned())?;
+            f(backend.as_ref())
+        })
+        .await
+    }
+
+    /// Runs `f` on the backend from the blocking thread pool, with exclusive access to it.
+    async fn write_backend<F, R>(&self, f: F) -> Result<R>
+    where
+        F: FnOnce(&mut dyn Backend<T::Id>) -> Result<R> + Send + 'static,
+        R: Send + 'static,
+    {
+        let backend = self.backend.clone();
+        run_blocking(move || {
+            let mut backend = backend.write().map_err(|_| lock_poisoned())?;
+            f(backend.as_mut())
+        })
+        .await
     }
 }
 
@@ -242,9 +380,54 @@ impl Subdir for RegisterChunkStore {
     }
 }
 
-fn to_chunk_id<T: ChunkId>(entry: &DirEntry) -> Option<T> {
-    let file_name = entry.file_name();
-    let file_name = file_name.into_string().ok()?;
-    let bytes = hex::decode(file_name).ok()?;
-    bincode::deserialize(&bytes).ok()
+/// Runs `f` on the blocking thread pool.
+async fn run_blocking<F, R>(f: F) -> Result<R>
+where
+    F: FnOnce() -> Result<R> + Send + 'static,
+    R: Send + 'static,
+{
+    task::spawn_blocking(f)
+        .await
+        .map_err(|error| Error::Logic(format!("ChunkStore task failed: {}", error)))?
+}
+
+fn lock_poisoned() -> Error {
+    Error::Logic("ChunkStore backend lock poisoned".to_string())
+}
+
+/// Encodes a serialised chunk into the bytes to store for it, compressing and encrypting it as set
+/// out.
+fn to_value(
+    serialised: &[u8],
+    compress: bool,
+    encryption_key: Option<&EncryptionKey>,
+) -> Result<Vec<u8>> {
+    let compressed = if compress {
+        compression::compress(serialised)?
+    } else {
+        None
+    };
+    let encrypted =
+        encryption::encrypt(encryption_key, compressed.as_deref().unwrap_or(serialised))?;
+    Ok(checksum::seal(&encrypted))
+}
+
+/// Deserialises the chunk `id` from the bytes stored for it, checking that it is intact and the
+/// requested one.
+fn to_chunk<T: Chunk>(
+    value: Option<Vec<u8>>,
+    id: &T::Id,
+    encryption_key: Option<&EncryptionKey>,
+) -> Result<T> {
+    let value = value.ok_or_else(|| Error::NoSuchChunk(id.to_data_address()))?;
+    let payload =
+        checksum::open(&value).ok_or_else(|| Error::ChunkCorrupted(id.to_data_address()))?;
+    let decrypted = encryption::decrypt(encryption_key, payload)?;
+    let chunk = bincode::deserialize::<T>(&compression::decompress(&decrypted)?)?;
+    // Check it's the requested chunk variant.
+    if chunk.id() == id {
+        Ok(chunk)
+    } else {
+        Err(Error::NoSuchChunk(id.to_data_address()))
+    }
 }
@@ -7,16 +7,33 @@
 // permissions and limitations relating to use of the SAFE Network Software.
 
 use super::{
+    backend::{self, Backend, FsBackend, LogBackend, QUARANTINE_DIR, TEMP_FILE_EXTENSION},
+    checksum,
     chunk::{Chunk, ChunkId},
-    ChunkStore, Result as ChunkStoreResult, Subdir,
+    ChunkStore, Result as ChunkStoreResult, StoreOptions, Subdir, CHUNK_STORE_DIR,
 };
-use crate::{to_db_key::ToDbKey, Error, Result};
+use crate::{encryption::EncryptionKey, to_db_key::ToDbKey, utils, Error, Result, StorageBackend};
+use futures::future::join_all;
 use rand::{distributions::Standard, rngs::ThreadRng, Rng};
 use serde::{Deserialize, Serialize};
 use sn_data_types::{BlobAddress, DataAddress};
 use sn_routing::XorName;
-use std::{path::Path, u64};
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+    u64,
+};
 use tempdir::TempDir;
+use tokio::{
+    task::{yield_now, JoinHandle},
+    time::sleep,
+};
 
 #[derive(Debug, Deserialize, Eq, PartialEq, Serialize)]
 struct Data {
@@ -59,6 +76,19 @@ fn temp_dir() -> ChunkStoreResult<TempDir> {
     TempDir::new("test").map_err(|e| Error::TempDirCreationFailed(e.to_string()))
 }
 
+fn options(backend: StorageBackend) -> StoreOptions {
+    StoreOptions {
+        backend,
+        compress: false,
+        encryption_key: None,
+    }
+}
+
+fn store_dir(root: &Path) -> PathBuf {
+    root.join(CHUNK_STORE_DIR)
+        .join(ChunkStore::<Data>::subdir())
+}
+
 struct Chunks {
     data_and_sizes: Vec<(Vec<u8>, u64)>,
     total_size: u64,
@@ -66,7 +96,7 @@ struct Chunks {
 
 impl Chunks {
     // Construct random amount of randomly-sized chunks, keeping track of the total size of all
-    // chunks when serialised.
+    // chunks when stored.
     fn gen<R: Rng>(rng: &mut R) -> Result<Self> {
         let mut chunks = Self {
             data_and_sizes: vec![],
@@ -79,7 +109,8 @@ impl Chunks {
                 id: Id(0),
                 value: rng.sample_iter(&Standard).take(size as usize).collect(),
             };
-            let serialised_size = bincode::serialized_size(&data).map_err(Error::Bincode)?;
+            let serialised_size = bincode::serialized_size(&data).map_err(Error::Bincode)?
+                + checksum::HEADER_LEN as u64;
 
             chunks.total_size += serialised_size;
             chunks.data_and_sizes.push((data.value, serialised_size));
@@ -94,7 +125,9 @@ async fn successful_put() -> Result<()> {
     let chunks = Chunks::gen(&mut rng)?;
 
     let root = temp_dir()?;
-    let mut chunk_store = ChunkStore::<Data>::new(root.path(), u64::MAX).await?;
+    let mut chunk_store =
+        ChunkStore::<Data>::new(root.path(), u64::MAX, &options(StorageBackend::Filesystem))
+            .await?;
 
     for (index, (data, size)) in chunks.data_and_sizes.iter().enumerate().rev() {
         let the_data = &Data {
@@ -102,17 +135,17 @@ async fn successful_put() -> Result<()> {
             value: data.clone(),
         };
         let used_space_before = chunk_store.total_used_space().await;
-        assert!(!chunk_store.has(&the_data.id));
+        assert!(!chunk_store.has(&the_data.id).await);
         chunk_store.put(the_data).await?;
         let used_space_after = chunk_store.total_used_space().await;
         assert_eq!(used_space_after, used_space_before + size);
-        assert!(chunk_store.has(&the_data.id));
+        assert!(chunk_store.has(&the_data.id).await);
         assert!(used_space_after <= chunks.total_size);
     }
 
     assert_eq!(chunk_store.total_used_space().await, chunks.total_size);
 
-    let mut keys = chunk_store.keys();
+    let mut keys = chunk_store.keys().await;
     keys.sort();
     assert_eq!(
         (0..chunks.data_and_sizes.len())
@@ -129,7 +162,8 @@ async fn failed_put_when_not_enough_space() -> Result<()> {
     let mut rng = new_rng();
     let root = temp_dir()?;
     let capacity = 32;
-    let mut chunk_store = ChunkStore::new(root.path(), capacity).await?;
+    let mut chunk_store =
+        ChunkStore::new(root.path(), capacity, &options(StorageBackend::Filesystem)).await?;
 
     let data = Data {
         id: Id(rng.gen()),
@@ -153,7 +187,8 @@ async fn delete() -> Result<()> {
     let chunks = Chunks::gen(&mut rng)?;
 
     let root = temp_dir()?;
-    let mut chunk_store = ChunkStore::new(root.path(), u64::MAX).await?;
+    let mut chunk_store =
+        ChunkStore::new(root.path(), u64::MAX, &options(StorageBackend::Filesystem)).await?;
 
     for (index, (data, size)) in chunks.data_and_sizes.iter().enumerate() {
         let the_data = &Data {
@@ -162,9 +197,9 @@ async fn delete() -> Result<()> {
         };
         chunk_store.put(the_data).await?;
         assert_eq!(chunk_store.total_used_space().await, *size);
-        assert!(chunk_store.has(&the_data.id));
+        assert!(chunk_store.has(&the_data.id).await);
         chunk_store.delete(&the_data.id).await?;
-        assert!(!chunk_store.has(&the_data.id));
+        assert!(!chunk_store.has(&the_data.id).await);
         assert_eq!(chunk_store.total_used_space().await, 0);
     }
 
@@ -177,7 +212,8 @@ async fn put_and_get_value_should_be_same() -> Result<()> {
     let chunks = Chunks::gen(&mut rng)?;
 
     let root = temp_dir()?;
-    let mut chunk_store = ChunkStore::new(root.path(), u64::MAX).await?;
+    let mut chunk_store =
+        ChunkStore::new(root.path(), u64::MAX, &options(StorageBackend::Filesystem)).await?;
 
     for (index, (data, _)) in chunks.data_and_sizes.iter().enumerate() {
         chunk_store
@@ -189,7 +225,7 @@ async fn put_and_get_value_should_be_same() -> Result<()> {
     }
 
     for (index, (data, _)) in chunks.data_and_sizes.iter().enumerate() {
-        let retrieved_value = chunk_store.get(&Id(index as u64))?;
+        let retrieved_value = chunk_store.get(&Id(index as u64)).await?;
         assert_eq!(*data, retrieved_value.value);
     }
 
@@ -202,7 +238,8 @@ async fn overwrite_value() -> Result<()> {
     let chunks = Chunks::gen(&mut rng)?;
 
     let root = temp_dir()?;
-    let mut chunk_store = ChunkStore::new(root.path(), u64::MAX).await?;
+    let mut chunk_store =
+        ChunkStore::new(root.path(), u64::MAX, &options(StorageBackend::Filesystem)).await?;
 
     for (data, size) in chunks.data_and_sizes {
         chunk_store
@@ -212,7 +249,7 @@ async fn overwrite_value() -> Result<()> {
             })
             .await?;
         assert_eq!(chunk_store.total_used_space().await, size);
-        let retrieved_data = chunk_store.get(&Id(0))?;
+        let retrieved_data = chunk_store.get(&Id(0)).await?;
         assert_eq!(data, retrieved_data.value);
     }
 
@@ -222,10 +259,11 @@ async fn overwrite_value() -> Result<()> {
 #[tokio::test]
 async fn get_fails_when_key_does_not_exist() -> Result<()> {
     let root = temp_dir()?;
-    let chunk_store: ChunkStore<Data> = ChunkStore::new(root.path(), u64::MAX).await?;
+    let chunk_store: ChunkStore<Data> =
+        ChunkStore::new(root.path(), u64::MAX, &options(StorageBackend::Filesystem)).await?;
 
     let id = Id(new_rng().gen());
-    match chunk_store.get(&id) {
+    match chunk_store.get(&id).await {
         Err(Error::NoSuchChunk(_)) => (),
         x => return Err(crate::Error::Logic(format!("Unexpected {:?}", x))),
     }
@@ -239,11 +277,12 @@ async fn keys() -> Result<()> {
     let chunks = Chunks::gen(&mut rng)?;
 
     let root = temp_dir()?;
-    let mut chunk_store = ChunkStore::new(root.path(), u64::MAX).await?;
+    let mut chunk_store =
+        ChunkStore::new(root.path(), u64::MAX, &options(StorageBackend::Filesystem)).await?;
 
     for (index, (data, _)) in chunks.data_and_sizes.iter().enumerate() {
         let id = Id(index as u64);
-        assert!(!chunk_store.keys().contains(&id));
+        assert!(!chunk_store.keys().await.contains(&id));
         chunk_store
             .put(&Data {
                 id,
@@ -251,7 +290,7 @@ async fn keys() -> Result<()> {
             })
             .await?;
 
-        let keys = chunk_store.keys();
+        let keys = chunk_store.keys().await;
         assert!(keys.contains(&id));
         assert_eq!(keys.len(), index + 1);
     }
@@ -259,13 +298,567 @@ async fn keys() -> Result<()> {
     for (index, _) in chunks.data_and_sizes.iter().enumerate() {
         let id = Id(index as u64);
 
-        assert!(chunk_store.keys().contains(&id));
+        assert!(chunk_store.keys().await.contains(&id));
         chunk_store.delete(&id).await?;
 
-        let keys = chunk_store.keys();
+        let keys = chunk_store.keys().await;
         assert!(!keys.contains(&id));
         assert_eq!(keys.len(), chunks.data_and_sizes.len() - index - 1);
     }
 
     Ok(())
 }
+
+#[tokio::test]
+async fn migrates_flat_layout() -> Result<()> {
+    let mut rng = new_rng();
+    let chunks = Chunks::gen(&mut rng)?;
+
+    let root = temp_dir()?;
+    let mut chunk_store =
+        ChunkStore::new(root.path(), u64::MAX, &options(StorageBackend::Filesystem)).await?;
+
+    for (index, (data, _)) in chunks.data_and_sizes.iter().enumerate() {
+        chunk_store
+            .put(&Data {
+                id: Id(index as u64),
+                value: data.clone(),
+            })
+            .await?;
+    }
+    let used_space = chunk_store.used_space.local(chunk_store.id).await;
+
+    // Move every chunk file back to where the flat layout kept it.
+    let dir = store_dir(root.path());
+    for index in 0..chunks.data_and_sizes.len() {
+        let file_path = FsBackend::file_path(&dir, &Id(index as u64))?;
+        let file_name = file_path
+            .file_name()
+            .ok_or_else(|| Error::Logic("Chunk file has no name".to_string()))?;
+        fs::rename(&file_path, dir.join(file_name))?;
+    }
+    assert!(chunk_store.keys().await.is_empty());
+    drop(chunk_store);
+
+    let chunk_store: ChunkStore<Data> =
+        ChunkStore::new(root.path(), u64::MAX, &options(StorageBackend::Filesystem)).await?;
+    assert_eq!(
+        chunk_store.used_space.local(chunk_store.id).await,
+        used_space
+    );
+    assert_eq!(chunk_store.keys().await.len(), chunks.data_and_sizes.len());
+    for (index, (data, _)) in chunks.data_and_sizes.iter().enumerate() {
+        let retrieved_value = chunk_store.get(&Id(index as u64)).await?;
+        assert_eq!(*data, retrieved_value.value);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn reconciles_on_startup() -> Result<()> {
+    let root = temp_dir()?;
+    let mut chunk_store =
+        ChunkStore::new(root.path(), u64::MAX, &options(StorageBackend::Filesystem)).await?;
+
+    let intact = Data {
+        id: Id(0),
+        value: vec![1; 32],
+    };
+    let corrupted = Data {
+        id: Id(1),
+        value: vec![2; 32],
+    };
+    chunk_store.put(&intact).await?;
+    chunk_store.put(&corrupted).await?;
+    let intact_size =
+        bincode::serialized_size(&intact).map_err(Error::Bincode)? + checksum::HEADER_LEN as u64;
+
+    // Simulate a crash mid-write, and a chunk file that was left truncated.
+    let dir = store_dir(root.path());
+    let temp_file_path = FsBackend::file_path(&dir, &Id(2))?.with_extension(TEMP_FILE_EXTENSION);
+    fs::create_dir_all(
+        temp_file_path
+            .parent()
+            .ok_or_else(|| Error::Logic("Temp file has no parent directory".to_string()))?,
+    )?;
+    fs::write(&temp_file_path, [3; 8])?;
+    let corrupted_path = FsBackend::file_path(&dir, &corrupted.id)?;
+    fs::write(&corrupted_path, [2; 4])?;
+    drop(chunk_store);
+
+    let chunk_store: ChunkStore<Data> =
+        ChunkStore::new(root.path(), u64::MAX, &options(StorageBackend::Filesystem)).await?;
+    assert!(!temp_file_path.exists());
+    assert!(!corrupted_path.exists());
+    assert!(dir
+        .join(QUARANTINE_DIR)
+        .join(corrupted_path.file_name().unwrap_or_default())
+        .exists());
+    assert_eq!(chunk_store.keys().await, vec![intact.id]);
+    assert_eq!(chunk_store.get(&intact.id).await?, intact);
+    assert_eq!(chunk_store.total_used_space().await, intact_size);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn backends_hold_the_same_chunks() -> Result<()> {
+    let mut rng = new_rng();
+    let chunks = Chunks::gen(&mut rng)?;
+
+    for backend in &[
+        StorageBackend::Filesystem,
+        StorageBackend::Memory,
+        StorageBackend::Log,
+    ] {
+        let root = temp_dir()?;
+        let mut chunk_store = ChunkStore::new(root.path(), u64::MAX, &options(*backend)).await?;
+
+        for (index, (data, _)) in chunks.data_and_sizes.iter().enumerate() {
+            chunk_store
+                .put(&Data {
+                    id: Id(index as u64),
+                    value: data.clone(),
+                })
+                .await?;
+        }
+        // Overwrite and delete some, so the log holds dead records.
+        for (index, (data, _)) in chunks.data_and_sizes.iter().enumerate().step_by(2) {
+            chunk_store
+                .put(&Data {
+                    id: Id(index as u64),
+                    value: data.clone(),
+                })
+                .await?;
+        }
+        for index in (0..chunks.data_and_sizes.len()).step_by(3) {
+            chunk_store.delete(&Id(index as u64)).await?;
+        }
+
+        let expected: u64 = chunks
+            .data_and_sizes
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| index % 3 != 0)
+            .map(|(_, (_, size))| size)
+            .sum();
+        // The log takes the space of its headers and dead records on top.
+        let log_len = || fs::metadata(store_dir(root.path()).join("chunks.log")).map(|m| m.len());
+        let expected_used = |backend| match backend {
+            StorageBackend::Log => log_len(),
+            _ => Ok(expected),
+        };
+        assert_eq!(
+            chunk_store.total_used_space().await,
+            expected_used(*backend)?
+        );
+        for (index, (data, _)) in chunks.data_and_sizes.iter().enumerate() {
+            let id = Id(index as u64);
+            if index % 3 == 0 {
+                assert!(!chunk_store.has(&id).await);
+            } else {
+                assert_eq!(*data, chunk_store.get(&id).await?.value);
+            }
+        }
+        drop(chunk_store);
+
+        if *backend == StorageBackend::Memory {
+            continue;
+        }
+        let chunk_store: ChunkStore<Data> =
+            ChunkStore::new(root.path(), u64::MAX, &options(*backend)).await?;
+        assert_eq!(
+            chunk_store.total_used_space().await,
+            expected_used(*backend)?
+        );
+        let mut keys = chunk_store.keys().await;
+        keys.sort();
+        assert_eq!(
+            keys,
+            (0..chunks.data_and_sizes.len())
+                .filter(|index| index % 3 != 0)
+                .map(|index| Id(index as u64))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn log_backend_cuts_off_incomplete_record() -> Result<()> {
+    let root = temp_dir()?;
+    let mut chunk_store =
+        ChunkStore::new(root.path(), u64::MAX, &options(StorageBackend::Log)).await?;
+
+    let data = Data {
+        id: Id(0),
+        value: vec![1; 32],
+    };
+    chunk_store.put(&data).await?;
+    drop(chunk_store);
+
+    // Simulate a crash halfway through appending a record.
+    let mut log = OpenOptions::new()
+        .append(true)
+        .open(store_dir(root.path()).join("chunks.log"))?;
+    log.write_all(&[0, 16, 0, 0, 0])?;
+    drop(log);
+
+    let mut chunk_store: ChunkStore<Data> =
+        ChunkStore::new(root.path(), u64::MAX, &options(StorageBackend::Log)).await?;
+    assert_eq!(chunk_store.keys().await, vec![data.id]);
+    assert_eq!(chunk_store.get(&data.id).await?, data);
+
+    let other = Data {
+        id: Id(1),
+        value: vec![2; 32],
+    };
+    chunk_store.put(&other).await?;
+    drop(chunk_store);
+
+    let chunk_store: ChunkStore<Data> =
+        ChunkStore::new(root.path(), u64::MAX, &options(StorageBackend::Log)).await?;
+    assert_eq!(chunk_store.get(&data.id).await?, data);
+    assert_eq!(chunk_store.get(&other.id).await?, other);
+
+    Ok(())
+}
+
+#[test]
+fn log_backend_rejects_corrupt_key_length() -> Result<()> {
+    let root = temp_dir()?;
+    let dir = root.path().join("log");
+    fs::create_dir_all(&dir)?;
+    // A put record with a key longer than any chunk key.
+    let mut record = vec![0];
+    record.extend_from_slice(&u32::MAX.to_le_bytes());
+    record.extend_from_slice(&0u64.to_le_bytes());
+    fs::write(dir.join("chunks.log"), record)?;
+
+    assert!(matches!(LogBackend::open(&dir), Err(Error::Io(_))));
+
+    Ok(())
+}
+
+#[test]
+fn log_backend_compacts_as_it_goes() -> Result<()> {
+    let root = temp_dir()?;
+    let dir = root.path().join("log");
+    let mut log = LogBackend::open_compacting_at(&dir, 0)?;
+
+    Backend::<Id>::put(&mut log, &Id(0), &[0; 64])?;
+    let record_len = fs::metadata(dir.join("chunks.log"))?.len();
+    assert_eq!(Backend::<Id>::overhead(&log), record_len - 64);
+
+    // The overwritten value is dead, but doesn't outweigh the live one yet.
+    Backend::<Id>::put(&mut log, &Id(1), &[1; 64])?;
+    Backend::<Id>::put(&mut log, &Id(0), &[2; 64])?;
+    assert_eq!(Backend::<Id>::overhead(&log), 3 * record_len - 2 * 64);
+
+    // Deleting the other value tips it over.
+    Backend::<Id>::delete(&mut log, &Id(1))?;
+    assert_eq!(fs::metadata(dir.join("chunks.log"))?.len(), record_len);
+    assert_eq!(Backend::<Id>::overhead(&log), record_len - 64);
+    assert_eq!(Backend::<Id>::get(&log, &Id(0))?, Some(vec![2; 64]));
+    assert_eq!(Backend::<Id>::get(&log, &Id(1))?, None);
+    drop(log);
+
+    let log = LogBackend::open(&dir)?;
+    assert_eq!(Backend::<Id>::keys(&log), vec![Id(0)]);
+    assert_eq!(Backend::<Id>::get(&log, &Id(0))?, Some(vec![2; 64]));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn detects_corrupted_chunk() -> Result<()> {
+    let root = temp_dir()?;
+    let mut chunk_store =
+        ChunkStore::new(root.path(), u64::MAX, &options(StorageBackend::Filesystem)).await?;
+
+    let data = Data {
+        id: Id(0),
+        value: vec![1; 32],
+    };
+    chunk_store.put(&data).await?;
+
+    // Flip a bit of the stored value, keeping it deserialisable.
+    let file_path = FsBackend::file_path(&store_dir(root.path()), &data.id)?;
+    let mut value = fs::read(&file_path)?;
+    if let Some(byte) = value.last_mut() {
+        *byte ^= 1;
+    }
+    fs::write(&file_path, value)?;
+
+    match chunk_store.get(&data.id).await {
+        Err(Error::ChunkCorrupted(_)) => (),
+        x => return Err(Error::Logic(format!("Unexpected: {:?}", x))),
+    }
+    drop(chunk_store);
+
+    let mut chunk_store: ChunkStore<Data> =
+        ChunkStore::new(root.path(), u64::MAX, &options(StorageBackend::Filesystem)).await?;
+    assert!(!chunk_store.has(&data.id).await);
+    assert_eq!(chunk_store.take_quarantined(), vec![data.id]);
+    assert!(chunk_store.take_quarantined().is_empty());
+    assert_eq!(chunk_store.total_used_space().await, 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn reads_chunks_stored_without_checksum() -> Result<()> {
+    let root = temp_dir()?;
+    let chunk_store: ChunkStore<Data> =
+        ChunkStore::new(root.path(), u64::MAX, &options(StorageBackend::Filesystem)).await?;
+    drop(chunk_store);
+
+    let data = Data {
+        id: Id(0),
+        value: vec![1; 32],
+    };
+    let serialised = bincode::serialize(&data)?;
+    let file_path = FsBackend::file_path(&store_dir(root.path()), &data.id)?;
+    fs::create_dir_all(
+        file_path
+            .parent()
+            .ok_or_else(|| Error::Logic("Chunk file has no parent directory".to_string()))?,
+    )?;
+    fs::write(&file_path, &serialised)?;
+
+    let mut chunk_store: ChunkStore<Data> =
+        ChunkStore::new(root.path(), u64::MAX, &options(StorageBackend::Filesystem)).await?;
+    assert_eq!(chunk_store.get(&data.id).await?, data);
+    assert_eq!(
+        chunk_store.total_used_space().await,
+        serialised.len() as u64
+    );
+
+    // Rewriting it adds the checksum.
+    chunk_store.put(&data).await?;
+    assert_eq!(
+        fs::read(&file_path)?.len(),
+        serialised.len() + checksum::HEADER_LEN
+    );
+    assert_eq!(chunk_store.get(&data.id).await?, data);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn compressed_and_uncompressed_chunks_coexist() -> Result<()> {
+    let rng = new_rng();
+    let root = temp_dir()?;
+    let compressed_options = StoreOptions {
+        compress: true,
+        ..options(StorageBackend::Filesystem)
+    };
+
+    let uncompressed = Data {
+        id: Id(0),
+        value: vec![1; 1024],
+    };
+    let mut chunk_store =
+        ChunkStore::new(root.path(), u64::MAX, &options(StorageBackend::Filesystem)).await?;
+    chunk_store.put(&uncompressed).await?;
+    let uncompressed_size = chunk_store.total_used_space().await;
+    drop(chunk_store);
+
+    let compressible = Data {
+        id: Id(1),
+        value: vec![2; 1024],
+    };
+    let incompressible = Data {
+        id: Id(2),
+        value: rng.sample_iter(&Standard).take(1024).collect(),
+    };
+    let mut chunk_store = ChunkStore::new(root.path(), u64::MAX, &compressed_options).await?;
+    let used_space_before = chunk_store.total_used_space().await;
+    chunk_store.put(&compressible).await?;
+    let compressed_size = chunk_store.total_used_space().await - used_space_before;
+    assert!(compressed_size < uncompressed_size / 4);
+
+    // Data which doesn't compress is stored as it is.
+    let used_space_before = chunk_store.total_used_space().await;
+    chunk_store.put(&incompressible).await?;
+    assert_eq!(
+        chunk_store.total_used_space().await - used_space_before,
+        bincode::serialized_size(&incompressible)? + checksum::HEADER_LEN as u64
+    );
+    drop(chunk_store);
+
+    for options in &[options(StorageBackend::Filesystem), compressed_options] {
+        let chunk_store: ChunkStore<Data> = ChunkStore::new(root.path(), u64::MAX, options).await?;
+        assert_eq!(chunk_store.get(&uncompressed.id).await?, uncompressed);
+        assert_eq!(chunk_store.get(&compressible.id).await?, compressible);
+        assert_eq!(chunk_store.get(&incompressible.id).await?, incompressible);
+        assert_eq!(
+            chunk_store.total_used_space().await,
+            uncompressed_size
+                + compressed_size
+                + bincode::serialized_size(&incompressible)?
+                + checksum::HEADER_LEN as u64
+        );
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn encrypted_chunks_need_their_key() -> Result<()> {
+    let root = temp_dir()?;
+    let encrypted_options = |passphrase| -> Result<StoreOptions> {
+        Ok(StoreOptions {
+            encryption_key: Some(EncryptionKey::from_passphrase(passphrase, root.path())?),
+            ..options(StorageBackend::Filesystem)
+        })
+    };
+
+    let plaintext = Data {
+        id: Id(0),
+        value: b"stored before encryption was enabled".to_vec(),
+    };
+    let mut chunk_store =
+        ChunkStore::new(root.path(), u64::MAX, &options(StorageBackend::Filesystem)).await?;
+    chunk_store.put(&plaintext).await?;
+    drop(chunk_store);
+
+    let encrypted = Data {
+        id: Id(1),
+        value: b"stored encrypted".to_vec(),
+    };
+    let mut chunk_store =
+        ChunkStore::new(root.path(), u64::MAX, &encrypted_options("passphrase")?).await?;
+    chunk_store.put(&encrypted).await?;
+    assert_eq!(chunk_store.get(&plaintext.id).await?, plaintext);
+    assert_eq!(chunk_store.get(&encrypted.id).await?, encrypted);
+    drop(chunk_store);
+
+    let file_path = FsBackend::file_path(&store_dir(root.path()), &encrypted.id)?;
+    let stored = fs::read(&file_path)?;
+    assert!(!stored
+        .windows(encrypted.value.len())
+        .any(|window| window == &encrypted.value[..]));
+
+    // Opening the store with another key or none fails, and leaves the chunks in place.
+    for options in &[
+        encrypted_options("wrong passphrase")?,
+        options(StorageBackend::Filesystem),
+    ] {
+        match ChunkStore::<Data>::new(root.path(), u64::MAX, options).await {
+            Err(Error::InvalidEncryptionKey) => (),
+            Err(error) => return Err(error),
+            Ok(_) => return Err(Error::Logic("Opened store with wrong key".to_string())),
+        }
+    }
+    assert_eq!(fs::read(&file_path)?, stored);
+
+    let chunk_store: ChunkStore<Data> =
+        ChunkStore::new(root.path(), u64::MAX, &encrypted_options("passphrase")?).await?;
+    assert_eq!(chunk_store.get(&encrypted.id).await?, encrypted);
+
+    Ok(())
+}
+
+/// Compares writing chunks on the runtime thread against writing them through the store, and
+/// reading chunks one after the other against reading them concurrently.
+///
+/// Run with `cargo test --release chunk_io_throughput -- --ignored --nocapture`.
+#[tokio::test]
+#[ignore]
+async fn chunk_io_throughput() -> Result<()> {
+    const CHUNK_COUNT: usize = 64;
+    const CHUNK_SIZE: usize = 1024 * 1024;
+    let mut rng = new_rng();
+    let chunks: Vec<_> = (0..CHUNK_COUNT)
+        .map(|index| Data {
+            id: Id(index as u64),
+            value: utils::random_vec(&mut rng, CHUNK_SIZE),
+        })
+        .collect();
+    let total_mb = (CHUNK_COUNT * CHUNK_SIZE) as f64 / (1024.0 * 1024.0);
+
+    // Writing straight to the backend from async code, as the store used to.
+    let root = temp_dir()?;
+    let mut backend = backend::open::<Id>(StorageBackend::Filesystem, &store_dir(root.path()))?;
+    let ticker = Ticker::start().await;
+    let started = Instant::now();
+    for data in &chunks {
+        backend.put(&data.id, &bincode::serialize(data)?)?;
+    }
+    let elapsed = started.elapsed();
+    println!(
+        "blocking writes: {:.1} MB/s, runtime stalled for up to {:?}",
+        total_mb / elapsed.as_secs_f64(),
+        ticker.stop().await
+    );
+
+    let root = temp_dir()?;
+    let mut chunk_store =
+        ChunkStore::new(root.path(), u64::MAX, &options(StorageBackend::Filesystem)).await?;
+    let ticker = Ticker::start().await;
+    let started = Instant::now();
+    for data in &chunks {
+        chunk_store.put(data).await?;
+    }
+    let elapsed = started.elapsed();
+    println!(
+        "store writes: {:.1} MB/s, runtime stalled for up to {:?}",
+        total_mb / elapsed.as_secs_f64(),
+        ticker.stop().await
+    );
+
+    let started = Instant::now();
+    for data in &chunks {
+        let _ = chunk_store.get(&data.id).await?;
+    }
+    println!(
+        "sequential reads: {:.1} MB/s",
+        total_mb / started.elapsed().as_secs_f64()
+    );
+
+    let started = Instant::now();
+    for result in join_all(chunks.iter().map(|data| chunk_store.get(&data.id))).await {
+        let _ = result?;
+    }
+    println!(
+        "concurrent reads: {:.1} MB/s",
+        total_mb / started.elapsed().as_secs_f64()
+    );
+
+    Ok(())
+}
+
+/// Measures how late a task ticking on the runtime gets woken up.
+struct Ticker {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<Duration>,
+}
+
+impl Ticker {
+    const PERIOD: Duration = Duration::from_millis(1);
+
+    async fn start() -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_flag = stop.clone();
+        let handle = tokio::spawn(async move {
+            let mut max_stall = Duration::default();
+            while !stop_flag.load(Ordering::Relaxed) {
+                let started = Instant::now();
+                sleep(Self::PERIOD).await;
+                max_stall = max_stall.max(started.elapsed().saturating_sub(Self::PERIOD));
+            }
+            max_stall
+        });
+        // Lets the ticker start before the caller gets to block the runtime.
+        yield_now().await;
+        Self { stop, handle }
+    }
+
+    async fn stop(self) -> Duration {
+        self.stop.store(true, Ordering::Relaxed);
+        self.handle.await.unwrap_or_default()
+    }
+}
@@ -71,6 +71,11 @@ impl UsedSpace {
         self.inner.lock().await.add_local_store(dir).await
     }
 
+    /// Replace the used amount of a single chunk store, adjusting the global used value to match
+    pub async fn set_local(&self, id: StoreId, value: u64) -> Result<()> {
+        self.inner.lock().await.set_local(id, value).await
+    }
+
     /// Increase the used amount of a single chunk store and the global used value
     pub async fn increase(&self, id: StoreId, consumed: u64) -> Result<()> {
         self.inner.lock().await.increase(id, consumed).await
@@ -184,12 +189,26 @@ mod inner {
                 local_value,
                 local_record,
             };
+            // the recorded space is already in use, so count it towards the total
+            self.total_value = self.total_value.saturating_add(local_value);
             let id = self.next_id;
             self.next_id += 1;
             let _ = self.local_stores.insert(id, local_store);
             Ok(id)
         }
 
+        /// Replace the used space of a local store, adjusting the global value by the difference
+        pub async fn set_local(&mut self, id: StoreId, value: u64) -> Result<()> {
+            let local_store = self.local_stores.get_mut(&id).ok_or(Error::NoStoreId)?;
+            Self::write_local_to_file(&mut local_store.local_record, value).await?;
+            self.total_value = self
+                .total_value
+                .saturating_sub(local_store.local_value)
+                .saturating_add(value);
+            local_store.local_value = value;
+            Ok(())
+        }
+
         /// Increase used space in a local store and globally at the same time
         pub async fn increase(&mut self, id: StoreId, consumed: u64) -> Result<()> {
             let new_total = self
@@ -0,0 +1,200 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use crate::{
+    chunk_store::{BlobChunkStore, StoreOptions},
+    Error, Result,
+};
+use log::{trace, warn};
+use sn_data_types::{Blob, BlobAddress};
+use std::{
+    collections::{HashMap, VecDeque},
+    path::Path,
+};
+
+const CACHE_DIR: &str = "cache";
+
+/// Number of reads after which a chunk we hold is also cached.
+const HOT_CHUNK_READS: usize = 3;
+
+/// Read counts are dropped once this many chunks are tracked, so only chunks read often within a
+/// short while make it to the cache.
+const MAX_TRACKED_READS: usize = 1024;
+
+/// A size-bounded cache of chunks, evicting the least recently used ones once full.
+///
+/// It holds chunks we handed off to new holders, and chunks we hold that are read often, so they
+/// can still be served if we stop holding them. Private chunks aren't cached, as their deletes
+/// only reach their holders, and would miss the copies cached elsewhere.
+pub(crate) struct ChunkCache {
+    chunks: BlobChunkStore,
+    // Cached chunks, least recently used first.
+    recency: VecDeque<BlobAddress>,
+    // Reads of chunks not cached yet.
+    reads: HashMap<BlobAddress, usize>,
+}
+
+impl ChunkCache {
+    /// Opens the cache within `path`, taking up to `capacity` bytes.
+    ///
+    /// Chunks cached before a restart are kept, in no particular order of use.
+    pub(crate) async fn new(path: &Path, capacity: u64, options: &StoreOptions) -> Result<Self> {
+        let chunks = BlobChunkStore::new(path.join(CACHE_DIR), capacity, options).await?;
+        let recency = chunks.keys().await.into();
+        Ok(Self {
+            chunks,
+            recency,
+            reads: HashMap::new(),
+        })
+    }
+
+    /// Returns the chunk at `address`, if cached, marking it as the most recently used.
+    pub(crate) async fn get(&mut self, address: &BlobAddress) -> Option<Blob> {
+        match self.chunks.get(address).await {
+            Ok(chunk) => {
+                self.touch(address);
+                Some(chunk)
+            }
+            Err(Error::NoSuchChunk(_)) => None,
+            Err(error) => {
+                warn!(
+                    "Dropping unreadable cached chunk {:?}: {:?}",
+                    address, error
+                );
+                let _ = self.remove(address).await;
+                None
+            }
+        }
+    }
+
+    /// Caches `chunk`, evicting the least recently used chunks to make room for it. Private chunks
+    /// are left out.
+    pub(crate) async fn insert(&mut self, chunk: &Blob) -> Result<()> {
+        let address = *chunk.address();
+        let _ = self.reads.remove(&address);
+        if chunk.is_private() {
+            return Ok(());
+        }
+        if self.recency.contains(&address) {
+            self.touch(&address);
+            return Ok(());
+        }
+        loop {
+            match self.chunks.put(chunk).await {
+                Ok(()) => {
+                    self.touch(&address);
+                    return Ok(());
+                }
+                Err(Error::NotEnoughSpace) => match self.recency.pop_front() {
+                    Some(evicted) => {
+                        trace!("Evicting cached chunk {:?}", evicted);
+                        self.chunks.delete(&evicted).await?;
+                    }
+                    // The chunk doesn't fit even in an empty cache.
+                    None => return Err(Error::NotEnoughSpace),
+                },
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    /// Records a read of a chunk we hold, returning true once it's read often enough to be cached.
+    pub(crate) fn record_read(&mut self, address: &BlobAddress) -> bool {
+        if self.recency.contains(address) {
+            self.touch(address);
+            return false;
+        }
+        if self.reads.len() >= MAX_TRACKED_READS && !self.reads.contains_key(address) {
+            self.reads.clear();
+        }
+        let reads = self.reads.entry(*address).or_insert(0);
+        *reads += 1;
+        *reads >= HOT_CHUNK_READS
+    }
+
+    /// Removes the chunk at `address`, if cached.
+    pub(crate) async fn remove(&mut self, address: &BlobAddress) -> Result<()> {
+        self.recency.retain(|cached| cached != address);
+        self.chunks.delete(address).await
+    }
+
+    fn touch(&mut self, address: &BlobAddress) {
+        self.recency.retain(|cached| cached != address);
+        self.recency.push_back(*address);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ChunkCache;
+    use crate::{chunk_store::StoreOptions, Error, Result};
+    use sn_data_types::{Blob, PrivateBlob, PublicBlob, PublicKey};
+    use tempdir::TempDir;
+
+    fn temp_dir() -> Result<TempDir> {
+        TempDir::new("test").map_err(|e| Error::TempDirCreationFailed(e.to_string()))
+    }
+
+    fn blob(byte: u8) -> Blob {
+        Blob::Public(PublicBlob::new(vec![byte; 1024]))
+    }
+
+    #[tokio::test]
+    async fn evicts_least_recently_used_chunks() -> Result<()> {
+        let root = temp_dir()?;
+        // Room for two chunks, not three.
+        let mut cache = ChunkCache::new(root.path(), 2500, &StoreOptions::default()).await?;
+        let (first, second, third) = (blob(1), blob(2), blob(3));
+
+        cache.insert(&first).await?;
+        cache.insert(&second).await?;
+        // Reading the first chunk makes the second the least recently used.
+        assert_eq!(cache.get(first.address()).await, Some(first.clone()));
+        cache.insert(&third).await?;
+
+        assert_eq!(cache.get(first.address()).await, Some(first));
+        assert_eq!(cache.get(second.address()).await, None);
+        assert_eq!(cache.get(third.address()).await, Some(third));
+
+        let too_big = Blob::Public(PublicBlob::new(vec![4; 4096]));
+        assert!(matches!(
+            cache.insert(&too_big).await,
+            Err(Error::NotEnoughSpace)
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn caches_chunks_read_often() -> Result<()> {
+        let root = temp_dir()?;
+        let mut cache = ChunkCache::new(root.path(), u64::MAX, &StoreOptions::default()).await?;
+        let chunk = blob(1);
+
+        assert!(!cache.record_read(chunk.address()));
+        assert!(!cache.record_read(chunk.address()));
+        assert!(cache.record_read(chunk.address()));
+        cache.insert(&chunk).await?;
+        assert!(!cache.record_read(chunk.address()));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn leaves_private_chunks_out() -> Result<()> {
+        let root = temp_dir()?;
+        let mut cache = ChunkCache::new(root.path(), u64::MAX, &StoreOptions::default()).await?;
+        let owner = PublicKey::from(bls::SecretKey::random().public_key());
+        let chunk = Blob::Private(PrivateBlob::new(vec![1; 1024], owner));
+
+        cache.insert(&chunk).await?;
+        assert_eq!(cache.get(chunk.address()).await, None);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,192 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use crate::{
+    to_db_key::{from_db_key, ToDbKey},
+    utils, Error, Result,
+};
+use pickledb::PickleDb;
+use sn_data_types::BlobAddress;
+use std::{
+    collections::HashMap,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+const CHUNK_HANDOFFS_DB_NAME: &str = "chunk_handoffs.db";
+
+/// How long to wait for Elders to confirm a handoff before asking them to republish the chunk
+/// again. It doubles on every attempt, up to `MAX_HANDOFF_BACKOFF`.
+const INITIAL_HANDOFF_BACKOFF: Duration = Duration::from_secs(30);
+
+const MAX_HANDOFF_BACKOFF: Duration = Duration::from_secs(30 * 60);
+
+struct Handoff {
+    backoff: Duration,
+    next_attempt: Instant,
+}
+
+impl Handoff {
+    fn new(now: Instant) -> Self {
+        Self {
+            backoff: INITIAL_HANDOFF_BACKOFF,
+            next_attempt: now + INITIAL_HANDOFF_BACKOFF,
+        }
+    }
+}
+
+/// Chunks we no longer hold, but keep until Elders confirm their new holders stored them. The
+/// leaving chunks are kept on disk, so their handoffs resume after a restart, the wait for Elders
+/// to confirm them starting over.
+pub(crate) struct ChunkHandoffs {
+    // addresses of the leaving chunks
+    db: PickleDb,
+    leaving: HashMap<BlobAddress, Handoff>,
+}
+
+impl ChunkHandoffs {
+    pub(crate) fn new(path: &Path, now: Instant) -> Result<Self> {
+        let db = utils::new_auto_dump_db(path, CHUNK_HANDOFFS_DB_NAME)?;
+        let leaving = db
+            .get_all()
+            .iter()
+            .filter_map(|key| from_db_key(key).ok())
+            .map(|address| (address, Handoff::new(now)))
+            .collect();
+        Ok(Self { db, leaving })
+    }
+
+    /// Marks the chunk at `address` as leaving, returning false if it already was.
+    pub(crate) fn start(&mut self, address: BlobAddress, now: Instant) -> Result<bool> {
+        if self.leaving.contains_key(&address) {
+            return Ok(false);
+        }
+        self.db
+            .set(&address.to_db_key()?, &true)
+            .map_err(Error::PickleDb)?;
+        let _ = self.leaving.insert(address, Handoff::new(now));
+        Ok(true)
+    }
+
+    pub(crate) fn is_leaving(&self, address: &BlobAddress) -> bool {
+        self.leaving.contains_key(address)
+    }
+
+    /// Ends the handoff of the chunk at `address`, returning whether it was leaving.
+    pub(crate) fn end(&mut self, address: &BlobAddress) -> Result<bool> {
+        if self.leaving.remove(address).is_none() {
+            return Ok(false);
+        }
+        let _ = self
+            .db
+            .rem(&address.to_db_key()?)
+            .map_err(Error::PickleDb)?;
+        Ok(true)
+    }
+
+    /// Restarts the wait for Elders to confirm the handoff of the chunk at `address`, if it's
+    /// leaving, as it was only just sent to them.
+    pub(crate) fn postpone(&mut self, address: &BlobAddress, now: Instant) {
+        if let Some(handoff) = self.leaving.get_mut(address) {
+            handoff.next_attempt = now + handoff.backoff;
+        }
+    }
+
+    /// Leaving chunks whose handoff is due to be attempted again, backing off their next attempt.
+    pub(crate) fn due(&mut self, now: Instant) -> Vec<BlobAddress> {
+        let mut due = vec![];
+        for (address, handoff) in &mut self.leaving {
+            if handoff.next_attempt > now {
+                continue;
+            }
+            handoff.backoff = std::cmp::min(handoff.backoff * 2, MAX_HANDOFF_BACKOFF);
+            handoff.next_attempt = now + handoff.backoff;
+            due.push(*address);
+        }
+        due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChunkHandoffs, INITIAL_HANDOFF_BACKOFF, MAX_HANDOFF_BACKOFF};
+    use crate::{Error, Result};
+    use sn_data_types::{Blob, PublicBlob};
+    use std::time::Instant;
+    use tempdir::TempDir;
+
+    fn temp_dir() -> Result<TempDir> {
+        TempDir::new("test").map_err(|e| Error::TempDirCreationFailed(e.to_string()))
+    }
+
+    #[test]
+    fn handoffs_are_retried_with_backoff_until_ended() -> Result<()> {
+        let root = temp_dir()?;
+        let address = *Blob::Public(PublicBlob::new(vec![1, 2, 3])).address();
+        let start = Instant::now();
+        let mut handoffs = ChunkHandoffs::new(root.path(), start)?;
+
+        assert!(handoffs.start(address, start)?);
+        assert!(!handoffs.start(address, start)?);
+        assert!(handoffs.due(start).is_empty());
+
+        // Sending the chunk late delays its first retry.
+        handoffs.postpone(&address, start + INITIAL_HANDOFF_BACKOFF);
+        assert!(handoffs.due(start + INITIAL_HANDOFF_BACKOFF).is_empty());
+
+        let first_retry = start + 2 * INITIAL_HANDOFF_BACKOFF;
+        assert_eq!(handoffs.due(first_retry), vec![address]);
+        // The next attempt waits twice as long.
+        assert!(handoffs
+            .due(first_retry + INITIAL_HANDOFF_BACKOFF)
+            .is_empty());
+        let second_retry = first_retry + 2 * INITIAL_HANDOFF_BACKOFF;
+        assert_eq!(handoffs.due(second_retry), vec![address]);
+
+        let much_later = second_retry + 100 * MAX_HANDOFF_BACKOFF;
+        assert_eq!(handoffs.due(much_later), vec![address]);
+        assert_eq!(
+            handoffs.due(much_later + MAX_HANDOFF_BACKOFF),
+            vec![address]
+        );
+
+        assert!(handoffs.is_leaving(&address));
+        assert!(handoffs.end(&address)?);
+        assert!(!handoffs.is_leaving(&address));
+        assert!(handoffs
+            .due(much_later + 2 * MAX_HANDOFF_BACKOFF)
+            .is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn handoffs_resume_after_restarts() -> Result<()> {
+        let root = temp_dir()?;
+        let leaving = *Blob::Public(PublicBlob::new(vec![1])).address();
+        let ended = *Blob::Public(PublicBlob::new(vec![2])).address();
+        let start = Instant::now();
+        let mut handoffs = ChunkHandoffs::new(root.path(), start)?;
+        assert!(handoffs.start(leaving, start)?);
+        assert!(handoffs.start(ended, start)?);
+        assert!(handoffs.end(&ended)?);
+
+        let restart = start + MAX_HANDOFF_BACKOFF;
+        let mut handoffs = ChunkHandoffs::new(root.path(), restart)?;
+        assert!(handoffs.is_leaving(&leaving));
+        assert!(!handoffs.is_leaving(&ended));
+        // Confirmations sent while we were down are lost, so Elders are given a while again.
+        assert!(handoffs.due(restart).is_empty());
+        assert_eq!(
+            handoffs.due(restart + INITIAL_HANDOFF_BACKOFF),
+            vec![leaving]
+        );
+
+        Ok(())
+    }
+}
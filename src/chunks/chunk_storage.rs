@@ -6,13 +6,16 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
+use super::chunk_cache::ChunkCache;
 use crate::{
-    chunk_store::BlobChunkStore,
+    capacity::StorageLevel,
+    chunk_store::{BlobChunkStore, StoreOptions},
     error::convert_to_error_message,
     node_ops::{NodeDuties, NodeDuty, OutgoingMsg},
+    storage_msg::{chunk_proof, ChunkProof, Nonce},
     Error, Result,
 };
-use log::{error, info};
+use log::{error, info, warn};
 use sn_data_types::{Blob, BlobAddress, DataAddress};
 use sn_messaging::{
     client::{CmdError, Error as ErrorMessage, Message, NodeEvent, QueryResponse},
@@ -22,21 +25,36 @@ use std::{
     fmt::{self, Display, Formatter},
     path::Path,
 };
+use tokio::sync::Mutex;
+
+/// Share of the max capacity set aside for the chunk cache.
+const CHUNK_CACHE_SHARE: f64 = 0.1;
 
 /// Storage of data chunks.
 pub(crate) struct ChunkStorage {
     chunks: BlobChunkStore,
+    // chunks handed off to other holders, and chunks read often
+    cache: Mutex<ChunkCache>,
 }
 
 impl ChunkStorage {
     #[allow(dead_code)]
-    pub(crate) async fn new(path: &Path, max_capacity: u64) -> Result<Self> {
-        let chunks = BlobChunkStore::new(path, max_capacity).await?;
-        Ok(Self { chunks })
+    pub(crate) async fn new(
+        path: &Path,
+        max_capacity: u64,
+        options: &StoreOptions,
+    ) -> Result<Self> {
+        let cache_capacity = (max_capacity as f64 * CHUNK_CACHE_SHARE) as u64;
+        let chunks = BlobChunkStore::new(path, max_capacity - cache_capacity, options).await?;
+        let cache = ChunkCache::new(path, cache_capacity, options).await?;
+        Ok(Self {
+            chunks,
+            cache: Mutex::new(cache),
+        })
     }
 
-    pub fn keys(&self) -> Vec<BlobAddress> {
-        self.chunks.keys()
+    pub async fn keys(&self) -> Vec<BlobAddress> {
+        self.chunks.keys().await
     }
 
     pub(crate) async fn store(&mut self, data: &Blob, msg_id: MessageId) -> Result<NodeDuty> {
@@ -59,7 +77,7 @@ impl ChunkStorage {
     }
 
     async fn try_store(&mut self, data: &Blob) -> Result<()> {
-        if self.chunks.has(data.address()) {
+        if self.chunks.has(data.address()).await {
             info!(
                 "{}: Immutable chunk already exists, not storing: {:?}",
                 self,
@@ -70,18 +88,66 @@ impl ChunkStorage {
         self.chunks.put(&data).await
     }
 
-    pub(crate) fn get_chunk(&self, address: &BlobAddress) -> Result<Blob> {
-        self.chunks.get(address)
+    pub(crate) async fn get_chunk(&self, address: &BlobAddress) -> Result<Blob> {
+        self.chunks.get(address).await
+    }
+
+    /// Keeps a copy of a public chunk we no longer hold, so we can still serve it.
+    pub(crate) async fn cache_chunk(&self, chunk: &Blob) -> Result<()> {
+        self.cache.lock().await.insert(chunk).await
+    }
+
+    /// Reads the chunk from the primary store, falling back to the cache.
+    ///
+    /// Chunks read often from the primary store are also cached.
+    async fn read_chunk(&self, address: &BlobAddress) -> Result<Blob> {
+        match self.chunks.get(address).await {
+            Ok(chunk) => {
+                let mut cache = self.cache.lock().await;
+                if cache.record_read(address) {
+                    if let Err(error) = cache.insert(&chunk).await {
+                        warn!("{}: Could not cache chunk {:?}: {:?}", self, address, error);
+                    }
+                }
+                Ok(chunk)
+            }
+            Err(error) => match self.cache.lock().await.get(address).await {
+                Some(chunk) => Ok(chunk),
+                None => Err(error),
+            },
+        }
+    }
+
+    /// Proves we hold the chunk at `address`, for the challenge with `nonce`. Cached chunks
+    /// aren't held, so they don't count.
+    pub(crate) async fn prove(&self, address: &BlobAddress, nonce: &Nonce) -> Option<ChunkProof> {
+        let chunk = self.chunks.get(address).await.ok()?;
+        Some(chunk_proof(nonce, &chunk))
+    }
+
+    /// Whether we hold the chunk at `address`. Cached chunks don't count.
+    pub(crate) async fn has_chunk(&self, address: &BlobAddress) -> bool {
+        self.chunks.has(address).await
     }
 
     pub(crate) async fn delete_chunk(&mut self, address: &BlobAddress) -> Result<()> {
         self.chunks.delete(&address).await
     }
 
-    pub(crate) fn get(&self, address: &BlobAddress, msg_id: MessageId) -> NodeDuties {
+    pub(crate) async fn quarantine_chunk(&mut self, address: &BlobAddress) -> Result<()> {
+        self.chunks.quarantine(address).await
+    }
+
+    /// Chunks found corrupt when the store was opened.
+    pub(crate) fn take_quarantined(&mut self) -> Vec<BlobAddress> {
+        self.chunks.take_quarantined()
+    }
+
+    pub(crate) async fn get(&self, address: &BlobAddress, msg_id: MessageId) -> NodeDuties {
         let mut ops = vec![];
         let result = self
-            .get_chunk(address)
+            .read_chunk(address)
+            .await
             .map_err(|_| ErrorMessage::DataNotFound(DataAddress::Blob(*address)));
 
         // Sent back to data's metadata section, who will then
@@ -102,7 +168,7 @@ impl ChunkStorage {
 
     /// Stores a chunk that Elders sent to it for replication.
     pub async fn store_for_replication(&mut self, blob: Blob) -> Result<()> {
-        if self.chunks.has(blob.address()) {
+        if self.chunks.has(blob.address()).await {
             info!(
                 "{}: Immutable chunk already exists, not storing: {:?}",
                 self,
@@ -120,21 +186,54 @@ impl ChunkStorage {
         self.chunks.used_space_ratio().await
     }
 
+    pub(crate) async fn storage_level(&self) -> StorageLevel {
+        StorageLevel {
+            used: self.chunks.total_used_space().await,
+            max: self.chunks.max_capacity().await,
+        }
+    }
+
     pub(crate) async fn delete(
         &mut self,
         address: BlobAddress,
         msg_id: MessageId,
         origin: EndUser,
     ) -> Result<NodeDuty> {
-        if !self.chunks.has(&address) {
+        let result = if !self.chunks.has(&address).await {
             info!("{}: Immutable chunk doesn't exist: {:?}", self, address);
-            return Ok(NodeDuty::NoOp);
-        }
+            // A cached copy is dropped without checking the owner, as it can be evicted anyway.
+            let _ = self.cache.get_mut().remove(&address).await;
+            // Elders send deletes to every candidate holder, so they're told there's nothing here
+            // rather than waiting for an answer which never comes.
+            Err(ErrorMessage::DataNotFound(DataAddress::Blob(address)))
+        } else {
+            self.delete_held(&address, msg_id, origin).await
+        };
 
-        let result = match self.chunks.get(&address) {
+        Ok(NodeDuty::Send(OutgoingMsg {
+            msg: Message::NodeEvent {
+                event: NodeEvent::ChunkWriteHandled(result.map_err(CmdError::Data)),
+                id: MessageId::in_response_to(&msg_id),
+                correlation_id: msg_id,
+            },
+            section_source: false, // sent as single node
+            // respond to data's metadata elders
+            dst: DstLocation::Section(*address.name()),
+            aggregation: Aggregation::None,
+        }))
+    }
+
+    async fn delete_held(
+        &mut self,
+        address: &BlobAddress,
+        msg_id: MessageId,
+        origin: EndUser,
+    ) -> Result<(), ErrorMessage> {
+        match self.chunks.get(address).await {
             Ok(Blob::Private(data)) => {
                 if data.owner() == origin.id() {
-                    self.delete_chunk(&address)
+                    let _ = self.cache.get_mut().remove(address).await;
+                    self.delete_chunk(address)
                         .await
                         .map_err(|_error| ErrorMessage::FailedToDelete)
                 } else {
@@ -152,19 +251,7 @@ impl ChunkStorage {
                 )))
             }
             _ => Err(ErrorMessage::NoSuchKey),
-        };
-
-        Ok(NodeDuty::Send(OutgoingMsg {
-            msg: Message::NodeEvent {
-                event: NodeEvent::ChunkWriteHandled(result.map_err(CmdError::Data)),
-                id: MessageId::in_response_to(&msg_id),
-                correlation_id: msg_id,
-            },
-            section_source: false, // sent as single node
-            // respond to data's metadata elders
-            dst: DstLocation::Section(*address.name()),
-            aggregation: Aggregation::None,
-        }))
+        }
     }
 }
 
@@ -194,11 +281,11 @@ mod tests {
     #[tokio::test]
     pub async fn try_store_stores_public_blob() -> Result<()> {
         let path = PathBuf::from(temp_dir()?.path());
-        let mut storage = ChunkStorage::new(&path, u64::MAX).await?;
+        let mut storage = ChunkStorage::new(&path, u64::MAX, &StoreOptions::default()).await?;
         let value = "immutable data value".to_owned().into_bytes();
         let blob = Blob::Public(PublicBlob::new(value));
         assert!(storage.try_store(&blob).await.is_ok());
-        assert!(storage.chunks.has(blob.address()));
+        assert!(storage.chunks.has(blob.address()).await);
 
         Ok(())
     }
@@ -206,12 +293,96 @@ mod tests {
     #[tokio::test]
     pub async fn try_store_stores_private_blob() -> Result<()> {
         let path = PathBuf::from(temp_dir()?.path());
-        let mut storage = ChunkStorage::new(&path, u64::MAX).await?;
+        let mut storage = ChunkStorage::new(&path, u64::MAX, &StoreOptions::default()).await?;
         let value = "immutable data value".to_owned().into_bytes();
         let key = get_random_pk();
         let blob = Blob::Private(PrivateBlob::new(value, key));
         assert!(storage.try_store(&blob).await.is_ok());
-        assert!(storage.chunks.has(blob.address()));
+        assert!(storage.chunks.has(blob.address()).await);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    pub async fn get_falls_back_to_cache() -> Result<()> {
+        let path = PathBuf::from(temp_dir()?.path());
+        let mut storage = ChunkStorage::new(&path, u64::MAX, &StoreOptions::default()).await?;
+        let value = "immutable data value".to_owned().into_bytes();
+        let blob = Blob::Public(PublicBlob::new(value));
+        storage.try_store(&blob).await?;
+
+        // Handed off to another holder.
+        storage.cache_chunk(&blob).await?;
+        storage.delete_chunk(blob.address()).await?;
+
+        assert!(!storage.chunks.has(blob.address()).await);
+        assert!(storage.get_chunk(blob.address()).await.is_err());
+        assert_eq!(storage.read_chunk(blob.address()).await?, blob);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    pub async fn private_blobs_are_not_served_once_no_longer_held() -> Result<()> {
+        let path = PathBuf::from(temp_dir()?.path());
+        let mut storage = ChunkStorage::new(&path, u64::MAX, &StoreOptions::default()).await?;
+        let value = "immutable data value".to_owned().into_bytes();
+        let blob = Blob::Private(PrivateBlob::new(value, get_random_pk()));
+        storage.try_store(&blob).await?;
+
+        // Read often, then handed off to another holder, where it may get deleted.
+        for _ in 0..3 {
+            assert_eq!(storage.read_chunk(blob.address()).await?, blob);
+        }
+        storage.cache_chunk(&blob).await?;
+        storage.delete_chunk(blob.address()).await?;
+
+        assert!(storage.read_chunk(blob.address()).await.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    pub async fn deletes_of_chunks_not_held_are_answered() -> Result<()> {
+        let path = PathBuf::from(temp_dir()?.path());
+        let mut storage = ChunkStorage::new(&path, u64::MAX, &StoreOptions::default()).await?;
+        let owner = get_random_pk();
+        let value = "immutable data value".to_owned().into_bytes();
+        let address = *Blob::Private(PrivateBlob::new(value, owner)).address();
+
+        let duty = storage
+            .delete(address, MessageId::new(), EndUser::AllClients(owner))
+            .await?;
+        let not_found = ErrorMessage::DataNotFound(DataAddress::Blob(address));
+        assert!(matches!(
+            duty,
+            NodeDuty::Send(OutgoingMsg {
+                msg: Message::NodeEvent {
+                    event: NodeEvent::ChunkWriteHandled(Err(CmdError::Data(error))),
+                    ..
+                },
+                ..
+            }) if error == not_found
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    pub async fn prove_hashes_nonce_with_held_chunk() -> Result<()> {
+        let path = PathBuf::from(temp_dir()?.path());
+        let mut storage = ChunkStorage::new(&path, u64::MAX, &StoreOptions::default()).await?;
+        let value = "immutable data value".to_owned().into_bytes();
+        let blob = Blob::Public(PublicBlob::new(value));
+        let nonce = rand::random();
+        assert_eq!(storage.prove(blob.address(), &nonce).await, None);
+
+        storage.try_store(&blob).await?;
+
+        assert_eq!(
+            storage.prove(blob.address(), &nonce).await,
+            Some(chunk_proof(&nonce, &blob))
+        );
 
         Ok(())
     }
@@ -6,55 +6,205 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
+mod chunk_cache;
+mod chunk_handoffs;
 mod chunk_storage;
+mod replication_queue;
+mod shard_marks;
+
+pub use replication_queue::ReplicationRate;
 
 use crate::{
+    chunk_store::StoreOptions,
     error::convert_to_error_message,
     node_ops::{NodeDuties, NodeDuty, OutgoingMsg},
-    Result,
+    storage_msg::{Nonce, StorageMsg},
+    Error, Result,
 };
+use chunk_handoffs::ChunkHandoffs;
 use chunk_storage::ChunkStorage;
-use log::info;
+use log::{info, warn};
+use replication_queue::ReplicationQueue;
+use shard_marks::ShardMarks;
 use sn_data_types::{Blob, BlobAddress};
 use sn_messaging::{
     client::{BlobRead, BlobWrite, CmdError, Message, NodeEvent},
     Aggregation, DstLocation, EndUser, MessageId,
 };
 use std::{
+    collections::{BTreeSet, VecDeque},
     fmt::{self, Display, Formatter},
+    iter,
     path::Path,
+    time::Instant,
 };
+use xor_name::XorName;
 
 /// At 50% full, the node will report that it's reaching full capacity.
 pub const MAX_STORAGE_USAGE_RATIO: f64 = 0.5;
 
+/// Back under 40% full, the node will report that it has storage available again. The gap to
+/// `MAX_STORAGE_USAGE_RATIO` keeps a node hovering around it from flapping in and out of the full
+/// list.
+const STORAGE_AVAILABLE_RATIO: f64 = 0.4;
+
+/// Number of chunks whose checksums are verified on each scrub.
+const SCRUB_BATCH_SIZE: usize = 32;
+
 /// Operations on data chunks.
 pub(crate) struct Chunks {
     chunk_storage: ChunkStorage,
+    // chunks still to be verified in the current scrub pass
+    scrub_queue: VecDeque<BlobAddress>,
+    // whether we last reported being full, or None if we haven't reported either way yet
+    reported_full: Option<bool>,
+    // chunks we no longer hold, kept until their new holders are confirmed to have them
+    handoffs: ChunkHandoffs,
+    // chunks to send to Elders for republishing
+    replication: ReplicationQueue,
+    // chunks Elders marked as shards, which we hold alone
+    shard_marks: ShardMarks,
 }
 
 impl Chunks {
-    pub async fn new(path: &Path, max_capacity: u64) -> Result<Self> {
+    pub async fn new(
+        path: &Path,
+        max_capacity: u64,
+        options: &StoreOptions,
+        replication_rate: ReplicationRate,
+    ) -> Result<Self> {
         Ok(Self {
-            chunk_storage: ChunkStorage::new(path, max_capacity).await?,
+            chunk_storage: ChunkStorage::new(path, max_capacity, options).await?,
+            scrub_queue: VecDeque::new(),
+            reported_full: None,
+            handoffs: ChunkHandoffs::new(path, Instant::now())?,
+            replication: ReplicationQueue::new(path, replication_rate)?,
+            shard_marks: ShardMarks::new(path)?,
         })
     }
 
-    pub fn keys(&self) -> Vec<BlobAddress> {
-        self.chunk_storage.keys()
+    pub async fn keys(&self) -> Vec<BlobAddress> {
+        self.chunk_storage.keys().await
     }
 
     pub async fn remove_chunk(&mut self, address: &BlobAddress) -> Result<()> {
+        self.shard_marks.unmark(address)?;
         self.chunk_storage.delete_chunk(address).await
     }
 
-    pub fn get_chunk(&self, address: &BlobAddress) -> Result<Blob> {
-        self.chunk_storage.get_chunk(address)
+    /// Elders told us the chunk at `address` is a shard, which we hold alone.
+    pub fn mark_shard(&mut self, address: &BlobAddress) -> Result<()> {
+        self.shard_marks.mark(address)
+    }
+
+    pub fn is_shard(&self, address: &BlobAddress) -> bool {
+        self.shard_marks.is_marked(address)
+    }
+
+    pub async fn has_chunk(&self, address: &BlobAddress) -> bool {
+        self.chunk_storage.has_chunk(address).await
+    }
+
+    /// Keeps a copy of a chunk we handed off to its new holders, to serve reads still routed to us.
+    pub async fn cache_chunk(&self, chunk: &Blob) -> Result<()> {
+        self.chunk_storage.cache_chunk(chunk).await
+    }
+
+    /// Keeps a chunk we no longer hold, until Elders confirm its new holders stored it. Returns
+    /// false if it was already being handed off.
+    pub fn start_handoff(&mut self, address: BlobAddress) -> Result<bool> {
+        self.handoffs.start(address, Instant::now())
+    }
+
+    /// Stops handing off a chunk we hold again.
+    pub fn cancel_handoff(&mut self, address: &BlobAddress) -> Result<()> {
+        let _ = self.handoffs.end(address)?;
+        Ok(())
+    }
+
+    pub fn is_leaving(&self, address: &BlobAddress) -> bool {
+        self.handoffs.is_leaving(address)
     }
 
-    pub fn read(&mut self, read: &BlobRead, msg_id: MessageId) -> NodeDuties {
+    /// Queues chunks to be republished, with the number of copies thought to be left of them.
+    pub fn queue_replication(&mut self, chunks: Vec<(BlobAddress, usize)>) -> Result<()> {
+        self.replication.push(chunks)
+    }
+
+    /// Queues the chunks being handed off whose new holders weren't confirmed in time, to be
+    /// republished again. Those still queued from before aren't due yet.
+    pub fn queue_due_handoffs(&mut self, copies_left: usize) -> Result<()> {
+        let replication = &self.replication;
+        let due = self
+            .handoffs
+            .due(Instant::now())
+            .into_iter()
+            .filter(|address| !replication.contains(address))
+            .map(|address| (address, copies_left))
+            .collect::<Vec<_>>();
+        if due.is_empty() {
+            return Ok(());
+        }
+        self.replication.push(due)
+    }
+
+    /// Takes as many chunks off the replication queue as its rate allows now.
+    pub async fn next_replications(&mut self) -> Result<Vec<Blob>> {
+        let now = Instant::now();
+        let mut chunks = vec![];
+        while let Some(address) = self.replication.pop(now)? {
+            match self.chunk_storage.get_chunk(&address).await {
+                Ok(chunk) => {
+                    self.replication.charge(chunk.value().len());
+                    // The wait for its new holders to be confirmed starts once it's sent.
+                    self.handoffs.postpone(&address, now);
+                    chunks.push(chunk);
+                }
+                // deleted since it was queued
+                Err(Error::NoSuchChunk(_)) => {
+                    let _ = self.handoffs.end(&address)?;
+                }
+                Err(error) => warn!("Could not replicate chunk {:?}: {:?}", address, error),
+            }
+        }
+        self.replication.flush()?;
+        Ok(chunks)
+    }
+
+    /// Elders confirmed the new holders of a chunk we handed off stored it, so we can let go of
+    /// it. It's still cached, to serve reads routed to us meanwhile.
+    pub async fn complete_handoff(&mut self, address: &BlobAddress) -> Result<()> {
+        if !self.handoffs.end(address)? {
+            return Ok(());
+        }
+        info!("Handoff of chunk {:?} confirmed", address);
+        let chunk = self.chunk_storage.get_chunk(address).await?;
+        if let Err(error) = self.cache_chunk(&chunk).await {
+            warn!("Error caching chunk after its handoff: {:?}", error);
+        }
+        self.remove_chunk(address).await
+    }
+
+    /// Elders moved a chunk we held to another Adult, which stored it, so we can let go of it.
+    /// It's still cached, to serve reads routed to us meanwhile.
+    pub async fn release_moved(&mut self, address: &BlobAddress) -> Result<()> {
+        let _ = self.handoffs.end(address)?;
+        let chunk = match self.chunk_storage.get_chunk(address).await {
+            Ok(chunk) => chunk,
+            Err(Error::NoSuchChunk(_)) => return Ok(()),
+            Err(error) => return Err(error),
+        };
+        info!("Chunk {:?} moved off us", address);
+        if let Err(error) = self.cache_chunk(&chunk).await {
+            warn!("Error caching chunk after moving it off: {:?}", error);
+        }
+        self.remove_chunk(address).await
+    }
+
+    /// Reads only take `&self`, so several of them can be served concurrently.
+    pub async fn read(&self, read: &BlobRead, msg_id: MessageId) -> NodeDuties {
         let BlobRead::Get(address) = read;
-        self.chunk_storage.get(address, msg_id)
+        self.chunk_storage.get(address, msg_id).await
     }
 
     pub async fn write(
@@ -72,15 +222,84 @@ impl Chunks {
         }
     }
 
-    pub async fn check_storage(&self) -> Result<NodeDuties> {
+    /// Reports crossing into or back out of full capacity, once each time.
+    pub async fn check_storage(&mut self) -> Result<NodeDuties> {
         info!("Checking used storage");
-        if self.chunk_storage.used_space_ratio().await > MAX_STORAGE_USAGE_RATIO {
+        let used_space_ratio = self.chunk_storage.used_space_ratio().await;
+        if used_space_ratio > MAX_STORAGE_USAGE_RATIO && self.reported_full != Some(true) {
+            self.reported_full = Some(true);
             Ok(NodeDuties::from(NodeDuty::ReachingMaxCapacity))
+        } else if used_space_ratio < STORAGE_AVAILABLE_RATIO && self.reported_full != Some(false) {
+            self.reported_full = Some(false);
+            Ok(NodeDuties::from(NodeDuty::StorageAvailable))
         } else {
             Ok(vec![])
         }
     }
 
+    /// Verifies the next batch of stored chunks against their checksums, working through all of
+    /// them over successive calls.
+    ///
+    /// Corrupt chunks are quarantined, and our Elders are asked to restore them from their other
+    /// holders.
+    pub async fn scrub(&mut self, elders: BTreeSet<XorName>) -> Result<NodeDuties> {
+        if self.scrub_queue.is_empty() {
+            self.scrub_queue = self.keys().await.into();
+        }
+        let mut corrupted = self.chunk_storage.take_quarantined();
+        let batch_size = SCRUB_BATCH_SIZE.min(self.scrub_queue.len());
+        for address in self.scrub_queue.drain(..batch_size).collect::<Vec<_>>() {
+            match self.chunk_storage.get_chunk(&address).await {
+                // Chunks deleted since the pass started are skipped.
+                Ok(_) | Err(Error::NoSuchChunk(_)) => (),
+                Err(Error::ChunkCorrupted(_)) | Err(Error::Bincode(_)) => {
+                    warn!("Quarantining corrupt chunk {:?}", address);
+                    self.chunk_storage.quarantine_chunk(&address).await?;
+                    corrupted.push(address);
+                }
+                Err(error) => warn!("Could not verify chunk {:?}: {:?}", address, error),
+            }
+        }
+
+        Ok(corrupted
+            .into_iter()
+            .map(|address| NodeDuty::SendStorageMsg {
+                msg: StorageMsg::ChunkCorrupted { address },
+                targets: elders.clone(),
+            })
+            .collect())
+    }
+
+    /// Answers an Elder challenging us to prove we still store a chunk.
+    pub async fn prove(&self, address: BlobAddress, nonce: Nonce, elder: XorName) -> NodeDuty {
+        let proof = self.chunk_storage.prove(&address, &nonce).await;
+        NodeDuty::SendStorageMsg {
+            msg: StorageMsg::ChunkProof {
+                address,
+                nonce,
+                proof,
+            },
+            targets: iter::once(elder).collect(),
+        }
+    }
+
+    /// Answers an Elder asking whether we hold a chunk.
+    pub async fn report_held(&self, address: BlobAddress, elder: XorName) -> NodeDuty {
+        let held = self.chunk_storage.has_chunk(&address).await;
+        NodeDuty::SendStorageMsg {
+            msg: StorageMsg::ChunkHeld { address, held },
+            targets: iter::once(elder).collect(),
+        }
+    }
+
+    /// Tells our Elders how much storage we use, for them to prefer emptier Adults as holders.
+    pub async fn report_storage_level(&self, elders: BTreeSet<XorName>) -> NodeDuty {
+        NodeDuty::SendStorageMsg {
+            msg: StorageMsg::StorageLevel(self.chunk_storage.storage_level().await),
+            targets: elders,
+        }
+    }
+
     /// Stores a chunk that Elders sent to it for replication.
     pub async fn store_for_replication(
         &mut self,
@@ -111,3 +330,58 @@ impl Display for Chunks {
         write!(formatter, "Chunks")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sn_data_types::PublicBlob;
+    use tempdir::TempDir;
+
+    fn temp_dir() -> Result<TempDir> {
+        TempDir::new("test").map_err(|e| Error::TempDirCreationFailed(e.to_string()))
+    }
+
+    #[tokio::test]
+    async fn check_storage_reports_full_and_available_once() -> Result<()> {
+        let root = temp_dir()?;
+        let rate = ReplicationRate {
+            bytes_per_sec: 1000,
+            chunks_per_sec: 1,
+        };
+        let mut chunks = Chunks::new(root.path(), 10_000, &StoreOptions::default(), rate).await?;
+        let blobs = (0..5)
+            .map(|byte| Blob::Public(PublicBlob::new(vec![byte; 1000])))
+            .collect::<Vec<_>>();
+
+        // Nothing reported yet, so the Elders are told there's room.
+        assert!(matches!(
+            chunks.check_storage().await?[..],
+            [NodeDuty::StorageAvailable]
+        ));
+        assert!(chunks.check_storage().await?.is_empty());
+
+        for blob in &blobs {
+            let _ = chunks
+                .store_for_replication(blob.clone(), MessageId::new())
+                .await?;
+        }
+        assert!(matches!(
+            chunks.check_storage().await?[..],
+            [NodeDuty::ReachingMaxCapacity]
+        ));
+        assert!(chunks.check_storage().await?.is_empty());
+
+        // Just under the full mark isn't enough to be available again.
+        chunks.remove_chunk(blobs[0].address()).await?;
+        assert!(chunks.check_storage().await?.is_empty());
+
+        chunks.remove_chunk(blobs[1].address()).await?;
+        assert!(matches!(
+            chunks.check_storage().await?[..],
+            [NodeDuty::StorageAvailable]
+        ));
+        assert!(chunks.check_storage().await?.is_empty());
+
+        Ok(())
+    }
+}
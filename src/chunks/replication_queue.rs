@@ -0,0 +1,190 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use crate::{
+    to_db_key::{from_db_key, ToDbKey},
+    utils, Error, Result,
+};
+use pickledb::PickleDb;
+use sn_data_types::BlobAddress;
+use std::{collections::BTreeSet, path::Path, time::Instant};
+
+const REPLICATION_QUEUE_DB_NAME: &str = "replication_queue.db";
+
+/// How fast an Adult sends its chunks to Elders to be republished after churn.
+#[derive(Clone, Copy, Debug)]
+pub struct ReplicationRate {
+    /// Bytes of chunks sent per second.
+    pub bytes_per_sec: u64,
+    /// Chunks sent per second.
+    pub chunks_per_sec: u32,
+}
+
+/// Chunks waiting to be sent to Elders for republishing, those with the fewest copies left first.
+/// It's kept on disk, so sending resumes after a restart.
+pub(crate) struct ReplicationQueue {
+    // copies left of the queued chunks, by their address
+    db: PickleDb,
+    // the queued chunks, by the copies left of them
+    order: BTreeSet<(usize, BlobAddress)>,
+    rate: ReplicationRate,
+    // What can still be sent. It's refilled at `rate`, up to a second's worth.
+    byte_allowance: f64,
+    chunk_allowance: f64,
+    refilled: Instant,
+}
+
+impl ReplicationQueue {
+    pub(crate) fn new(path: &Path, rate: ReplicationRate) -> Result<Self> {
+        let db = utils::new_dump_on_request_db(path, REPLICATION_QUEUE_DB_NAME)?;
+        let order = db
+            .get_all()
+            .iter()
+            .filter_map(|key| Some((db.get(key)?, from_db_key(key).ok()?)))
+            .collect();
+        let (bytes_per_sec, chunks_per_sec) = per_sec(rate);
+        Ok(Self {
+            db,
+            order,
+            rate,
+            byte_allowance: bytes_per_sec,
+            chunk_allowance: chunks_per_sec,
+            refilled: Instant::now(),
+        })
+    }
+
+    /// Queues chunks with the number of copies left of them. A chunk already queued keeps the
+    /// lowest number it was queued with.
+    pub(crate) fn push(
+        &mut self,
+        chunks: impl IntoIterator<Item = (BlobAddress, usize)>,
+    ) -> Result<()> {
+        for (address, copies_left) in chunks {
+            let key = address.to_db_key()?;
+            match self.db.get::<usize>(&key) {
+                Some(queued) if queued <= copies_left => continue,
+                Some(queued) => {
+                    let _ = self.order.remove(&(queued, address));
+                }
+                None => (),
+            }
+            self.db.set(&key, &copies_left).map_err(Error::PickleDb)?;
+            let _ = self.order.insert((copies_left, address));
+        }
+        self.flush()
+    }
+
+    pub(crate) fn contains(&self, address: &BlobAddress) -> bool {
+        address
+            .to_db_key()
+            .map(|key| self.db.exists(&key))
+            .unwrap_or(false)
+    }
+
+    /// Takes the next chunk to send, if the rate allows sending one by `now`. Its size is to be
+    /// `charge`d once known, and the queue `flush`ed once done taking chunks.
+    pub(crate) fn pop(&mut self, now: Instant) -> Result<Option<BlobAddress>> {
+        self.refill(now);
+        if self.chunk_allowance < 1.0 || self.byte_allowance <= 0.0 {
+            return Ok(None);
+        }
+        let (copies_left, address) = match self.order.iter().next() {
+            Some(next) => *next,
+            None => return Ok(None),
+        };
+        let _ = self.order.remove(&(copies_left, address));
+        let _ = self
+            .db
+            .rem(&address.to_db_key()?)
+            .map_err(Error::PickleDb)?;
+        self.chunk_allowance -= 1.0;
+        Ok(Some(address))
+    }
+
+    /// Charges the bytes of a chunk taken against the rate. A chunk bigger than what's left of
+    /// the allowance is still sent, but delays those after it.
+    pub(crate) fn charge(&mut self, bytes: usize) {
+        self.byte_allowance -= bytes as f64;
+    }
+
+    /// Writes the chunks taken off the queue to disk.
+    pub(crate) fn flush(&mut self) -> Result<()> {
+        self.db.dump().map_err(Error::PickleDb)
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.refilled).as_secs_f64();
+        self.refilled = now;
+        let (bytes_per_sec, chunks_per_sec) = per_sec(self.rate);
+        self.byte_allowance = bytes_per_sec.min(self.byte_allowance + elapsed * bytes_per_sec);
+        self.chunk_allowance = chunks_per_sec.min(self.chunk_allowance + elapsed * chunks_per_sec);
+    }
+}
+
+// A rate of zero would never send anything, so it's treated as the lowest rate instead.
+fn per_sec(rate: ReplicationRate) -> (f64, f64) {
+    (
+        rate.bytes_per_sec.max(1) as f64,
+        f64::from(rate.chunks_per_sec.max(1)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ReplicationQueue, ReplicationRate};
+    use crate::{Error, Result};
+    use sn_data_types::{Blob, BlobAddress, PublicBlob};
+    use std::time::{Duration, Instant};
+    use tempdir::TempDir;
+
+    fn temp_dir() -> Result<TempDir> {
+        TempDir::new("test").map_err(|e| Error::TempDirCreationFailed(e.to_string()))
+    }
+
+    fn address(byte: u8) -> BlobAddress {
+        *Blob::Public(PublicBlob::new(vec![byte])).address()
+    }
+
+    #[test]
+    fn chunks_with_fewest_copies_left_are_sent_first_at_the_rate() -> Result<()> {
+        let root = temp_dir()?;
+        let rate = ReplicationRate {
+            bytes_per_sec: 1000,
+            chunks_per_sec: 2,
+        };
+        let mut queue = ReplicationQueue::new(root.path(), rate)?;
+        queue.push(vec![(address(0), 4), (address(1), 1), (address(2), 2)])?;
+        // Only the lowest number of copies left counts.
+        queue.push(vec![(address(0), 3), (address(1), 4)])?;
+
+        // The queue survives restarts.
+        let mut queue = ReplicationQueue::new(root.path(), rate)?;
+        let start = Instant::now();
+        assert_eq!(queue.pop(start)?, Some(address(1)));
+        queue.charge(10);
+        assert_eq!(queue.pop(start)?, Some(address(2)));
+        queue.charge(10);
+        // Two chunks a second.
+        assert_eq!(queue.pop(start)?, None);
+        queue.flush()?;
+
+        let mut queue = ReplicationQueue::new(root.path(), rate)?;
+        assert!(!queue.contains(&address(1)));
+        assert!(queue.contains(&address(0)));
+        let start = Instant::now();
+        queue.push(vec![(address(3), 0)])?;
+        assert_eq!(queue.pop(start)?, Some(address(3)));
+        // A chunk over the byte allowance holds back the next ones until it's paid off.
+        queue.charge(2500);
+        assert_eq!(queue.pop(start + Duration::from_secs(1))?, None);
+        assert_eq!(queue.pop(start + Duration::from_secs(3))?, Some(address(0)));
+        assert_eq!(queue.pop(start + Duration::from_secs(4))?, None);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,74 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use crate::{to_db_key::ToDbKey, utils, Error, Result};
+use pickledb::PickleDb;
+use sn_data_types::BlobAddress;
+use std::path::Path;
+
+const SHARD_MARKS_DB_NAME: &str = "shard_marks.db";
+
+/// Chunks Elders marked as shards of erasure-coded blobs, which we hold alone rather than along
+/// with other holders. Elders mark the shards they write to us, as only they know which chunks are
+/// shards. The marks are kept on disk, so they hold after a restart.
+pub(crate) struct ShardMarks {
+    db: PickleDb,
+}
+
+impl ShardMarks {
+    pub(crate) fn new(path: &Path) -> Result<Self> {
+        Ok(Self {
+            db: utils::new_auto_dump_db(path, SHARD_MARKS_DB_NAME)?,
+        })
+    }
+
+    pub(crate) fn mark(&mut self, address: &BlobAddress) -> Result<()> {
+        self.db
+            .set(&address.to_db_key()?, &true)
+            .map_err(Error::PickleDb)
+    }
+
+    pub(crate) fn unmark(&mut self, address: &BlobAddress) -> Result<()> {
+        let _ = self
+            .db
+            .rem(&address.to_db_key()?)
+            .map_err(Error::PickleDb)?;
+        Ok(())
+    }
+
+    pub(crate) fn is_marked(&self, address: &BlobAddress) -> bool {
+        address
+            .to_db_key()
+            .map_or(false, |key| self.db.exists(&key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ShardMarks;
+    use crate::{Error, Result};
+    use sn_data_types::{Blob, PublicBlob};
+    use tempdir::TempDir;
+
+    #[test]
+    fn marks_survive_restarts() -> Result<()> {
+        let root = TempDir::new("test").map_err(|e| Error::TempDirCreationFailed(e.to_string()))?;
+        let shard = *Blob::Public(PublicBlob::new(vec![1])).address();
+        let chunk = *Blob::Public(PublicBlob::new(vec![2])).address();
+
+        ShardMarks::new(root.path())?.mark(&shard)?;
+        let mut marks = ShardMarks::new(root.path())?;
+        assert!(marks.is_marked(&shard));
+        assert!(!marks.is_marked(&chunk));
+
+        marks.unmark(&shard)?;
+        assert!(!ShardMarks::new(root.path())?.is_marked(&shard));
+
+        Ok(())
+    }
+}
@@ -17,7 +17,8 @@ use std::{
     fs::{self, File},
     io::{self, BufReader},
     net::{IpAddr, Ipv4Addr, SocketAddr},
-    path::PathBuf,
+    path::{Path, PathBuf},
+    str::FromStr,
 };
 use structopt::StructOpt;
 
@@ -25,6 +26,8 @@ const CONFIG_FILE: &str = "node.config";
 const CONNECTION_INFO_FILE: &str = "node_connection_info.config";
 const DEFAULT_ROOT_DIR_NAME: &str = "root_dir";
 const DEFAULT_MAX_CAPACITY: u64 = 2 * 1024 * 1024 * 1024;
+const DEFAULT_REPLICATION_BANDWIDTH: u64 = 1024 * 1024;
+const DEFAULT_REPLICATION_MSG_RATE: u32 = 10;
 
 /// Node configuration
 #[derive(Default, Clone, Debug, Serialize, Deserialize, Eq, PartialEq, StructOpt)]
@@ -115,11 +118,114 @@ pub struct Config {
     /// Duration of a UPnP port mapping.
     #[structopt(long)]
     pub upnp_lease_duration: Option<u32>,
+    /// Storage backend for ChunkStores: [filesystem, log]. If not set, it defaults to
+    /// "filesystem".
+    #[structopt(long)]
+    pub storage_backend: Option<StorageBackend>,
+    /// Compress chunks before storing them
+    #[structopt(long)]
+    pub compress_chunks: bool,
+    /// Store blobs as Reed-Solomon erasure-coded shards spread across Adults, rather than as full
+    /// copies, while this node is an Elder.
+    #[structopt(long)]
+    pub erasure_coding: bool,
+    /// How many holders must store a blob before its write is confirmed to the client, while this
+    /// node is an Elder: [one, majority, all]. If not set, it defaults to "majority".
+    #[structopt(long)]
+    pub write_ack_policy: Option<WriteAckPolicy>,
+    /// Bytes per second an Adult spends sending its chunks to be republished after churn. If not
+    /// set, it defaults to 1 MiB/s.
+    #[structopt(long)]
+    pub replication_bandwidth: Option<u64>,
+    /// Chunks per second an Adult sends to be republished after churn. If not set, it defaults
+    /// to 10.
+    #[structopt(long)]
+    pub replication_msg_rate: Option<u32>,
+    /// Encrypt stored chunks and transfers with a key derived from this passphrase. It is never
+    /// written to the config file.
+    #[structopt(long)]
+    #[serde(skip)]
+    pub encryption_passphrase: Option<String>,
+    /// Encrypt stored chunks and transfers with the hex-encoded 256-bit key held in this file.
+    #[structopt(long, parse(from_os_str))]
+    pub encryption_key_file: Option<PathBuf>,
     #[structopt(skip)]
     #[allow(missing_docs)]
     pub network_config: NetworkConfig,
 }
 
+/// Where `ChunkStore`s hold their chunks.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageBackend {
+    /// One file per chunk, fanned out over shard directories.
+    Filesystem,
+    /// In memory only, nothing survives a restart. Only built for tests, as Elders would keep
+    /// counting a restarted node as the holder of chunks it lost.
+    #[cfg(test)]
+    Memory,
+    /// A single append-only log file per store.
+    Log,
+}
+
+impl Default for StorageBackend {
+    fn default() -> Self {
+        Self::Filesystem
+    }
+}
+
+impl FromStr for StorageBackend {
+    type Err = Error;
+
+    fn from_str(backend: &str) -> Result<Self> {
+        match backend {
+            "filesystem" => Ok(Self::Filesystem),
+            #[cfg(test)]
+            "memory" => Ok(Self::Memory),
+            "log" => Ok(Self::Log),
+            _ => Err(Error::Configuration(format!(
+                "Unknown storage backend: {}",
+                backend
+            ))),
+        }
+    }
+}
+
+/// How many of the copies a blob is meant to have must be stored before its write is confirmed to
+/// the client. Writes to fewer holders than that fail.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum WriteAckPolicy {
+    /// Any one copy.
+    One,
+    /// More than half of the copies.
+    Majority,
+    /// All the copies.
+    All,
+}
+
+impl Default for WriteAckPolicy {
+    fn default() -> Self {
+        Self::Majority
+    }
+}
+
+impl FromStr for WriteAckPolicy {
+    type Err = Error;
+
+    fn from_str(policy: &str) -> Result<Self> {
+        match policy {
+            "one" => Ok(Self::One),
+            "majority" => Ok(Self::Majority),
+            "all" => Ok(Self::All),
+            _ => Err(Error::Configuration(format!(
+                "Unknown write ack policy: {}",
+                policy
+            ))),
+        }
+    }
+}
+
 impl Config {
     /// Returns a new `Config` instance.  Tries to read from the default node config file location,
     /// and overrides values with any equivalent command line args.
@@ -236,6 +342,34 @@ impl Config {
         if let Some(upnp_lease_duration) = config.upnp_lease_duration {
             self.network_config.upnp_lease_duration = Some(upnp_lease_duration);
         }
+
+        if let Some(storage_backend) = config.storage_backend {
+            self.storage_backend = Some(storage_backend);
+        }
+
+        self.compress_chunks = config.compress_chunks || self.compress_chunks;
+
+        self.erasure_coding = config.erasure_coding || self.erasure_coding;
+
+        if let Some(write_ack_policy) = config.write_ack_policy {
+            self.write_ack_policy = Some(write_ack_policy);
+        }
+
+        if let Some(replication_bandwidth) = config.replication_bandwidth {
+            self.replication_bandwidth = Some(replication_bandwidth);
+        }
+
+        if let Some(replication_msg_rate) = config.replication_msg_rate {
+            self.replication_msg_rate = Some(replication_msg_rate);
+        }
+
+        if let Some(encryption_passphrase) = config.encryption_passphrase {
+            self.encryption_passphrase = Some(encryption_passphrase);
+        }
+
+        if let Some(encryption_key_file) = config.encryption_key_file {
+            self.encryption_key_file = Some(encryption_key_file);
+        }
     }
 
     /// The address to be credited when this node farms SafeCoin.
@@ -253,6 +387,48 @@ impl Config {
         self.max_capacity.unwrap_or(DEFAULT_MAX_CAPACITY)
     }
 
+    /// Storage backend for `ChunkStore`s.
+    pub fn storage_backend(&self) -> StorageBackend {
+        self.storage_backend.unwrap_or_default()
+    }
+
+    /// Compress chunks before storing them?
+    pub fn compress_chunks(&self) -> bool {
+        self.compress_chunks
+    }
+
+    /// Store blobs erasure-coded rather than as full copies?
+    pub fn erasure_coding(&self) -> bool {
+        self.erasure_coding
+    }
+
+    /// How many holders must store a blob before its write is confirmed to the client.
+    pub fn write_ack_policy(&self) -> WriteAckPolicy {
+        self.write_ack_policy.unwrap_or_default()
+    }
+
+    /// Bytes per second spent sending chunks to be republished after churn.
+    pub fn replication_bandwidth(&self) -> u64 {
+        self.replication_bandwidth
+            .unwrap_or(DEFAULT_REPLICATION_BANDWIDTH)
+    }
+
+    /// Chunks per second sent to be republished after churn.
+    pub fn replication_msg_rate(&self) -> u32 {
+        self.replication_msg_rate
+            .unwrap_or(DEFAULT_REPLICATION_MSG_RATE)
+    }
+
+    /// Passphrase to derive the key encrypting stored data from.
+    pub fn encryption_passphrase(&self) -> Option<&str> {
+        self.encryption_passphrase.as_deref()
+    }
+
+    /// File holding the key encrypting stored data.
+    pub fn encryption_key_file(&self) -> Option<&Path> {
+        self.encryption_key_file.as_deref()
+    }
+
     /// Root directory for `ChunkStore`s and cached state. If not set, it defaults to
     /// `DEFAULT_ROOT_DIR_NAME` within the project's data directory (see `Config::root_dir` for the
     /// directories on each platform).
@@ -428,7 +604,7 @@ fn smoke() {
     // NOTE: IF this value is being changed due to a change in the config,
     // the change in config also be handled in Config::merge()
     // and in examples/config_handling.rs
-    let expected_size = 504;
+    let expected_size = 576;
 
     assert_eq!(std::mem::size_of::<Config>(), expected_size);
 }
@@ -0,0 +1,297 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Encryption at rest of the chunks and transfers a node stores.
+//!
+//! An encrypted value is `MAGIC`, followed by a random nonce, followed by the AES-256-GCM
+//! ciphertext of the value and its tag. Values stored before encryption was enabled don't start
+//! with `MAGIC`, and are read back as they are.
+
+use crate::{Config, Error, Result};
+use hex::decode;
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+    pbkdf2,
+    rand::{SecureRandom, SystemRandom},
+};
+use std::{
+    borrow::Cow,
+    fmt::{self, Debug, Formatter},
+    fs,
+    num::NonZeroU32,
+    path::Path,
+};
+
+const MAGIC: [u8; 4] = *b"snce";
+const KEY_LEN: usize = 32;
+const SALT_LEN: usize = 16;
+const PBKDF2_ITERATIONS: u32 = 100_000;
+
+// Filename for storing the salt a passphrase is stretched with
+const SALT_FILENAME: &str = "encryption_salt";
+// Filename for storing a known value encrypted with the key, to tell a wrong key on startup
+const KEY_CHECK_FILENAME: &str = "encryption_key_check";
+const KEY_CHECK_VALUE: &[u8] = b"sn_node encryption key check";
+
+/// Key the node's stores are encrypted with.
+#[derive(Clone)]
+pub struct EncryptionKey([u8; KEY_LEN]);
+
+impl EncryptionKey {
+    /// Derives the key from a passphrase, stretched with the salt kept in `root_dir`. The salt is
+    /// created on first use.
+    pub fn from_passphrase(passphrase: &str, root_dir: &Path) -> Result<Self> {
+        let salt_path = root_dir.join(SALT_FILENAME);
+        let salt = if salt_path.is_file() {
+            fs::read(&salt_path)?
+        } else {
+            let salt = random_bytes(SALT_LEN)?;
+            fs::write(&salt_path, &salt)?;
+            salt
+        };
+
+        let iterations = NonZeroU32::new(PBKDF2_ITERATIONS)
+            .ok_or_else(|| Error::Logic("Zero PBKDF2 iterations".to_string()))?;
+        let mut key = [0; KEY_LEN];
+        pbkdf2::derive(
+            pbkdf2::PBKDF2_HMAC_SHA256,
+            iterations,
+            &salt,
+            passphrase.as_bytes(),
+            &mut key,
+        );
+        Ok(Self(key))
+    }
+
+    /// Reads a hex-encoded 256-bit key from the file at `path`.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let key_hex_bytes = fs::read(path)?;
+        let key_bytes = decode(String::from_utf8_lossy(&key_hex_bytes).trim()).map_err(|err| {
+            Error::Configuration(format!(
+                "Couldn't hex-decode encryption key in {}: {}",
+                path.display(),
+                err
+            ))
+        })?;
+        if key_bytes.len() != KEY_LEN {
+            return Err(Error::Configuration(format!(
+                "Encryption key in {} is {} bytes long, expected {}",
+                path.display(),
+                key_bytes.len(),
+                KEY_LEN
+            )));
+        }
+        let mut key = [0; KEY_LEN];
+        key.copy_from_slice(&key_bytes);
+        Ok(Self(key))
+    }
+
+    /// Encrypts `value` for storing.
+    pub fn encrypt(&self, value: &[u8]) -> Result<Vec<u8>> {
+        let nonce_bytes = random_bytes(NONCE_LEN)?;
+        let nonce = Nonce::try_assume_unique_for_key(&nonce_bytes)
+            .map_err(|_| Error::Encryption("Invalid nonce".to_string()))?;
+
+        let mut in_out = value.to_vec();
+        self.aead_key()?
+            .seal_in_place_append_tag(nonce, Aad::empty(), &mut in_out)
+            .map_err(|_| Error::Encryption("Couldn't encrypt value".to_string()))?;
+
+        let mut encrypted = Vec::with_capacity(MAGIC.len() + NONCE_LEN + in_out.len());
+        encrypted.extend_from_slice(&MAGIC);
+        encrypted.extend_from_slice(&nonce_bytes);
+        encrypted.extend_from_slice(&in_out);
+        Ok(encrypted)
+    }
+
+    /// Decrypts a stored `value`.
+    ///
+    /// Returns `Error::InvalidEncryptionKey` if it was encrypted with another key.
+    pub fn decrypt(&self, value: &[u8]) -> Result<Vec<u8>> {
+        if value.len() < MAGIC.len() + NONCE_LEN {
+            return Err(Error::Encryption(
+                "Encrypted value is truncated".to_string(),
+            ));
+        }
+        let (nonce_bytes, ciphertext) = value[MAGIC.len()..].split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce_bytes)
+            .map_err(|_| Error::Encryption("Invalid nonce".to_string()))?;
+
+        let mut in_out = ciphertext.to_vec();
+        let plaintext_len = self
+            .aead_key()?
+            .open_in_place(nonce, Aad::empty(), &mut in_out)
+            .map_err(|_| Error::InvalidEncryptionKey)?
+            .len();
+        in_out.truncate(plaintext_len);
+        Ok(in_out)
+    }
+
+    fn aead_key(&self) -> Result<LessSafeKey> {
+        let key = UnboundKey::new(&AES_256_GCM, &self.0)
+            .map_err(|_| Error::Encryption("Invalid key length".to_string()))?;
+        Ok(LessSafeKey::new(key))
+    }
+}
+
+impl Debug for EncryptionKey {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        write!(formatter, "EncryptionKey(..)")
+    }
+}
+
+/// Returns the key set by `config`, if any, having checked it against the one the data in
+/// `root_dir` was stored with.
+pub(crate) fn load_key(config: &Config, root_dir: &Path) -> Result<Option<EncryptionKey>> {
+    let key = match (config.encryption_passphrase(), config.encryption_key_file()) {
+        (Some(_), Some(_)) => {
+            return Err(Error::Configuration(
+                "Only one of --encryption-passphrase and --encryption-key-file can be passed"
+                    .to_string(),
+            ))
+        }
+        (Some(passphrase), None) => Some(EncryptionKey::from_passphrase(passphrase, root_dir)?),
+        (None, Some(path)) => Some(EncryptionKey::from_file(path)?),
+        (None, None) => None,
+    };
+    verify_key(key.as_ref(), root_dir)?;
+    Ok(key)
+}
+
+/// Checks `key` against the one the data in `root_dir` was stored with, recording it on first
+/// use.
+///
+/// Returns `Error::InvalidEncryptionKey` if the key is not that one, or if the data is encrypted
+/// and no key was given.
+pub(crate) fn verify_key(key: Option<&EncryptionKey>, root_dir: &Path) -> Result<()> {
+    let key_check_path = root_dir.join(KEY_CHECK_FILENAME);
+    match (key, key_check_path.is_file()) {
+        (Some(key), true) => {
+            if key.decrypt(&fs::read(&key_check_path)?)? == KEY_CHECK_VALUE {
+                Ok(())
+            } else {
+                Err(Error::InvalidEncryptionKey)
+            }
+        }
+        (Some(key), false) => {
+            fs::write(&key_check_path, key.encrypt(KEY_CHECK_VALUE)?)?;
+            Ok(())
+        }
+        (None, true) => Err(Error::InvalidEncryptionKey),
+        (None, false) => Ok(()),
+    }
+}
+
+/// Encrypts `value` for storing if there's a key, otherwise returns it as it is.
+pub(crate) fn encrypt<'a>(key: Option<&EncryptionKey>, value: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+    match key {
+        Some(key) => Ok(Cow::Owned(key.encrypt(value)?)),
+        None => Ok(Cow::Borrowed(value)),
+    }
+}
+
+/// Tells whether a stored `value` is encrypted.
+pub(crate) fn is_encrypted(value: &[u8]) -> bool {
+    value.starts_with(&MAGIC)
+}
+
+/// Returns the plaintext of a stored `value`, decrypting it if needed.
+///
+/// Returns `Error::InvalidEncryptionKey` if the value is encrypted and there's no key, or another
+/// one.
+pub(crate) fn decrypt<'a>(key: Option<&EncryptionKey>, value: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+    if !is_encrypted(value) {
+        return Ok(Cow::Borrowed(value));
+    }
+    match key {
+        Some(key) => Ok(Cow::Owned(key.decrypt(value)?)),
+        None => Err(Error::InvalidEncryptionKey),
+    }
+}
+
+fn random_bytes(len: usize) -> Result<Vec<u8>> {
+    let mut bytes = vec![0; len];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| Error::Encryption("Couldn't generate random bytes".to_string()))?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decrypt, encrypt, verify_key, EncryptionKey};
+    use crate::{Error, Result};
+    use std::fs;
+    use tempdir::TempDir;
+
+    #[test]
+    fn round_trip() -> Result<()> {
+        let root = TempDir::new("encryption")?;
+        let key = EncryptionKey::from_passphrase("passphrase", root.path())?;
+        let value = b"some value".to_vec();
+
+        let encrypted = encrypt(Some(&key), &value)?;
+        assert_ne!(&*encrypted, &value[..]);
+        assert_eq!(&*decrypt(Some(&key), &encrypted)?, &value[..]);
+
+        // Values stored before encryption was enabled are read as they are.
+        assert_eq!(&*decrypt(Some(&key), &value)?, &value[..]);
+
+        // The salt is kept, so the same passphrase gives the same key.
+        let same_key = EncryptionKey::from_passphrase("passphrase", root.path())?;
+        assert_eq!(&*decrypt(Some(&same_key), &encrypted)?, &value[..]);
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_wrong_key() -> Result<()> {
+        let root = TempDir::new("encryption")?;
+        let key = EncryptionKey::from_passphrase("passphrase", root.path())?;
+        let wrong_key = EncryptionKey::from_passphrase("wrong passphrase", root.path())?;
+        let encrypted = encrypt(Some(&key), b"some value")?;
+
+        assert!(matches!(
+            decrypt(Some(&wrong_key), &encrypted),
+            Err(Error::InvalidEncryptionKey)
+        ));
+        assert!(matches!(
+            decrypt(None, &encrypted),
+            Err(Error::InvalidEncryptionKey)
+        ));
+
+        verify_key(Some(&key), root.path())?;
+        verify_key(Some(&key), root.path())?;
+        assert!(matches!(
+            verify_key(Some(&wrong_key), root.path()),
+            Err(Error::InvalidEncryptionKey)
+        ));
+        assert!(matches!(
+            verify_key(None, root.path()),
+            Err(Error::InvalidEncryptionKey)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn reads_key_file() -> Result<()> {
+        let root = TempDir::new("encryption")?;
+        let path = root.path().join("key");
+
+        fs::write(&path, format!("{}\n", "ab".repeat(32)))?;
+        let key = EncryptionKey::from_file(&path)?;
+        let encrypted = key.encrypt(b"some value")?;
+        assert_eq!(key.decrypt(&encrypted)?, b"some value");
+
+        fs::write(&path, "ab".repeat(16))?;
+        assert!(matches!(
+            EncryptionKey::from_file(&path),
+            Err(Error::Configuration(_))
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,170 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Reed-Solomon erasure coding of blobs, as an alternative to storing full copies of them.
+//!
+//! A blob is split into `DATA_SHARDS` data shards and `PARITY_SHARDS` parity shards, any
+//! `DATA_SHARDS` of which rebuild it. Each shard is stored as a blob of its own, at a single
+//! holder, so a blob takes `(DATA_SHARDS + PARITY_SHARDS) / DATA_SHARDS` times its size to store
+//! instead of `CHUNK_COPY_COUNT` times.
+//!
+//! The value of a shard blob is `MAGIC`, the name of the blob it's a shard of, its index and then
+//! its bytes. The name of the blob makes shards of different blobs never share an address. A client
+//! can store a blob with the same bytes though, so whether a chunk is a shard is told from the shard
+//! maps Elders keep, and the marks they send the holders of shards, never from its bytes.
+
+use crate::{Error, Result};
+use reed_solomon_erasure::galois_8::ReedSolomon;
+use serde::{Deserialize, Serialize};
+use sn_data_types::{Blob, BlobAddress, PrivateBlob, PublicBlob, PublicKey};
+use xor_name::XOR_NAME_LEN;
+
+/// Number of shards a blob is split into, and needed to rebuild it.
+pub(crate) const DATA_SHARDS: usize = 4;
+
+/// Number of extra shards computed, and so of shards which can be lost without losing the blob.
+pub(crate) const PARITY_SHARDS: usize = 2;
+
+const MAGIC: [u8; 4] = *b"snsh";
+const HEADER_LEN: usize = MAGIC.len() + XOR_NAME_LEN + 1;
+
+/// Where the shards of a blob are stored, how long the blob is and who owns it, if it's private.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ShardMap {
+    /// Addresses of the shards, data shards first.
+    pub(crate) shards: Vec<BlobAddress>,
+    pub(crate) owner: Option<PublicKey>,
+    len: usize,
+}
+
+/// Splits `blob` into shards, which are private with the same owner if `blob` is private.
+pub(crate) fn encode(blob: &Blob) -> Result<(ShardMap, Vec<Blob>)> {
+    let value = blob.value();
+    let shard_len = std::cmp::max(1, (value.len() + DATA_SHARDS - 1) / DATA_SHARDS);
+    let mut shards = value
+        .chunks(shard_len)
+        .map(|bytes| bytes.to_vec())
+        .collect::<Vec<_>>();
+    shards.resize(DATA_SHARDS + PARITY_SHARDS, vec![]);
+    for shard in &mut shards {
+        shard.resize(shard_len, 0);
+    }
+    coder()?.encode(&mut shards).map_err(to_error)?;
+
+    let shards = shards
+        .into_iter()
+        .enumerate()
+        .map(|(index, bytes)| {
+            let mut value = Vec::with_capacity(HEADER_LEN + bytes.len());
+            value.extend_from_slice(&MAGIC);
+            value.extend_from_slice(&blob.name().0);
+            value.push(index as u8);
+            value.extend_from_slice(&bytes);
+            match blob.owner() {
+                Some(owner) => Blob::Private(PrivateBlob::new(value, *owner)),
+                None => Blob::Public(PublicBlob::new(value)),
+            }
+        })
+        .collect::<Vec<_>>();
+    let map = ShardMap {
+        shards: shards.iter().map(|shard| *shard.address()).collect(),
+        owner: blob.owner().copied(),
+        len: value.len(),
+    };
+    Ok((map, shards))
+}
+
+/// Rebuilds the blob at `address` from its shards, any `DATA_SHARDS` of which are needed.
+///
+/// `shards` are in the order of `map.shards`, with `None` for the ones missing.
+pub(crate) fn decode(
+    address: &BlobAddress,
+    map: &ShardMap,
+    shards: Vec<Option<Blob>>,
+) -> Result<Blob> {
+    let mut shards = shards
+        .into_iter()
+        .enumerate()
+        .map(|(index, shard)| {
+            shard
+                .filter(|shard| map.shards.get(index) == Some(shard.address()))
+                .map(|shard| shard.value()[HEADER_LEN..].to_vec())
+        })
+        .collect::<Vec<_>>();
+    coder()?.reconstruct_data(&mut shards).map_err(to_error)?;
+
+    let mut value = shards
+        .into_iter()
+        .take(DATA_SHARDS)
+        .flatten()
+        .flatten()
+        .collect::<Vec<_>>();
+    value.truncate(map.len);
+    let blob = match (address, map.owner) {
+        (BlobAddress::Private(_), Some(owner)) => Blob::Private(PrivateBlob::new(value, owner)),
+        (BlobAddress::Public(_), None) => Blob::Public(PublicBlob::new(value)),
+        _ => return Err(Error::Erasure(format!("Shards don't match {:?}", address))),
+    };
+    if blob.address() != address {
+        return Err(Error::Erasure(format!(
+            "Shards rebuilt a blob other than {:?}",
+            address
+        )));
+    }
+    Ok(blob)
+}
+
+fn coder() -> Result<ReedSolomon> {
+    ReedSolomon::new(DATA_SHARDS, PARITY_SHARDS).map_err(to_error)
+}
+
+fn to_error(error: reed_solomon_erasure::Error) -> Error {
+    Error::Erasure(error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, encode, DATA_SHARDS, PARITY_SHARDS};
+    use crate::{Error, Result};
+    use sn_data_types::{Blob, PublicBlob};
+
+    #[test]
+    fn any_data_shards_rebuild_the_blob() -> Result<()> {
+        let blob = Blob::Public(PublicBlob::new((0..1001).map(|i| i as u8).collect()));
+        let (map, shards) = encode(&blob)?;
+        assert_eq!(shards.len(), DATA_SHARDS + PARITY_SHARDS);
+
+        // Losing the first data shards leaves exactly enough to rebuild from.
+        let mut remaining = shards.into_iter().map(Some).collect::<Vec<_>>();
+        for shard in remaining.iter_mut().take(PARITY_SHARDS) {
+            *shard = None;
+        }
+        assert_eq!(decode(blob.address(), &map, remaining.clone())?, blob);
+
+        remaining[PARITY_SHARDS] = None;
+        assert!(matches!(
+            decode(blob.address(), &map, remaining),
+            Err(Error::Erasure(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn shards_of_different_blobs_differ() -> Result<()> {
+        // Both blobs split into identical shard bytes.
+        let (_, shards) = encode(&Blob::Public(PublicBlob::new(vec![0; 4])))?;
+        let (_, other_shards) = encode(&Blob::Public(PublicBlob::new(vec![0; 3])))?;
+
+        assert!(shards.iter().all(|shard| other_shards
+            .iter()
+            .all(|other| other.address() != shard.address())));
+
+        Ok(())
+    }
+}
@@ -57,6 +57,18 @@ pub enum Error {
     /// Key, Value pair not found in `ChunkStore`.
     #[error("No such chunk: {0:?}")]
     NoSuchChunk(DataAddress),
+    /// Chunk in `ChunkStore` doesn't match its checksum.
+    #[error("Chunk failed its checksum: {0:?}")]
+    ChunkCorrupted(DataAddress),
+    /// Stored data is encrypted with another key than the one given, or no key was given.
+    #[error("Wrong or missing encryption key for the stored data")]
+    InvalidEncryptionKey,
+    /// Encryption error.
+    #[error("Encryption error: {0}")]
+    Encryption(String),
+    /// Erasure coding error, or too few shards to rebuild a blob from.
+    #[error("Erasure coding error: {0}")]
+    Erasure(String),
     /// Unable to process fund churn message.
     #[error("Cannot process fund churn message")]
     NotChurningFunds,
@@ -135,6 +147,7 @@ pub(crate) fn convert_to_error_message(error: Error) -> Result<sn_messaging::cli
         Error::InvalidSignedTransfer(_) => Ok(ErrorMessage::InvalidSignature),
         Error::TransferAlreadyRegistered => Ok(ErrorMessage::TransactionIdExists),
         Error::NoSuchChunk(address) => Ok(ErrorMessage::DataNotFound(address)),
+        Error::ChunkCorrupted(address) => Ok(ErrorMessage::DataNotFound(address)),
         Error::NotEnoughSpace => Ok(ErrorMessage::NotEnoughSpace),
         Error::TempDirCreationFailed(_) => Ok(ErrorMessage::FailedToWriteFile),
         Error::DataExists => Ok(ErrorMessage::DataExists),
@@ -7,7 +7,8 @@
 // permissions and limitations relating to use of the SAFE Network Software.
 
 use super::{LazyError, Mapping, MsgContext};
-use crate::{node_ops::NodeDuty, Error};
+use crate::{node_ops::NodeDuty, storage_msg::StorageMsg, Error};
+use bytes::Bytes;
 use sn_messaging::{
     client::{
         Cmd, Message, NodeCmd, NodeEvent, NodeQuery, NodeRewardQuery, NodeSystemCmd,
@@ -153,6 +154,94 @@ pub fn map_node_msg(msg: Message, src: SrcLocation, dst: DstLocation) -> Mapping
     }
 }
 
+pub fn map_storage_msg(msg: StorageMsg, src: SrcLocation, content: Bytes) -> Mapping {
+    let op = match msg {
+        StorageMsg::ChallengeChunk { address, nonce } => NodeDuty::ProveChunk {
+            address,
+            nonce,
+            elder: src.name(),
+        },
+        StorageMsg::ChunkProof {
+            address,
+            nonce,
+            proof,
+        } => NodeDuty::RecordChunkProof {
+            address,
+            nonce,
+            proof,
+            src: src.name(),
+        },
+        StorageMsg::QueryChunkHeld { address } => NodeDuty::ReportChunkHeld {
+            address,
+            elder: src.name(),
+        },
+        StorageMsg::ChunkHeld { address, held } => NodeDuty::RecordChunkHeld {
+            address,
+            held,
+            src: src.name(),
+        },
+        StorageMsg::ChunkCorrupted { address } => NodeDuty::RestoreChunk {
+            address,
+            src: src.name(),
+        },
+        StorageMsg::StorageAvailable => NodeDuty::DecrementFullNodeCount {
+            node_name: src.name(),
+        },
+        StorageMsg::StorageLevel(level) => NodeDuty::RecordStorageLevel {
+            level,
+            src: src.name(),
+        },
+        StorageMsg::MarkShard { address } => NodeDuty::MarkShard {
+            address,
+            src: src.name(),
+        },
+        StorageMsg::HandoffComplete { address } => NodeDuty::CompleteHandoff {
+            address,
+            src: src.name(),
+        },
+        StorageMsg::ChunkMoved { address, from, to } => NodeDuty::RecordChunkMoved {
+            address,
+            from,
+            to,
+            src: src.name(),
+        },
+        StorageMsg::Placement {
+            msg_id,
+            address,
+            holders,
+        } => NodeDuty::RecordPlacement {
+            msg_id,
+            address,
+            holders,
+            src: src.name(),
+        },
+        StorageMsg::FullAdults(full_adults) => NodeDuty::UpdateFullAdults {
+            full_adults,
+            src: src.name(),
+        },
+        StorageMsg::ShardMaps(maps) => NodeDuty::UpdateShardMaps {
+            maps,
+            src: src.name(),
+        },
+        StorageMsg::ChunkHolders(holders) => NodeDuty::UpdateChunkHolders {
+            holders,
+            src: src.name(),
+        },
+        StorageMsg::Tombstones {
+            sequences,
+            registers,
+        } => NodeDuty::UpdateTombstones {
+            sequences,
+            registers,
+            src: src.name(),
+        },
+    };
+    Mapping::Ok {
+        op,
+        ctx: Some(MsgContext::Bytes { msg: content, src }),
+    }
+}
+
 fn match_or_err(msg: Message, src: SrcLocation) -> Mapping {
     match match_node_msg(msg.clone(), src) {
         NodeDuty::NoOp => Mapping::Error(LazyError {
@@ -272,6 +361,7 @@ fn match_node_msg(msg: Message, origin: SrcLocation) -> NodeDuty {
         } => NodeDuty::ProcessRepublish {
             chunk: data.clone(),
             msg_id: *id,
+            src: origin.name(),
         },
         // Aggregated by us, for security
         Message::NodeQuery {
@@ -9,9 +9,9 @@
 mod map_msg;
 
 use super::node_ops::NodeDuty;
-use crate::network::Network;
+use crate::{network::Network, storage_msg::StorageMsg};
 use log::{debug, error, info, trace};
-use map_msg::{map_node_msg, match_user_sent_msg};
+use map_msg::{map_node_msg, map_storage_msg, match_user_sent_msg};
 use sn_data_types::PublicKey;
 use sn_messaging::{client::Message, SrcLocation};
 use sn_routing::XorName;
@@ -46,6 +46,16 @@ pub async fn map_routing_event(event: RoutingEvent, network_api: &Network) -> Ma
         RoutingEvent::MessageReceived {
             content, src, dst, ..
         } => {
+            match StorageMsg::from_bytes(&content) {
+                Some(Ok(msg)) => return map_storage_msg(msg, src, content),
+                Some(Err(error)) => {
+                    return Mapping::Error(LazyError {
+                        msg: MsgContext::Bytes { msg: content, src },
+                        error,
+                    })
+                }
+                None => (),
+            }
             let msg = match Message::from(content.clone()) {
                 Ok(msg) => msg,
                 Err(error) => {
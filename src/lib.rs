@@ -34,6 +34,8 @@ mod chaos;
 mod chunk_store;
 mod chunks;
 mod config_handler;
+mod encryption;
+mod erasure;
 mod error;
 mod event_mapping;
 mod metadata;
@@ -41,6 +43,7 @@ mod network;
 mod node;
 mod node_ops;
 mod section_funds;
+mod storage_msg;
 mod to_db_key;
 mod transfers;
 
@@ -50,7 +53,9 @@ pub mod state_db;
 pub mod utils;
 
 pub use crate::{
-    config_handler::{add_connection_info, set_connection_info, Config},
+    config_handler::{
+        add_connection_info, set_connection_info, Config, StorageBackend, WriteAckPolicy,
+    },
     error::{Error, Result},
     node::Node,
 };
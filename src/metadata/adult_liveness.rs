@@ -12,17 +12,39 @@ use itertools::Itertools;
 use sn_data_types::BlobAddress;
 use sn_messaging::{EndUser, MessageId};
 use sn_routing::XorName;
-use std::collections::hash_map::Entry;
+use std::{
+    cmp::Ordering,
+    collections::hash_map::Entry,
+    iter,
+    time::{Duration, Instant},
+};
 
 const NEIGHBOUR_COUNT: usize = 2;
-const MIN_PENDING_OPS: usize = 10;
-const PENDING_OP_TOLERANCE_RATIO: f64 = 0.1;
+/// Storage challenges an Adult can fail in a row before it's deemed unreliable.
+const MAX_PROOF_FAILURES: usize = 3;
+/// How long the targets of an operation have to answer it before it times out.
+const OP_TIMEOUT: Duration = Duration::from_secs(30);
+/// Length of the windows Adults are scored over.
+const SCORE_WINDOW: Duration = Duration::from_secs(60);
+/// Weight of the latest response in the average latency of an Adult.
+const LATENCY_EWMA_WEIGHT: f64 = 0.2;
+/// Operations an Adult needs in a window for its success ratio to count.
+const MIN_WINDOW_OPS: usize = 10;
+/// Share of its operations in a window an Adult must succeed at.
+const MIN_SUCCESS_RATIO: f64 = 0.8;
+/// How many times slower than the slowest of its neighbours an Adult may answer.
+const LATENCY_TOLERANCE_RATIO: f64 = 5.0;
+/// Average latency, in seconds, below which an Adult is never too slow.
+const MIN_DEGRADED_LATENCY: f64 = 1.0;
+/// Windows in a row an Adult must be degraded in before it's deemed unresponsive.
+const DEGRADED_WINDOWS: usize = 3;
 
 #[derive(Clone, Debug)]
-enum Operation {
+pub enum Operation {
     Read {
         address: BlobAddress,
-        origin: EndUser,
+        // Set to None if read was initiated by the section
+        origin: Option<EndUser>,
         targets: BTreeSet<XorName>,
     },
     Write {
@@ -33,18 +55,116 @@ enum Operation {
     },
 }
 
+impl Operation {
+    fn targets(&self) -> &BTreeSet<XorName> {
+        match self {
+            Operation::Read { targets, .. } | Operation::Write { targets, .. } => targets,
+        }
+    }
+
+    fn targets_mut(&mut self) -> &mut BTreeSet<XorName> {
+        match self {
+            Operation::Read { targets, .. } | Operation::Write { targets, .. } => targets,
+        }
+    }
+}
+
+struct PendingOp {
+    operation: Operation,
+    sent: Instant,
+}
+
+impl PendingOp {
+    fn new(operation: Operation) -> Self {
+        Self {
+            operation,
+            sent: Instant::now(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Score {
+    // average latency of the answers, in seconds, timeouts counting as answers after `OP_TIMEOUT`
+    latency: Option<f64>,
+    // outcomes of the operations in the current window
+    successes: usize,
+    errors: usize,
+    timeouts: usize,
+    // windows in a row the Adult was degraded in
+    degraded_windows: usize,
+}
+
+impl Score {
+    // An answer with no outcome only counts towards the latency.
+    fn record_answer(&mut self, latency: Duration, succeeded: Option<bool>) {
+        match succeeded {
+            Some(true) => self.successes += 1,
+            Some(false) => self.errors += 1,
+            None => (),
+        }
+        self.record_latency(latency);
+    }
+
+    fn record_timeout(&mut self) {
+        self.timeouts += 1;
+        self.record_latency(OP_TIMEOUT);
+    }
+
+    fn record_latency(&mut self, latency: Duration) {
+        let latency = latency.as_secs_f64();
+        self.latency = Some(match self.latency {
+            Some(average) => average + LATENCY_EWMA_WEIGHT * (latency - average),
+            None => latency,
+        });
+    }
+
+    // None if there were too few operations in the window to tell.
+    fn success_ratio(&self) -> Option<f64> {
+        let ops = self.successes + self.errors + self.timeouts;
+        if ops < MIN_WINDOW_OPS {
+            return None;
+        }
+        Some(self.successes as f64 / ops as f64)
+    }
+
+    fn end_window(&mut self, degraded: bool) {
+        if degraded {
+            self.degraded_windows += 1;
+        } else {
+            self.degraded_windows = 0;
+        }
+        self.successes = 0;
+        self.errors = 0;
+        self.timeouts = 0;
+    }
+}
+
+/// An operation whose targets didn't all answer before its deadline.
+#[derive(Debug)]
+pub struct ExpiredOp {
+    pub msg_id: MessageId,
+    /// The operation, with the targets which didn't answer.
+    pub operation: Operation,
+}
+
 pub struct AdultLiveness {
-    ops: HashMap<MessageId, Operation>,
-    pending_ops: HashMap<XorName, usize>,
+    ops: HashMap<MessageId, PendingOp>,
+    scores: HashMap<XorName, Score>,
+    window_start: Instant,
     closest_adults: HashMap<XorName, Vec<XorName>>,
+    // storage challenges failed in a row
+    proof_failures: HashMap<XorName, usize>,
 }
 
 impl AdultLiveness {
     pub fn new() -> Self {
         Self {
             ops: HashMap::default(),
-            pending_ops: HashMap::default(),
+            scores: HashMap::default(),
+            window_start: Instant::now(),
             closest_adults: HashMap::default(),
+            proof_failures: HashMap::default(),
         }
     }
 
@@ -58,17 +178,17 @@ impl AdultLiveness {
         targets: BTreeSet<XorName>,
     ) -> bool {
         let new_operation = if let Entry::Vacant(entry) = self.ops.entry(msg_id) {
-            let _ = entry.insert(Operation::Write {
+            let _ = entry.insert(PendingOp::new(Operation::Write {
                 address,
                 origin,
                 targets: targets.clone(),
-            });
+            }));
             true
         } else {
             false
         };
         if new_operation {
-            self.increment_pending_op(&targets);
+            self.track(&targets);
         }
         new_operation
     }
@@ -79,59 +199,79 @@ impl AdultLiveness {
         &mut self,
         msg_id: MessageId,
         address: BlobAddress,
-        origin: EndUser,
+        origin: Option<EndUser>,
         targets: BTreeSet<XorName>,
     ) -> bool {
         let new_operation = if let Entry::Vacant(entry) = self.ops.entry(msg_id) {
-            let _ = entry.insert(Operation::Read {
+            let _ = entry.insert(PendingOp::new(Operation::Read {
                 address,
                 origin,
                 targets: targets.clone(),
-            });
+            }));
             true
         } else {
             false
         };
         if new_operation {
-            self.increment_pending_op(&targets);
+            self.track(&targets);
         }
         new_operation
     }
 
-    pub fn retain_members_only(&mut self, current_members: BTreeSet<XorName>) {
+    /// Stops tracking the Adults which left. Returns the writes which targeted some of them, with
+    /// each such target. Those writes are kept pending even with no targets left, for the caller
+    /// to either send them to other Adults, or give up on the targets with `remove_target`.
+    pub fn retain_members_only(
+        &mut self,
+        current_members: BTreeSet<XorName>,
+    ) -> Vec<(MessageId, XorName)> {
+        self.proof_failures
+            .retain(|name, _| current_members.contains(name));
+        let mut displaced = vec![];
         let old_members = self.closest_adults.keys().cloned().collect::<Vec<_>>();
         for name in old_members {
             if !current_members.contains(&name) {
-                let _ = self.pending_ops.remove(&name);
+                let _ = self.scores.remove(&name);
                 let _ = self.closest_adults.remove(&name);
                 let message_ids = self.ops.keys().cloned().collect::<Vec<_>>();
-                // TODO(after T4): For write operations perhaps we need to write it to a different Adult
                 for msg_id in message_ids {
-                    self.remove_target(msg_id, &name);
+                    let displaced_write = match self.ops.get_mut(&msg_id) {
+                        Some(PendingOp {
+                            operation: Operation::Write { targets, .. },
+                            ..
+                        }) => targets.remove(&name),
+                        _ => false,
+                    };
+                    if displaced_write {
+                        displaced.push((msg_id, name));
+                    } else {
+                        self.remove_target(msg_id, &name);
+                    }
                 }
             }
         }
         self.recompute_closest_adults();
+        displaced
+    }
+
+    /// Adds `adult` to the targets of the pending write `msg_id`, in place of one which left.
+    /// The deadline of the write restarts, as it's only sent to `adult` now.
+    pub fn add_target(&mut self, msg_id: MessageId, adult: XorName, now: Instant) -> bool {
+        let op = match self.ops.get_mut(&msg_id) {
+            Some(op) => op,
+            None => return false,
+        };
+        let _ = op.operation.targets_mut().insert(adult);
+        op.sent = now;
+        self.track(&iter::once(adult).collect());
+        true
     }
 
     pub fn remove_target(&mut self, msg_id: MessageId, name: &XorName) {
-        if let Some(count) = self.pending_ops.get_mut(name) {
-            let counter = *count;
-            if counter > 0 {
-                *count -= 1;
-            }
-        }
-        let complete = if let Some(operation) = self.ops.get_mut(&msg_id) {
-            match operation {
-                Operation::Read { targets, .. } => {
-                    let _ = targets.remove(name);
-                    targets.is_empty()
-                }
-                Operation::Write { targets, .. } => {
-                    let _ = targets.remove(name);
-                    targets.is_empty()
-                }
-            }
+        let complete = if let Some(op) = self.ops.get_mut(&msg_id) {
+            let targets = op.operation.targets_mut();
+            let _ = targets.remove(name);
+            targets.is_empty()
         } else {
             true
         };
@@ -140,13 +280,21 @@ impl AdultLiveness {
         }
     }
 
+    /// Whether the operation is still waiting on some of its targets.
+    pub fn is_pending(&self, msg_id: &MessageId) -> bool {
+        self.ops.contains_key(msg_id)
+    }
+
+    /// Records the answer of `src` to a write. `succeeded` is `None` for answers which tell
+    /// nothing of how reliable `src` is, such as having no copy to delete of a chunk it was never
+    /// recorded holding.
     pub fn record_adult_write_liveness(
         &mut self,
         correlation_id: MessageId,
         src: XorName,
+        succeeded: Option<bool>,
     ) -> Option<(BlobAddress, Option<EndUser>)> {
-        let op = self.ops.get(&correlation_id).cloned();
-        self.remove_target(correlation_id, &src);
+        let op = self.answer(correlation_id, src, succeeded, Instant::now());
         op.and_then(|op| match op {
             Operation::Write {
                 address, origin, ..
@@ -155,13 +303,15 @@ impl AdultLiveness {
         })
     }
 
+    /// Records the answer of `src` to a read. `succeeded` is `None` for answers which tell nothing
+    /// of how reliable `src` is, such as not finding a chunk it was never recorded holding.
     pub fn record_adult_read_liveness(
         &mut self,
         correlation_id: MessageId,
         src: XorName,
-    ) -> Option<(BlobAddress, EndUser)> {
-        let op = self.ops.get(&correlation_id).cloned();
-        self.remove_target(correlation_id, &src);
+        succeeded: Option<bool>,
+    ) -> Option<(BlobAddress, Option<EndUser>)> {
+        let op = self.answer(correlation_id, src, succeeded, Instant::now());
         op.and_then(|op| match op {
             Operation::Read {
                 address, origin, ..
@@ -170,9 +320,70 @@ impl AdultLiveness {
         })
     }
 
-    fn increment_pending_op(&mut self, targets: &BTreeSet<XorName>) {
+    // Records the answer of `src` to an operation at `now`, returning the operation if it was
+    // pending.
+    fn answer(
+        &mut self,
+        msg_id: MessageId,
+        src: XorName,
+        succeeded: Option<bool>,
+        now: Instant,
+    ) -> Option<Operation> {
+        let op = self.ops.get_mut(&msg_id)?;
+        if !op.operation.targets().contains(&src) {
+            return None;
+        }
+        let operation = op.operation.clone();
+        let latency = now.saturating_duration_since(op.sent);
+        self.scores
+            .entry(src)
+            .or_default()
+            .record_answer(latency, succeeded);
+        self.remove_target(msg_id, &src);
+        Some(operation)
+    }
+
+    /// Removes the operations past their deadline by `now`. The targets which didn't answer them
+    /// had an operation time out.
+    pub fn expire(&mut self, now: Instant) -> Vec<ExpiredOp> {
+        let expired_ids = self
+            .ops
+            .iter()
+            .filter(|(_, op)| op.sent + OP_TIMEOUT <= now)
+            .map(|(msg_id, _)| *msg_id)
+            .collect::<Vec<_>>();
+        let mut expired = Vec::with_capacity(expired_ids.len());
+        for msg_id in expired_ids {
+            let op = match self.ops.remove(&msg_id) {
+                Some(op) => op,
+                None => continue,
+            };
+            for target in op.operation.targets() {
+                self.scores.entry(*target).or_default().record_timeout();
+            }
+            expired.push(ExpiredOp {
+                msg_id,
+                operation: op.operation,
+            });
+        }
+        expired
+    }
+
+    /// Records whether `adult` passed a storage challenge. Passing one clears earlier failures.
+    /// Returns whether `adult` just failed too many challenges in a row, which it does once each
+    /// time, so it's not proposed offline over and over as it keeps failing.
+    pub fn record_proof(&mut self, adult: XorName, passed: bool) -> bool {
+        if passed {
+            let _ = self.proof_failures.remove(&adult);
+            return false;
+        }
+        let failures = self.proof_failures.entry(adult).or_insert(0);
+        *failures += 1;
+        *failures == MAX_PROOF_FAILURES
+    }
+
+    fn track(&mut self, targets: &BTreeSet<XorName>) {
         for node in targets {
-            *self.pending_ops.entry(*node).or_insert(0) += 1;
             if !self.closest_adults.contains_key(node) {
                 let _ = self.closest_adults.insert(*node, Vec::new());
                 self.recompute_closest_adults();
@@ -194,30 +405,277 @@ impl AdultLiveness {
         }
     }
 
-    pub fn find_unresponsive_adults(&self) -> Vec<(XorName, usize)> {
+    /// Ends the scoring window if it's over by `now`, returning the Adults which were just degraded
+    /// in their `DEGRADED_WINDOWS`th window in a row. An Adult is degraded in a window if it failed
+    /// too many of its operations in it, or answers much slower than its neighbours. Adults staying
+    /// degraded past that aren't returned again, until they recover and degrade anew.
+    pub fn find_unresponsive_adults(&mut self, now: Instant) -> Vec<XorName> {
+        if now < self.window_start + SCORE_WINDOW {
+            return Vec::new();
+        }
+        self.window_start = now;
+        let degraded = self
+            .scores
+            .keys()
+            .filter(|adult| self.is_degraded(adult))
+            .copied()
+            .collect::<HashSet<_>>();
         let mut unresponsive_adults = Vec::new();
-        for (adult, neighbours) in &self.closest_adults {
-            if let Some(max_pending_by_neighbours) = neighbours
-                .iter()
-                .map(|neighbour| self.pending_ops.get(neighbour).cloned().unwrap_or(0))
-                .max()
-            {
-                let adult_pending_ops = *self.pending_ops.get(adult).unwrap_or(&0);
-                if adult_pending_ops > MIN_PENDING_OPS
-                    && max_pending_by_neighbours > MIN_PENDING_OPS
-                    && adult_pending_ops as f64 * PENDING_OP_TOLERANCE_RATIO
-                        > max_pending_by_neighbours as f64
-                {
-                    log::info!(
-                        "Pending ops for {}: {} Neighbour max: {}",
-                        adult,
-                        adult_pending_ops,
-                        max_pending_by_neighbours
-                    );
-                    unresponsive_adults.push((*adult, adult_pending_ops));
-                }
+        for (adult, score) in &mut self.scores {
+            score.end_window(degraded.contains(adult));
+            if score.degraded_windows == DEGRADED_WINDOWS {
+                log::info!(
+                    "Adult {} degraded for {} windows in a row: {:?}",
+                    adult,
+                    score.degraded_windows,
+                    score
+                );
+                unresponsive_adults.push(*adult);
             }
         }
         unresponsive_adults
     }
+
+    /// `holders` ordered best first: those degraded in the fewest windows in a row, then the
+    /// fastest to answer. Holders not scored yet count as fast, so they get scored.
+    pub fn rank_holders(&self, mut holders: Vec<XorName>) -> Vec<XorName> {
+        let rank = |holder: &XorName| {
+            self.scores.get(holder).map_or((0, 0.0), |score| {
+                (score.degraded_windows, score.latency.unwrap_or(0.0))
+            })
+        };
+        holders.sort_by(|lhs, rhs| {
+            let (lhs, rhs) = (rank(lhs), rank(rhs));
+            lhs.0
+                .cmp(&rhs.0)
+                .then(lhs.1.partial_cmp(&rhs.1).unwrap_or(Ordering::Equal))
+        });
+        holders
+    }
+
+    fn is_degraded(&self, adult: &XorName) -> bool {
+        let score = match self.scores.get(adult) {
+            Some(score) => score,
+            None => return false,
+        };
+        if score
+            .success_ratio()
+            .map_or(false, |ratio| ratio < MIN_SUCCESS_RATIO)
+        {
+            return true;
+        }
+        let latency = match score.latency {
+            Some(latency) => latency,
+            None => return false,
+        };
+        let max_latency_by_neighbours = self
+            .closest_adults
+            .get(adult)
+            .into_iter()
+            .flatten()
+            .filter_map(|neighbour| self.scores.get(neighbour)?.latency)
+            .fold(None, |max: Option<f64>, latency| {
+                Some(max.map_or(latency, |max| max.max(latency)))
+            });
+        match max_latency_by_neighbours {
+            Some(max_latency) => {
+                latency > MIN_DEGRADED_LATENCY && latency > max_latency * LATENCY_TOLERANCE_RATIO
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        AdultLiveness, Operation, DEGRADED_WINDOWS, MAX_PROOF_FAILURES, MIN_WINDOW_OPS, OP_TIMEOUT,
+        SCORE_WINDOW,
+    };
+    use sn_data_types::{Blob, BlobAddress, PublicBlob};
+    use sn_messaging::MessageId;
+    use sn_routing::XorName;
+    use std::{
+        collections::BTreeSet,
+        iter,
+        time::{Duration, Instant},
+    };
+
+    fn address() -> BlobAddress {
+        *Blob::Public(PublicBlob::new(vec![1, 2, 3])).address()
+    }
+
+    // A window's worth of writes to all `adults`, which the `failing` ones fail, and the `slow`
+    // ones answer late.
+    fn write_to(
+        liveness: &mut AdultLiveness,
+        adults: &BTreeSet<XorName>,
+        failing: &[XorName],
+        slow: &[XorName],
+    ) {
+        for _ in 0..MIN_WINDOW_OPS {
+            let msg_id = MessageId::new();
+            assert!(liveness.new_write(msg_id, None, address(), adults.clone()));
+            let sent = liveness.ops[&msg_id].sent;
+            for adult in adults {
+                let latency = if slow.contains(adult) {
+                    Duration::from_secs(10)
+                } else {
+                    Duration::from_millis(10)
+                };
+                let succeeded = Some(!failing.contains(adult));
+                let _ = liveness.answer(msg_id, *adult, succeeded, sent + latency);
+            }
+        }
+    }
+
+    #[test]
+    fn operations_not_answered_in_time_expire() {
+        let targets = (0..3).map(|_| XorName::random()).collect::<BTreeSet<_>>();
+        let holders = targets.iter().copied().collect::<Vec<_>>();
+        let mut liveness = AdultLiveness::new();
+        let (read, write) = (MessageId::new(), MessageId::new());
+
+        assert!(liveness.new_read(read, address(), None, targets.clone()));
+        assert!(liveness.new_write(write, None, address(), targets.clone()));
+        let _ = liveness.record_adult_read_liveness(read, holders[0], Some(true));
+        assert!(liveness.expire(Instant::now()).is_empty());
+
+        let mut expired = liveness.expire(Instant::now() + OP_TIMEOUT);
+        expired.sort_by_key(|op| matches!(op.operation, Operation::Read { .. }));
+        assert_eq!(expired.len(), 2);
+        assert_eq!(expired[0].msg_id, write);
+        assert_eq!(expired[1].msg_id, read);
+        match &expired[1].operation {
+            Operation::Read { targets, .. } => {
+                assert_eq!(*targets, holders[1..].iter().copied().collect())
+            }
+            Operation::Write { .. } => panic!("expected a read"),
+        }
+        assert!(!liveness.is_pending(&read) && !liveness.is_pending(&write));
+
+        // Holders which didn't answer had the operations time out.
+        assert_eq!(liveness.scores[&holders[0]].timeouts, 1);
+        assert_eq!(liveness.scores[&holders[1]].timeouts, 2);
+        assert_eq!(liveness.scores[&holders[2]].timeouts, 2);
+    }
+
+    #[test]
+    fn writes_to_adults_which_left_are_kept_for_redirecting() {
+        let targets = (0..2).map(|_| XorName::random()).collect::<BTreeSet<_>>();
+        let holders = targets.iter().copied().collect::<Vec<_>>();
+        let mut liveness = AdultLiveness::new();
+        let (read, write) = (MessageId::new(), MessageId::new());
+        assert!(liveness.new_read(read, address(), None, targets.clone()));
+        assert!(liveness.new_write(write, None, address(), targets));
+
+        let remaining = iter::once(holders[1]).collect::<BTreeSet<_>>();
+        assert_eq!(
+            liveness.retain_members_only(remaining.clone()),
+            vec![(write, holders[0])]
+        );
+        assert!(liveness.is_pending(&read) && liveness.is_pending(&write));
+
+        // The write waits on its replacement target, and the remaining one.
+        let replacement = XorName::random();
+        assert!(liveness.add_target(write, replacement, Instant::now()));
+        let _ = liveness.record_adult_write_liveness(write, holders[1], Some(true));
+        assert!(liveness.is_pending(&write));
+        let _ = liveness.record_adult_write_liveness(write, replacement, Some(true));
+        assert!(!liveness.is_pending(&write));
+
+        // Given up on, a write without targets left ends.
+        let write = MessageId::new();
+        assert!(liveness.new_write(write, None, address(), remaining));
+        assert_eq!(
+            liveness.retain_members_only(BTreeSet::new()),
+            vec![(write, holders[1])]
+        );
+        liveness.remove_target(write, &holders[1]);
+        assert!(!liveness.is_pending(&write) && !liveness.is_pending(&read));
+    }
+
+    #[test]
+    fn only_adults_degraded_across_windows_are_unresponsive() {
+        let adults = (0..4).map(|_| XorName::random()).collect::<BTreeSet<_>>();
+        let bad = *adults.iter().next().expect("no adults");
+        let mut liveness = AdultLiveness::new();
+        let start = liveness.window_start;
+        let window = |n: usize| start + SCORE_WINDOW * n as u32;
+
+        // A single window failing operations isn't enough.
+        write_to(&mut liveness, &adults, &[bad], &[]);
+        assert!(liveness.find_unresponsive_adults(window(1)).is_empty());
+        write_to(&mut liveness, &adults, &[], &[]);
+        assert!(liveness.find_unresponsive_adults(window(2)).is_empty());
+
+        for n in 3..3 + DEGRADED_WINDOWS {
+            write_to(&mut liveness, &adults, &[bad], &[]);
+            // Windows are only scored once over.
+            let ending = window(n) - Duration::from_secs(1);
+            assert!(liveness.find_unresponsive_adults(ending).is_empty());
+            let unresponsive = liveness.find_unresponsive_adults(window(n));
+            if n < 2 + DEGRADED_WINDOWS {
+                assert!(unresponsive.is_empty());
+            } else {
+                assert_eq!(unresponsive, vec![bad]);
+            }
+        }
+        // Staying degraded, it's not proposed offline again.
+        write_to(&mut liveness, &adults, &[bad], &[]);
+        let unresponsive = liveness.find_unresponsive_adults(window(3 + DEGRADED_WINDOWS));
+        assert!(unresponsive.is_empty());
+
+        // Answering much slower than its neighbours degrades an Adult too.
+        let mut liveness = AdultLiveness::new();
+        liveness.window_start = start;
+        for n in 1..=DEGRADED_WINDOWS {
+            write_to(&mut liveness, &adults, &[], &[bad]);
+            let unresponsive = liveness.find_unresponsive_adults(window(n));
+            if n < DEGRADED_WINDOWS {
+                assert!(unresponsive.is_empty());
+            } else {
+                assert_eq!(unresponsive, vec![bad]);
+            }
+        }
+        let ranked = liveness.rank_holders(adults.iter().copied().collect());
+        assert_eq!(ranked.last(), Some(&bad));
+    }
+
+    #[test]
+    fn answers_without_outcome_only_count_towards_latency() {
+        let adult = XorName::random();
+        let targets = iter::once(adult).collect::<BTreeSet<_>>();
+        let mut liveness = AdultLiveness::new();
+        for _ in 0..MIN_WINDOW_OPS {
+            let read = MessageId::new();
+            assert!(liveness.new_read(read, address(), None, targets.clone()));
+            assert!(liveness
+                .record_adult_read_liveness(read, adult, None)
+                .is_some());
+        }
+        let score = &liveness.scores[&adult];
+        assert_eq!(score.success_ratio(), None);
+        assert!(score.latency.is_some());
+        assert!(!liveness.is_degraded(&adult));
+    }
+
+    #[test]
+    fn adults_failing_proofs_are_reported_once_per_failing_stretch() {
+        let adult = XorName::random();
+        let mut liveness = AdultLiveness::new();
+        for _ in 1..MAX_PROOF_FAILURES {
+            assert!(!liveness.record_proof(adult, false));
+        }
+        assert!(liveness.record_proof(adult, false));
+        // Failing on, it's not reported again.
+        assert!(!liveness.record_proof(adult, false));
+
+        // Passing a challenge starts a new stretch.
+        assert!(!liveness.record_proof(adult, true));
+        for _ in 1..MAX_PROOF_FAILURES {
+            assert!(!liveness.record_proof(adult, false));
+        }
+        assert!(liveness.record_proof(adult, false));
+    }
 }
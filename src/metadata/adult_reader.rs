@@ -1,8 +1,12 @@
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 
+use sn_data_types::PublicKey;
 use sn_routing::{Prefix, XorName};
 
-use crate::network::Network;
+use crate::{
+    capacity::{select_read_holders, select_write_holders, StorageLevel},
+    network::Network,
+};
 
 // Copyright 2021 MaidSafe.net limited.
 //
@@ -28,19 +32,49 @@ impl AdultReader {
         self.network.our_prefix().await
     }
 
-    /// Dynamic state
+    /// Our node's public key.
+    pub async fn our_key(&self) -> PublicKey {
+        PublicKey::from(self.network.public_key().await)
+    }
+
+    /// Our node's name.
+    pub async fn our_name(&self) -> XorName {
+        self.network.our_name().await
+    }
+
+    /// Names of our section's Elders.
+    pub async fn our_elder_names(&self) -> BTreeSet<XorName> {
+        self.network.our_elder_names().await
+    }
+
+    /// The Elder of our section closest to `name`.
+    pub async fn elder_closest_to(&self, name: &XorName) -> Option<XorName> {
+        self.network
+            .our_elder_names_sorted_by_distance_to(name, 1)
+            .await
+            .pop()
+    }
+
+    /// Adults to store new copies of data at `name`, the emptier ones among the closest.
     pub async fn non_full_adults_closest_to(
         &self,
         name: &XorName,
         full_adults: &BTreeSet<XorName>,
+        levels: &BTreeMap<XorName, StorageLevel>,
         count: usize,
     ) -> Vec<XorName> {
-        self.network
-            .our_adults_sorted_by_distance_to(name)
-            .await
-            .into_iter()
-            .filter(|name| !full_adults.contains(name))
-            .take(count)
-            .collect::<Vec<_>>()
+        let adults = self.network.our_adults().await;
+        select_write_holders(name, &adults, full_adults, levels, count)
+    }
+
+    /// Adults holding copies of data at `name`, full ones included.
+    pub async fn holders_closest_to(
+        &self,
+        name: &XorName,
+        full_adults: &BTreeSet<XorName>,
+        count: usize,
+    ) -> Vec<XorName> {
+        let adults = self.network.our_adults().await;
+        select_read_holders(name, &adults, full_adults, count)
     }
 }
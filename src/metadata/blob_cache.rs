@@ -0,0 +1,110 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! A small cache of public blobs at Elders.
+//!
+//! Public blobs never change nor go away, so a cached one answers reads for as long as it's kept.
+//! Popular blobs stay cached, sparing their few holders from being queried on every read.
+
+use sn_data_types::{Blob, BlobAddress};
+use std::collections::{HashMap, VecDeque};
+
+/// Bytes of blobs cached at most.
+pub(super) const BLOB_CACHE_CAPACITY: usize = 32 * 1024 * 1024;
+
+/// A size-bounded cache of public blobs, evicting the least recently used ones once full.
+pub(super) struct BlobCache {
+    capacity: usize,
+    size: usize,
+    blobs: HashMap<BlobAddress, Blob>,
+    // Cached blobs, least recently used first.
+    recency: VecDeque<BlobAddress>,
+}
+
+impl BlobCache {
+    pub(super) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            size: 0,
+            blobs: HashMap::new(),
+            recency: VecDeque::new(),
+        }
+    }
+
+    /// Returns the blob at `address`, if cached, marking it as the most recently used.
+    pub(super) fn get(&mut self, address: &BlobAddress) -> Option<Blob> {
+        let blob = self.blobs.get(address)?.clone();
+        self.touch(address);
+        Some(blob)
+    }
+
+    /// Caches `blob` if it's public, evicting the least recently used blobs to make room for it.
+    pub(super) fn insert(&mut self, blob: &Blob) {
+        let address = *blob.address();
+        let size = blob.value().len();
+        if !address.is_public() || size > self.capacity {
+            return;
+        }
+        if self.blobs.contains_key(&address) {
+            self.touch(&address);
+            return;
+        }
+        while self.size + size > self.capacity {
+            let evicted = match self.recency.pop_front() {
+                Some(evicted) => evicted,
+                None => break,
+            };
+            if let Some(evicted) = self.blobs.remove(&evicted) {
+                self.size -= evicted.value().len();
+            }
+        }
+        let _ = self.blobs.insert(address, blob.clone());
+        self.recency.push_back(address);
+        self.size += size;
+    }
+
+    fn touch(&mut self, address: &BlobAddress) {
+        self.recency.retain(|cached| cached != address);
+        self.recency.push_back(*address);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BlobCache;
+    use bls::SecretKey;
+    use sn_data_types::{Blob, PrivateBlob, PublicBlob, PublicKey};
+
+    fn blob(byte: u8) -> Blob {
+        Blob::Public(PublicBlob::new(vec![byte; 100]))
+    }
+
+    #[test]
+    fn least_recently_used_blobs_are_evicted() {
+        let mut cache = BlobCache::new(250);
+        let (first, second, third) = (blob(1), blob(2), blob(3));
+        cache.insert(&first);
+        cache.insert(&second);
+        assert_eq!(cache.get(first.address()), Some(first.clone()));
+
+        // The second blob was used last longer ago.
+        cache.insert(&third);
+        assert_eq!(cache.get(second.address()), None);
+        assert_eq!(cache.get(first.address()), Some(first));
+        assert_eq!(cache.get(third.address()), Some(third));
+
+        // Private blobs, and blobs larger than the cache, aren't cached.
+        let owner = PublicKey::from(SecretKey::random().public_key());
+        let private = Blob::Private(PrivateBlob::new(vec![4; 100], owner));
+        cache.insert(&private);
+        assert_eq!(cache.get(private.address()), None);
+        let large = Blob::Public(PublicBlob::new(vec![5; 300]));
+        cache.insert(&large);
+        assert_eq!(cache.get(large.address()), None);
+    }
+}
@@ -7,48 +7,137 @@
 // permissions and limitations relating to use of the SAFE Network Software.
 
 use crate::{
-    capacity::AdultsStorageInfo,
+    capacity::{AdultsStorageInfo, StorageLevel},
+    erasure::{self, ShardMap},
     error::convert_to_error_message,
     node_ops::{NodeDuties, NodeDuty, OutgoingMsg},
-    Error, Result,
+    storage_msg::{ChunkProof, Nonce, StorageMsg},
+    Error, Result, WriteAckPolicy,
 };
 use log::{debug, error, info, warn};
-use sn_data_types::{Blob, BlobAddress, PublicKey};
+use sn_data_types::{Blob, BlobAddress, DataAddress, PublicKey};
 use sn_messaging::{
     client::{
-        BlobDataExchange, BlobRead, BlobWrite, CmdError, Message, NodeCmd, NodeQuery,
-        NodeSystemCmd, QueryResponse,
+        BlobDataExchange, BlobRead, BlobWrite, CmdError, Error as ErrorMessage, Message, NodeCmd,
+        NodeEvent, NodeQuery, NodeSystemCmd, QueryResponse,
     },
     Aggregation, DstLocation, EndUser, MessageId,
 };
 use sn_routing::Prefix;
 
 use std::{
-    collections::BTreeSet,
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet, HashMap},
     fmt::{self, Display, Formatter},
+    iter,
+    path::Path,
+    time::Instant,
 };
 use xor_name::XorName;
 
-use super::adult_liveness::AdultLiveness;
+use super::adult_liveness::{AdultLiveness, ExpiredOp, Operation};
 use super::adult_reader::AdultReader;
+use super::blob_cache::{BlobCache, BLOB_CACHE_CAPACITY};
+use super::blob_shards::{shard_msg_id, BlobShards};
+use super::chunk_holders::ChunkHolders;
+use super::hedged_reads::{Attempt, HedgedReads, ReadStep};
+use super::placements::{Placement, Placements};
+use super::read_coalescing::CoalescedReads;
+use super::rebalancing::{self, Move, Rebalancing, MAX_MOVES};
+use super::redundancy_audit::{AuditResult, RedundancyAudit, AUDITS_PER_ROUND};
+use super::storage_challenges::{StorageChallenges, Verdict};
+use super::write_acks::{WriteAcks, WriteOutcome};
 
 // The number of separate copies of a blob chunk which should be maintained.
 pub(crate) const CHUNK_COPY_COUNT: usize = 4;
 
+// The number of copies of each shard of an erasure-coded blob. Redundancy comes from the parity
+// shards instead.
+pub(crate) const SHARD_COPY_COUNT: usize = 1;
+
+// A chunk republished for the Adults handing it off, confirmed to them once all its new holders
+// stored it.
+struct Handoff {
+    address: BlobAddress,
+    sources: BTreeSet<XorName>,
+    failed: bool,
+}
+
+// A write of a new chunk, kept until all its holders answered, so it can be sent to other holders
+// in place of those leaving the section meanwhile.
+struct InFlightWrite {
+    address: BlobAddress,
+    copy_count: usize,
+    // holders the chunk was sent to so far
+    holders: BTreeSet<XorName>,
+    msg: Message,
+    aggregation: Aggregation,
+}
+
+// How the holders of a new blob are picked.
+enum Placing {
+    // by us, the Elder closest to the blob, with the storage levels we heard of
+    Leading,
+    // by the Elder closest to the blob, which told us
+    Following(Placement),
+    // on the state all Elders agree on alone, the Elder closest to the blob not telling us in time
+    Agreed,
+}
+
 /// Operations over the data type Blob.
 pub(super) struct BlobRecords {
     adult_storage_info: AdultsStorageInfo,
     reader: AdultReader,
     adult_liveness: AdultLiveness,
+    challenges: StorageChallenges,
+    audit: RedundancyAudit,
+    hedged: HedgedReads,
+    coalesced: CoalescedReads,
+    cache: BlobCache,
+    acks: WriteAcks,
+    shards: BlobShards,
+    chunk_holders: ChunkHolders,
+    placements: Placements,
+    // handoffs by the id of their republish
+    handoffs: HashMap<MessageId, Handoff>,
+    in_flight: HashMap<MessageId, InFlightWrite>,
+    // Adults recorded holding the chunks being deleted, by the id of their delete
+    deleting: HashMap<MessageId, BTreeSet<XorName>>,
+    rebalancing: Rebalancing,
+    // holders chunks are restored at, by the id of the read of the chunk from its other holders
+    restorations: HashMap<MessageId, XorName>,
+    // Whether new blobs are stored erasure-coded. Blobs stored otherwise before are still read.
+    erasure_coding: bool,
 }
 
 impl BlobRecords {
-    pub(super) fn new(adult_storage_info: AdultsStorageInfo, reader: AdultReader) -> Self {
-        Self {
+    pub(super) fn new(
+        path: &Path,
+        adult_storage_info: AdultsStorageInfo,
+        reader: AdultReader,
+        erasure_coding: bool,
+        write_ack_policy: WriteAckPolicy,
+    ) -> Result<Self> {
+        Ok(Self {
             adult_storage_info,
             reader,
             adult_liveness: AdultLiveness::new(),
-        }
+            challenges: StorageChallenges::new(),
+            audit: RedundancyAudit::new(),
+            hedged: HedgedReads::new(),
+            coalesced: CoalescedReads::new(),
+            cache: BlobCache::new(BLOB_CACHE_CAPACITY),
+            acks: WriteAcks::new(write_ack_policy),
+            shards: BlobShards::new(path)?,
+            chunk_holders: ChunkHolders::new(path)?,
+            placements: Placements::new(),
+            handoffs: HashMap::new(),
+            in_flight: HashMap::new(),
+            deleting: HashMap::new(),
+            rebalancing: Rebalancing::new(),
+            restorations: HashMap::new(),
+            erasure_coding,
+        })
     }
 
     pub async fn get_data_of(&self, prefix: Prefix) -> BlobDataExchange {
@@ -80,7 +169,8 @@ impl BlobRecords {
 
     /// Registered holders not present in provided list of members
     /// will be removed from adult_storage_info and no longer tracked for liveness.
-    pub async fn retain_members_only(&mut self, members: BTreeSet<XorName>) -> Result<()> {
+    /// Writes still waiting on some of them are sent to other holders instead.
+    pub async fn retain_members_only(&mut self, members: BTreeSet<XorName>) -> Result<NodeDuties> {
         // full adults
         let mut full_adults = self.adult_storage_info.full_adults.write().await;
         let absent_adults = full_adults
@@ -92,11 +182,98 @@ impl BlobRecords {
         for adult in &absent_adults {
             let _ = full_adults.remove(adult);
         }
+        drop(full_adults);
+        self.adult_storage_info
+            .levels
+            .write()
+            .await
+            .retain(|adult, _| members.contains(adult));
 
         // stop tracking liveness of absent holders
-        self.adult_liveness.retain_members_only(members);
+        self.challenges.retain_members_only(&members);
+        self.chunk_holders.retain_members_only(&members)?;
+        self.audit.retain_members_only(&members);
+        self.rebalancing.retain_members_only(&members);
+        let mut duties = vec![];
+        for (msg_id, departed) in self.adult_liveness.retain_members_only(members) {
+            duties.extend(self.redirect_write(msg_id, departed).await);
+        }
+        // Handoffs which couldn't be sent to other holders never will be. The Adults handing them
+        // off retry, and they're republished then.
+        let adult_liveness = &self.adult_liveness;
+        self.handoffs
+            .retain(|msg_id, _| adult_liveness.is_pending(msg_id));
+        self.deleting
+            .retain(|msg_id, _| adult_liveness.is_pending(msg_id));
 
-        Ok(())
+        Ok(duties)
+    }
+
+    // Sends the write `msg_id` to another holder in place of `departed`, which left the section
+    // before storing the chunk. Without another holder, `departed` counts as having failed it.
+    async fn redirect_write(&mut self, msg_id: MessageId, departed: XorName) -> NodeDuties {
+        let replacement = match self.in_flight.get(&msg_id) {
+            Some(write) => self
+                .get_agreed_holders(write.address.name(), write.copy_count)
+                .await
+                .into_iter()
+                .find(|holder| !write.holders.contains(holder)),
+            None => None,
+        };
+        let now = Instant::now();
+        if let Some(holder) = replacement {
+            if let Some(write) = self.in_flight.get_mut(&msg_id) {
+                if self.adult_liveness.add_target(msg_id, holder, now) {
+                    info!(
+                        "Redirecting write {:?} from {:?}, which left, to {:?}",
+                        msg_id, departed, holder
+                    );
+                    let _ = write.holders.insert(holder);
+                    let address = write.address;
+                    let targets = iter::once(holder).collect::<BTreeSet<_>>();
+                    let send = NodeDuty::SendToNodes {
+                        targets: targets.clone(),
+                        msg: write.msg.clone(),
+                        aggregation: write.aggregation,
+                    };
+                    self.challenges.add_holder(&address, holder);
+                    if !self.shards.is_shard(&address) {
+                        if let Err(error) = self.chunk_holders.add(&address, &targets) {
+                            warn!("Could not record holder of {:?}: {:?}", address, error);
+                        }
+                    }
+                    let mut duties = self
+                        .mark_shard(address, &targets)
+                        .into_iter()
+                        .collect::<Vec<_>>();
+                    duties.push(send);
+                    return duties;
+                }
+            }
+        }
+
+        warn!(
+            "No holder to redirect write {:?} to, in place of {:?}",
+            msg_id, departed
+        );
+        self.adult_liveness.remove_target(msg_id, &departed);
+        // Shard writes have a single holder, so they're never still pending here.
+        let client_msg_id = if self.adult_liveness.is_pending(&msg_id) {
+            msg_id
+        } else {
+            let _ = self.in_flight.remove(&msg_id);
+            self.shards.end_write(&msg_id).unwrap_or(msg_id)
+        };
+        match self.acks.record(&client_msg_id, false) {
+            Some(WriteOutcome::Failed(end_user)) => {
+                let error = CmdError::Data(ErrorMessage::InvalidOperation(format!(
+                    "Too few holders left to store the chunk of write {:?}",
+                    client_msg_id
+                )));
+                vec![Self::write_failed(client_msg_id, end_user, error)]
+            }
+            Some(WriteOutcome::Stored(_)) | None => vec![],
+        }
     }
 
     pub(super) async fn write(
@@ -104,7 +281,7 @@ impl BlobRecords {
         write: BlobWrite,
         msg_id: MessageId,
         origin: EndUser,
-    ) -> Result<NodeDuty> {
+    ) -> Result<NodeDuties> {
         use BlobWrite::*;
         match write {
             New(data) => self.store(data, msg_id, origin).await,
@@ -126,8 +303,7 @@ impl BlobRecords {
     }
 
     /// Removes a given node from the list of full nodes.
-    #[allow(unused)] // TODO: Remove node from full list at 50% ?
-    async fn decrease_full_node_count_if_present(&mut self, node_name: XorName) -> Result<()> {
+    pub async fn decrease_full_node_count_if_present(&mut self, node_name: XorName) -> Result<()> {
         info!("No. of Full Nodes: {:?}", self.full_nodes().await);
         info!("Checking if {:?} is present as full_node", node_name);
         match self
@@ -148,6 +324,21 @@ impl BlobRecords {
         }
     }
 
+    /// Records how much storage an Adult uses, emptier Adults being preferred as holders.
+    pub(super) async fn record_storage_level(&self, adult: XorName, level: StorageLevel) {
+        let _ = self
+            .adult_storage_info
+            .levels
+            .write()
+            .await
+            .insert(adult, level);
+    }
+
+    /// Adults reported full, which aren't picked as holders.
+    pub(super) async fn full_adults(&self) -> BTreeSet<XorName> {
+        self.adult_storage_info.full_adults.read().await.clone()
+    }
+
     /// Number of full chunk storing nodes in the section.
     async fn full_nodes(&self) -> u8 {
         self.adult_storage_info.full_adults.read().await.len() as u8
@@ -156,16 +347,10 @@ impl BlobRecords {
     async fn send_chunks_to_adults(
         &mut self,
         data: Blob,
+        target_holders: BTreeSet<XorName>,
         msg_id: MessageId,
         origin: EndUser,
     ) -> Result<NodeDuty> {
-        let target_holders = self
-            .get_holders_for_chunk(data.name())
-            .await
-            .iter()
-            .cloned()
-            .collect::<BTreeSet<_>>();
-
         info!("Storing {} copies of the data", target_holders.len());
 
         if target_holders.is_empty() {
@@ -179,6 +364,8 @@ impl BlobRecords {
         }
 
         let blob_address = *data.address();
+        self.challenges.add_chunk(&data, &target_holders);
+        self.chunk_holders.add(&blob_address, &target_holders)?;
         let blob_write = BlobWrite::New(data);
 
         if self.adult_liveness.new_write(
@@ -187,17 +374,22 @@ impl BlobRecords {
             blob_address,
             target_holders.clone(),
         ) {
-            Ok(NodeDuty::SendToNodes {
-                targets: target_holders,
-                msg: Message::NodeCmd {
+            self.acks
+                .start(msg_id, origin, CHUNK_COPY_COUNT, target_holders.len(), 1);
+            Ok(self.send_write(
+                msg_id,
+                blob_address,
+                CHUNK_COPY_COUNT,
+                target_holders,
+                Message::NodeCmd {
                     cmd: NodeCmd::Chunks {
                         cmd: blob_write,
                         origin,
                     },
                     id: msg_id,
                 },
-                aggregation: Aggregation::AtDestination,
-            })
+                Aggregation::AtDestination,
+            ))
         } else {
             info!(
                 "Operation with MessageId {:?} is already in progress",
@@ -207,12 +399,224 @@ impl BlobRecords {
         }
     }
 
-    async fn store(&mut self, data: Blob, msg_id: MessageId, origin: EndUser) -> Result<NodeDuty> {
+    // Shards are written under ids of their own, with a single holder each. All shards are checked
+    // to have holders before any write is registered, for a blob never to be left partly written.
+    async fn send_shards_to_adults(
+        &mut self,
+        address: BlobAddress,
+        map: ShardMap,
+        shards: Vec<Blob>,
+        placement: Placement,
+        msg_id: MessageId,
+        origin: EndUser,
+    ) -> Result<NodeDuties> {
+        let mut placements = Vec::with_capacity(shards.len());
+        for (index, (shard, target_holders)) in shards.into_iter().zip(placement).enumerate() {
+            if target_holders.is_empty() {
+                return Ok(vec![
+                    self.send_error(
+                        Error::NoAdults(self.reader.our_prefix().await),
+                        msg_id,
+                        origin,
+                    )
+                    .await?,
+                ]);
+            }
+            placements.push((shard_msg_id(msg_id, index)?, shard, target_holders));
+        }
+        self.shards.insert_map(&address, &map)?;
+
+        let mut registered = vec![];
+        for (shard_msg_id, shard, target_holders) in &placements {
+            if !self.adult_liveness.new_write(
+                *shard_msg_id,
+                Some(origin),
+                *shard.address(),
+                target_holders.clone(),
+            ) {
+                info!(
+                    "Operation with MessageId {:?} is already in progress",
+                    msg_id
+                );
+                // The writes of the shards registered so far are dropped along with it.
+                for (shard_msg_id, target_holders) in registered {
+                    for holder in target_holders {
+                        self.adult_liveness.remove_target(shard_msg_id, holder);
+                    }
+                    let _ = self.shards.end_write(&shard_msg_id);
+                }
+                return Ok(vec![]);
+            }
+            self.shards.start_write(*shard_msg_id, msg_id);
+            registered.push((*shard_msg_id, target_holders));
+        }
+
+        let mut duties = vec![];
+        for (shard_msg_id, shard, target_holders) in placements {
+            let shard_address = *shard.address();
+            // Holders can't tell a shard from its content, which the client chose.
+            duties.push(NodeDuty::SendStorageMsg {
+                msg: StorageMsg::MarkShard {
+                    address: shard_address,
+                },
+                targets: target_holders.clone(),
+            });
+            duties.push(self.send_write(
+                shard_msg_id,
+                shard_address,
+                SHARD_COPY_COUNT,
+                target_holders,
+                Message::NodeCmd {
+                    cmd: NodeCmd::Chunks {
+                        cmd: BlobWrite::New(shard),
+                        origin,
+                    },
+                    id: shard_msg_id,
+                },
+                Aggregation::AtDestination,
+            ));
+        }
+
+        info!("Storing {:?} as {} shards", address, map.shards.len());
+        // Fewer shards than it takes to rebuild the blob don't make it durable.
+        let shard_count = map.shards.len();
+        self.acks.start(
+            msg_id,
+            origin,
+            shard_count,
+            shard_count,
+            erasure::DATA_SHARDS,
+        );
+        Ok(duties)
+    }
+
+    async fn store(
+        &mut self,
+        data: Blob,
+        msg_id: MessageId,
+        origin: EndUser,
+    ) -> Result<NodeDuties> {
         if let Err(error) = validate_data_owner(&data, &origin) {
-            return self.send_error(error, msg_id, origin).await;
+            return Ok(vec![self.send_error(error, msg_id, origin).await?]);
+        }
+
+        let our_name = self.reader.our_name().await;
+        match self.reader.elder_closest_to(data.name()).await {
+            Some(leader) if leader != our_name => {
+                match self.placements.hold(msg_id, data, origin, Instant::now()) {
+                    Some((data, origin, placement)) => {
+                        self.store_placed(data, msg_id, origin, Placing::Following(placement))
+                            .await
+                    }
+                    None => Ok(vec![]),
+                }
+            }
+            _ => {
+                self.store_placed(data, msg_id, origin, Placing::Leading)
+                    .await
+            }
+        }
+    }
+
+    /// Stores the blob of the write `msg_id` at the holders `src`, the Elder closest to it, picked.
+    pub(super) async fn record_placement(
+        &mut self,
+        msg_id: MessageId,
+        address: BlobAddress,
+        placement: Placement,
+        src: XorName,
+    ) -> Result<NodeDuties> {
+        if self.reader.elder_closest_to(address.name()).await != Some(src) {
+            warn!(
+                "Holders of {:?} picked by {}, which is not the Elder closest to it",
+                address, src
+            );
+            return Ok(vec![]);
         }
+        match self
+            .placements
+            .place(msg_id, address, placement, Instant::now())
+        {
+            Some((data, origin, placement)) => {
+                self.store_placed(data, msg_id, origin, Placing::Following(placement))
+                    .await
+            }
+            None => Ok(vec![]),
+        }
+    }
+
+    async fn store_placed(
+        &mut self,
+        data: Blob,
+        msg_id: MessageId,
+        origin: EndUser,
+        placing: Placing,
+    ) -> Result<NodeDuties> {
+        let address = *data.address();
+        let (map, mut pieces, copy_count) = if self.erasure_coding {
+            let (map, shards) = erasure::encode(&data)?;
+            (Some(map), shards, SHARD_COPY_COUNT)
+        } else {
+            (None, vec![data], CHUNK_COPY_COUNT)
+        };
 
-        self.send_chunks_to_adults(data, msg_id, origin).await
+        let mut duties = vec![];
+        let mut placement = match placing {
+            Placing::Leading => {
+                let placement = self.place(&pieces, copy_count, true).await;
+                let mut targets = self.reader.our_elder_names().await;
+                let _ = targets.remove(&self.reader.our_name().await);
+                duties.push(NodeDuty::SendStorageMsg {
+                    msg: StorageMsg::Placement {
+                        msg_id,
+                        address,
+                        holders: placement.clone(),
+                    },
+                    targets,
+                });
+                placement
+            }
+            Placing::Following(placement) if placement.len() == pieces.len() => placement,
+            Placing::Following(_) => {
+                warn!(
+                    "Holders picked for {:?} don't match its pieces, picking them on agreed state",
+                    address
+                );
+                self.place(&pieces, copy_count, false).await
+            }
+            Placing::Agreed => self.place(&pieces, copy_count, false).await,
+        };
+
+        match map {
+            Some(map) => duties.extend(
+                self.send_shards_to_adults(address, map, pieces, placement, msg_id, origin)
+                    .await?,
+            ),
+            None => {
+                if let (Some(data), Some(holders)) = (pieces.pop(), placement.pop()) {
+                    duties.push(
+                        self.send_chunks_to_adults(data, holders, msg_id, origin)
+                            .await?,
+                    );
+                }
+            }
+        }
+        Ok(duties)
+    }
+
+    // Picks the holders of each piece of a new blob, preferring the emptier Adults as per the
+    // storage levels we heard of if `with_levels`.
+    async fn place(&self, pieces: &[Blob], copy_count: usize, with_levels: bool) -> Placement {
+        let mut placement = Vec::with_capacity(pieces.len());
+        for piece in pieces {
+            let holders = if with_levels {
+                self.get_holders(piece.name(), copy_count).await
+            } else {
+                self.get_agreed_holders(piece.name(), copy_count).await
+            };
+            placement.push(holders.into_iter().collect());
+        }
+        placement
     }
 
     pub async fn record_adult_write_liveness(
@@ -222,43 +626,71 @@ impl BlobRecords {
         src: XorName,
     ) -> NodeDuties {
         let mut duties = vec![];
-        if let Some((address, origin)) = self
-            .adult_liveness
-            .record_adult_write_liveness(correlation_id, src)
+        let succeeded = result.is_ok();
+        // Deletes go to every candidate holder. One with no copy of a chunk it was never recorded
+        // holding says nothing of itself or of the delete, while a recorded holder having lost its
+        // copy is failing, though the chunk is gone from it all the same.
+        let (liveness, acked) = match &result {
+            Err(CmdError::Data(ErrorMessage::DataNotFound(DataAddress::Blob(_)))) => {
+                let recorded = self
+                    .deleting
+                    .get(&correlation_id)
+                    .map_or(false, |holders| holders.contains(&src));
+                if recorded {
+                    (Some(false), Some(true))
+                } else {
+                    (None, None)
+                }
+            }
+            _ => (Some(succeeded), Some(succeeded)),
+        };
+        if let Some((address, origin)) =
+            self.adult_liveness
+                .record_adult_write_liveness(correlation_id, src, liveness)
         {
-            if let Err(error) = result {
-                error!("Error at Adult while performing a BlobWrite: {:?}", &error);
-                // Depending on error, we might have to take action here.
-                if let Some(end_user) = origin {
-                    duties.push(NodeDuty::Send(OutgoingMsg {
-                        msg: Message::CmdError {
-                            error,
-                            id: MessageId::in_response_to(&correlation_id),
-                            correlation_id,
-                        },
-                        dst: DstLocation::EndUser(end_user),
-                        section_source: false,
-                        aggregation: Aggregation::AtDestination,
-                    }))
+            if !self.adult_liveness.is_pending(&correlation_id) {
+                let _ = self.in_flight.remove(&correlation_id);
+                let _ = self.deleting.remove(&correlation_id);
+            }
+            // Shards are written under ids of their own, which the client doesn't know.
+            let correlation_id = self
+                .shards
+                .end_write(&correlation_id)
+                .unwrap_or(correlation_id);
+            match &result {
+                Err(error) => {
+                    error!("Error at Adult while performing a BlobWrite: {:?}", error)
                 }
-            } else {
-                info!(
+                Ok(()) => info!(
                     "AdultWrite operation at {:?} MessageId {:?} at {:?} was successful",
                     address, correlation_id, src
-                );
+                ),
+            }
+            // Writes by the node itself, like replication, aren't confirmed to anyone.
+            if origin.is_some() {
+                let outcome = match acked {
+                    Some(stored) => self.acks.record(&correlation_id, stored),
+                    None => self.acks.record_skipped(&correlation_id),
+                };
+                match outcome {
+                    Some(WriteOutcome::Stored(end_user)) => {
+                        duties.push(Self::write_stored(correlation_id, end_user))
+                    }
+                    Some(WriteOutcome::Failed(end_user)) => {
+                        let error = result.err().unwrap_or_else(|| {
+                            CmdError::Data(ErrorMessage::InvalidOperation(format!(
+                                "Too few holders stored {:?}",
+                                address
+                            )))
+                        });
+                        duties.push(Self::write_failed(correlation_id, end_user, error))
+                    }
+                    None => (),
+                }
             }
         }
-        let mut unresponsive_adults = Vec::new();
-        for (name, count) in self.adult_liveness.find_unresponsive_adults() {
-            warn!(
-                "Adult {} has {} pending ops. It might be unresponsive",
-                name, count
-            );
-            unresponsive_adults.push(name);
-        }
-        if !unresponsive_adults.is_empty() {
-            duties.push(NodeDuty::ProposeOffline(unresponsive_adults))
-        }
+        duties.extend(self.conclude_handoff(correlation_id, succeeded));
+        duties.extend(self.conclude_move(correlation_id, succeeded).await);
         duties
     }
 
@@ -275,35 +707,223 @@ impl BlobRecords {
             )));
         }
         let mut duties = vec![];
-        if let Some((_address, end_user)) = self
-            .adult_liveness
-            .record_adult_read_liveness(correlation_id, src)
+        // Shards, and chunks being moved, are only read from the Adults holding them, which are all
+        // expected to find them. Other reads may reach Adults which never held the chunk.
+        let succeeded = Some(matches!(response, QueryResponse::GetBlob(Ok(_))));
+        let outcome = self.read_outcome(&src, &response);
+        if self.shards.is_shard_read(&correlation_id) {
+            let _ = self
+                .adult_liveness
+                .record_adult_read_liveness(correlation_id, src, succeeded);
+            let shard = match response {
+                QueryResponse::GetBlob(Ok(shard)) => Some(shard),
+                _ => None,
+            };
+            duties.extend(self.record_shard(&correlation_id, shard).await?);
+        } else if self.rebalancing.is_fetch(&correlation_id) {
+            let _ = self
+                .adult_liveness
+                .record_adult_read_liveness(correlation_id, src, succeeded);
+            duties.extend(self.write_moved(&correlation_id, response)?);
+        } else if let Some(holder) = self.restorations.get(&correlation_id).copied() {
+            let _ = self
+                .adult_liveness
+                .record_adult_read_liveness(correlation_id, src, outcome);
+            duties.extend(self.write_restored(&correlation_id, holder, response)?);
+            if !self.adult_liveness.is_pending(&correlation_id) {
+                let _ = self.restorations.remove(&correlation_id);
+            }
+        } else if self.hedged.is_attempt(&correlation_id) {
+            if let Some((address, _)) =
+                self.adult_liveness
+                    .record_adult_read_liveness(correlation_id, src, outcome)
+            {
+                let valid = matches!(
+                    &response,
+                    QueryResponse::GetBlob(Ok(blob)) if *blob.address() == address
+                );
+                match self.hedged.record(&correlation_id, valid, Instant::now())? {
+                    Some(ReadStep::Answer { msg_id, origin }) => {
+                        duties.extend(self.answer_read(address, msg_id, origin, response))
+                    }
+                    Some(ReadStep::Query(attempt)) => duties.push(self.query_holder(attempt)),
+                    None => (),
+                }
+            }
+        } else if let Some((address, None)) =
+            self.adult_liveness
+                .record_adult_read_liveness(correlation_id, src, outcome)
         {
-            duties.push(NodeDuty::Send(OutgoingMsg {
-                msg: Message::QueryResponse {
-                    response,
-                    id: MessageId::in_response_to(&correlation_id),
-                    correlation_id,
-                },
-                dst: DstLocation::EndUser(end_user),
-                section_source: false,
-                aggregation: Aggregation::AtDestination,
-            }));
+            // A chunk being restored: republishing it stores it again at the holders missing it.
+            match response {
+                QueryResponse::GetBlob(Ok(blob)) if *blob.address() == address => {
+                    duties.extend(self.republish_chunk(blob).await?)
+                }
+                _ => info!(
+                    "Holder {} could not provide {:?} for restoring",
+                    src, address
+                ),
+            }
         }
-        let mut unresponsive_adults = Vec::new();
-        for (name, count) in self.adult_liveness.find_unresponsive_adults() {
+        Ok(duties)
+    }
+
+    // Whether `src` answering a read with `response` succeeded. Not finding the chunk only counts
+    // as failing at Adults it was recorded at, as the others may well not have it.
+    fn read_outcome(&self, src: &XorName, response: &QueryResponse) -> Option<bool> {
+        match response {
+            QueryResponse::GetBlob(Ok(_)) => Some(true),
+            QueryResponse::GetBlob(Err(ErrorMessage::DataNotFound(DataAddress::Blob(address))))
+                if !self.chunk_holders.holders_of(address).contains(src) =>
+            {
+                None
+            }
+            _ => Some(false),
+        }
+    }
+
+    /// Times out the operations whose holders didn't all answer in time. Clients waiting on a read
+    /// with no holders left to query get an error. Holders degraded for several scoring windows in a row are
+    /// proposed offline, once each time.
+    pub(super) async fn expire_pending_ops(&mut self) -> Result<NodeDuties> {
+        let now = Instant::now();
+        let mut duties = vec![];
+        for (msg_id, data, origin) in self.placements.expire(now) {
             warn!(
-                "Adult {} has {} pending ops. It might be unresponsive",
-                name, count
+                "Holders of {:?} weren't picked in time, picking them on agreed state",
+                data.address()
+            );
+            duties.extend(
+                self.store_placed(data, msg_id, origin, Placing::Agreed)
+                    .await?,
             );
-            unresponsive_adults.push(name);
         }
+        for ExpiredOp { msg_id, operation } in self.adult_liveness.expire(now) {
+            match operation {
+                Operation::Read {
+                    address, targets, ..
+                } => {
+                    warn!(
+                        "Read {:?} of {:?} timed out at holders {:?}",
+                        msg_id, address, targets
+                    );
+                    if self.shards.is_shard_read(&msg_id) {
+                        duties.extend(self.record_shard(&msg_id, None).await?);
+                    } else if self.rebalancing.end(&msg_id).is_some() {
+                        info!("Gave up moving chunk {:?}", address);
+                    } else if let Some(holder) = self.restorations.remove(&msg_id) {
+                        info!("Gave up restoring chunk {:?} at {}", address, holder);
+                    } else if let Some((msg_id, end_user)) = self.hedged.expire(&msg_id) {
+                        let error = ErrorMessage::InvalidOperation(format!(
+                            "Timed out reading {:?} from its holders",
+                            address
+                        ));
+                        let response = QueryResponse::GetBlob(Err(error));
+                        duties.extend(self.answer_read(address, msg_id, end_user, response));
+                    }
+                }
+                Operation::Write {
+                    address,
+                    origin,
+                    targets,
+                } => {
+                    warn!(
+                        "Write {:?} of {:?} timed out at holders {:?}",
+                        msg_id, address, targets
+                    );
+                    let _ = self.in_flight.remove(&msg_id);
+                    let _ = self.deleting.remove(&msg_id);
+                    let client_msg_id = self.shards.end_write(&msg_id).unwrap_or(msg_id);
+                    if origin.is_some() {
+                        let what = if self.acks.is_delete(&client_msg_id) {
+                            "deleting"
+                        } else {
+                            "storing"
+                        };
+                        for _ in &targets {
+                            if let Some(WriteOutcome::Failed(end_user)) =
+                                self.acks.record(&client_msg_id, false)
+                            {
+                                let error = CmdError::Data(ErrorMessage::InvalidOperation(
+                                    format!("Timed out {} {:?} at enough holders", what, address),
+                                ));
+                                duties.push(Self::write_failed(client_msg_id, end_user, error));
+                            }
+                        }
+                    }
+                    duties.extend(self.conclude_handoff(msg_id, false));
+                    if self.rebalancing.end_write(&msg_id).is_some() {
+                        info!("Gave up moving chunk {:?}", address);
+                    }
+                }
+            }
+        }
+        let unresponsive_adults = self.adult_liveness.find_unresponsive_adults(now);
         if !unresponsive_adults.is_empty() {
+            warn!(
+                "Adults {:?} have been degraded for a while. They might be unresponsive",
+                unresponsive_adults
+            );
             duties.push(NodeDuty::ProposeOffline(unresponsive_adults));
         }
         Ok(duties)
     }
 
+    // Sends the write of a new chunk to its holders, keeping it to redirect it should some of them
+    // leave before storing the chunk.
+    fn send_write(
+        &mut self,
+        msg_id: MessageId,
+        address: BlobAddress,
+        copy_count: usize,
+        targets: BTreeSet<XorName>,
+        msg: Message,
+        aggregation: Aggregation,
+    ) -> NodeDuty {
+        let _ = self.in_flight.insert(
+            msg_id,
+            InFlightWrite {
+                address,
+                copy_count,
+                holders: targets.clone(),
+                msg: msg.clone(),
+                aggregation,
+            },
+        );
+        NodeDuty::SendToNodes {
+            targets,
+            msg,
+            aggregation,
+        }
+    }
+
+    // Confirms to the client that its write `msg_id` was stored.
+    fn write_stored(msg_id: MessageId, origin: EndUser) -> NodeDuty {
+        NodeDuty::Send(OutgoingMsg {
+            msg: Message::NodeEvent {
+                event: NodeEvent::ChunkWriteHandled(Ok(())),
+                id: MessageId::in_response_to(&msg_id),
+                correlation_id: msg_id,
+            },
+            dst: DstLocation::EndUser(origin),
+            section_source: false,
+            aggregation: Aggregation::AtDestination,
+        })
+    }
+
+    fn write_failed(msg_id: MessageId, origin: EndUser, error: CmdError) -> NodeDuty {
+        NodeDuty::Send(OutgoingMsg {
+            msg: Message::CmdError {
+                error,
+                id: MessageId::in_response_to(&msg_id),
+                correlation_id: msg_id,
+            },
+            dst: DstLocation::EndUser(origin),
+            section_source: false,
+            aggregation: Aggregation::AtDestination,
+        })
+    }
+
     async fn send_error(
         &self,
         error: Error,
@@ -328,9 +948,29 @@ impl BlobRecords {
         address: BlobAddress,
         msg_id: MessageId,
         origin: EndUser,
+    ) -> Result<NodeDuties> {
+        if let Some(map) = self.shards.map(&address) {
+            return self.delete_shards(address, map, msg_id, origin).await;
+        }
+        Ok(vec![self.delete_chunk(address, msg_id, origin).await?])
+    }
+
+    async fn delete_chunk(
+        &mut self,
+        address: BlobAddress,
+        msg_id: MessageId,
+        origin: EndUser,
     ) -> Result<NodeDuty> {
-        let targets = self.get_holders_for_chunk(address.name()).await;
-        let targets = targets.iter().cloned().collect::<BTreeSet<_>>();
+        let mut targets = self
+            .get_read_holders_for_chunk(address.name())
+            .await
+            .into_iter()
+            .collect::<BTreeSet<_>>();
+        // Holders it was written to may since have fallen out of the candidates.
+        let recorded = self.chunk_holders.holders_of(&address);
+        targets.extend(recorded.iter().copied());
+        self.challenges.remove_chunk(&address);
+        self.chunk_holders.remove(&address)?;
 
         if self.adult_liveness.new_write(
             msg_id,
@@ -338,6 +978,9 @@ impl BlobRecords {
             address,
             targets.clone(),
         ) {
+            let _ = self.deleting.insert(msg_id, recorded);
+            self.acks
+                .start_delete(msg_id, origin, CHUNK_COPY_COUNT, targets.len());
             let msg = Message::NodeCmd {
                 cmd: NodeCmd::Chunks {
                     cmd: BlobWrite::DeletePrivate(address),
@@ -359,11 +1002,116 @@ impl BlobRecords {
         }
     }
 
-    pub(super) async fn republish_chunk(&mut self, data: Blob) -> Result<NodeDuty> {
+    // Owners are checked here, as the shard map is forgotten before the holders check them.
+    async fn delete_shards(
+        &mut self,
+        address: BlobAddress,
+        map: ShardMap,
+        msg_id: MessageId,
+        origin: EndUser,
+    ) -> Result<NodeDuties> {
+        if map.owner.as_ref() != Some(origin.id()) {
+            return Ok(vec![
+                self.send_error(Error::InvalidOwners(*origin.id()), msg_id, origin)
+                    .await?,
+            ]);
+        }
+        let _ = self.shards.remove_map(&address)?;
+
+        let shard_count = map.shards.len();
+        let mut duties = vec![];
+        for (index, shard_address) in map.shards.into_iter().enumerate() {
+            let targets = self
+                .get_read_holders(shard_address.name(), SHARD_COPY_COUNT)
+                .await
+                .into_iter()
+                .collect::<BTreeSet<_>>();
+            let shard_msg_id = shard_msg_id(msg_id, index)?;
+            if !self.adult_liveness.new_write(
+                shard_msg_id,
+                Some(origin),
+                shard_address,
+                targets.clone(),
+            ) {
+                continue;
+            }
+            self.shards.start_write(shard_msg_id, msg_id);
+            duties.push(NodeDuty::SendToNodes {
+                msg: Message::NodeCmd {
+                    cmd: NodeCmd::Chunks {
+                        cmd: BlobWrite::DeletePrivate(shard_address),
+                        origin,
+                    },
+                    id: shard_msg_id,
+                },
+                targets,
+                aggregation: Aggregation::AtDestination,
+            });
+        }
+        self.acks
+            .start_delete(msg_id, origin, shard_count, duties.len());
+        Ok(duties)
+    }
+
+    // Confirms a handoff to the Adults handing the chunk off, once all its new holders stored it.
+    fn conclude_handoff(&mut self, msg_id: MessageId, succeeded: bool) -> Option<NodeDuty> {
+        let handoff = self.handoffs.get_mut(&msg_id)?;
+        handoff.failed |= !succeeded;
+        if self.adult_liveness.is_pending(&msg_id) {
+            return None;
+        }
+        let handoff = self.handoffs.remove(&msg_id)?;
+        if handoff.failed {
+            warn!(
+                "New holders failed to store chunk {:?} handed off by {:?}",
+                handoff.address, handoff.sources
+            );
+            return None;
+        }
+        self.challenges
+            .remove_holders(&handoff.address, &handoff.sources);
+        Some(NodeDuty::SendStorageMsg {
+            msg: StorageMsg::HandoffComplete {
+                address: handoff.address,
+            },
+            targets: handoff.sources,
+        })
+    }
+
+    /// Republishes a chunk an Adult no longer holds, to confirm it to the Adult once the new
+    /// holders stored it.
+    pub(super) async fn hand_off_chunk(&mut self, data: Blob, src: XorName) -> Result<NodeDuties> {
+        let address = *data.address();
+        let (msg_id, duties) = self.republish(data).await?;
+        let _ = self
+            .handoffs
+            .entry(msg_id)
+            .or_insert_with(|| Handoff {
+                address,
+                sources: BTreeSet::new(),
+                failed: false,
+            })
+            .sources
+            .insert(src);
+        Ok(duties)
+    }
+
+    pub(super) async fn republish_chunk(&mut self, data: Blob) -> Result<NodeDuties> {
+        Ok(self.republish(data).await?.1)
+    }
+
+    // Returns the id of the republish, with the duties to send it if it's not in progress already.
+    async fn republish(&mut self, data: Blob) -> Result<(MessageId, NodeDuties)> {
         let owner = data.owner();
+        let is_shard = self.shards.is_shard(data.address());
+        let copy_count = if is_shard {
+            SHARD_COPY_COUNT
+        } else {
+            CHUNK_COPY_COUNT
+        };
 
         let target_holders = self
-            .get_holders_for_chunk(data.name())
+            .get_holders(data.name(), copy_count)
             .await
             .iter()
             .cloned()
@@ -378,6 +1126,11 @@ impl BlobRecords {
             &target_holders,
             msg_id
         );
+        // A shard has a single holder, with no others to cross-check its proofs with.
+        if !is_shard {
+            self.challenges.add_chunk(&data, &target_holders);
+            self.chunk_holders.add(data.address(), &target_holders)?;
+        }
 
         if self.adult_liveness.new_write(
             msg_id,
@@ -385,17 +1138,597 @@ impl BlobRecords {
             *data.address(),
             target_holders.clone(),
         ) {
-            Ok(NodeDuty::SendToNodes {
-                targets: target_holders,
-                msg: Message::NodeCmd {
+            let address = *data.address();
+            let mut duties = self
+                .mark_shard(address, &target_holders)
+                .into_iter()
+                .collect::<Vec<_>>();
+            duties.push(self.send_write(
+                msg_id,
+                address,
+                copy_count,
+                target_holders,
+                Message::NodeCmd {
                     cmd: NodeCmd::System(NodeSystemCmd::ReplicateChunk(data)),
                     id: msg_id,
                 },
-                aggregation: Aggregation::None,
-            })
+                Aggregation::None,
+            ));
+            Ok((msg_id, duties))
         } else {
             info!("Skipping chunk republish since it's already in progress");
-            Ok(NodeDuty::NoOp)
+            Ok((msg_id, vec![]))
+        }
+    }
+
+    // Tells `holders` the chunk at `address` is a shard, if it's one, as they can't tell from its
+    // content.
+    fn mark_shard(&self, address: BlobAddress, holders: &BTreeSet<XorName>) -> Option<NodeDuty> {
+        if !self.shards.is_shard(&address) {
+            return None;
+        }
+        Some(NodeDuty::SendStorageMsg {
+            msg: StorageMsg::MarkShard { address },
+            targets: holders.clone(),
+        })
+    }
+
+    /// Fetches a chunk which `holder` found corrupt from its other holders, to then store it at
+    /// `holder` again. Only holders of the chunk are taken up on it.
+    pub(super) async fn restore(&mut self, address: BlobAddress, holder: XorName) -> NodeDuties {
+        let mut sources = self
+            .get_read_holders_for_chunk(address.name())
+            .await
+            .into_iter()
+            .collect::<BTreeSet<_>>();
+        sources.extend(self.challenges.holders_of(&address));
+        if !sources.remove(&holder) {
+            warn!(
+                "{} reported chunk {:?} corrupt, but does not hold it",
+                holder, address
+            );
+            return vec![];
+        }
+        if sources.is_empty() {
+            warn!("No other holders to restore chunk {:?} from", address);
+            return vec![];
+        }
+
+        info!(
+            "Restoring chunk {:?} at {} from holders {:?}",
+            address, holder, sources
+        );
+        let msg_id = MessageId::new();
+        let origin = EndUser::AllClients(self.reader.our_key().await);
+        let _ = self.restorations.insert(msg_id, holder);
+        vec![self.fetch_for_restoring(address, sources, msg_id, origin)]
+    }
+
+    // Writes a chunk read from its other holders to the holder it's restored at. All the sources
+    // answering it, the write is sent once only.
+    fn write_restored(
+        &mut self,
+        fetch_msg_id: &MessageId,
+        holder: XorName,
+        response: QueryResponse,
+    ) -> Result<NodeDuties> {
+        let blob = match response {
+            QueryResponse::GetBlob(Ok(blob)) => blob,
+            _ => return Ok(vec![]),
+        };
+        let msg_id = MessageId::from_content(&(*fetch_msg_id, holder))?;
+        let address = *blob.address();
+        let targets = iter::once(holder).collect::<BTreeSet<_>>();
+        if !self
+            .adult_liveness
+            .new_write(msg_id, None, address, targets.clone())
+        {
+            return Ok(vec![]);
+        }
+        let mut duties = self
+            .mark_shard(address, &targets)
+            .into_iter()
+            .collect::<Vec<_>>();
+        duties.push(NodeDuty::SendToNodes {
+            msg: Message::NodeCmd {
+                cmd: NodeCmd::System(NodeSystemCmd::ReplicateChunk(blob)),
+                id: msg_id,
+            },
+            targets,
+            aggregation: Aggregation::None,
+        });
+        Ok(duties)
+    }
+
+    // Reads a chunk from `sources`, to republish or move it once one of them provides it.
+    fn fetch_for_restoring(
+        &mut self,
+        address: BlobAddress,
+        sources: BTreeSet<XorName>,
+        msg_id: MessageId,
+        origin: EndUser,
+    ) -> NodeDuty {
+        if self
+            .adult_liveness
+            .new_read(msg_id, address, None, sources.clone())
+        {
+            NodeDuty::SendToNodes {
+                msg: Message::NodeQuery {
+                    query: NodeQuery::Chunks {
+                        query: BlobRead::Get(address),
+                        origin,
+                    },
+                    id: msg_id,
+                },
+                targets: sources,
+                aggregation: Aggregation::None,
+            }
+        } else {
+            info!(
+                "Operation with MessageId {:?} is already in progress",
+                msg_id
+            );
+            NodeDuty::NoOp
+        }
+    }
+
+    /// Ends the challenges of the last round, and challenges the holders of a new sample of
+    /// chunks to prove they still store them.
+    pub(super) async fn challenge_holders(&mut self) -> NodeDuties {
+        let verdicts = self.challenges.expire();
+        let mut duties = self.conclude_challenges(verdicts).await;
+        for address in self.challenges.sample() {
+            // Adults the chunk wasn't written to may well not have it.
+            let holders = self.challenges.holders_of(&address);
+            if holders.is_empty() {
+                continue;
+            }
+            let nonce = self.challenges.start(address, holders.clone());
+            duties.push(NodeDuty::SendStorageMsg {
+                msg: StorageMsg::ChallengeChunk { address, nonce },
+                targets: holders,
+            });
+        }
+        duties
+    }
+
+    pub(super) async fn record_chunk_proof(
+        &mut self,
+        address: BlobAddress,
+        nonce: Nonce,
+        proof: Option<ChunkProof>,
+        src: XorName,
+    ) -> NodeDuties {
+        match self.challenges.record_proof(&address, &nonce, src, proof) {
+            Some(verdict) => self.conclude_challenges(vec![verdict]).await,
+            None => vec![],
+        }
+    }
+
+    // Holders failing a challenge count against their liveness, and the chunk is restored at them
+    // from the holders which passed it.
+    async fn conclude_challenges(&mut self, verdicts: Vec<Verdict>) -> NodeDuties {
+        let mut duties = vec![];
+        let mut failing_adults = vec![];
+        for verdict in verdicts {
+            for holder in &verdict.passed {
+                let _ = self.adult_liveness.record_proof(*holder, true);
+            }
+            for holder in &verdict.failed {
+                if self.adult_liveness.record_proof(*holder, false) {
+                    failing_adults.push(*holder);
+                }
+            }
+            if verdict.failed.is_empty() {
+                continue;
+            }
+            warn!(
+                "Holders {:?} failed to prove they store chunk {:?}",
+                verdict.failed, verdict.address
+            );
+            if verdict.passed.is_empty() {
+                warn!("No holders to restore chunk {:?} from", verdict.address);
+                continue;
+            }
+            let origin = EndUser::AllClients(self.reader.our_key().await);
+            duties.push(self.fetch_for_restoring(
+                verdict.address,
+                verdict.passed,
+                MessageId::new(),
+                origin,
+            ));
+        }
+        if !failing_adults.is_empty() {
+            warn!(
+                "Adults {:?} keep failing to prove they store chunks",
+                failing_adults
+            );
+            duties.push(NodeDuty::ProposeOffline(failing_adults));
+        }
+        duties
+    }
+
+    /// Moves chunks off the Adults above the high-water mark to emptier Adults among the candidate
+    /// holders of the chunks. Only the Elder closest to an Adult moves chunks off it, so Elders
+    /// don't move the same chunks.
+    pub(super) async fn rebalance(&mut self) -> NodeDuties {
+        let room = self.rebalancing.room();
+        if room == 0 {
+            return vec![];
+        }
+        let our_name = self.reader.our_name().await;
+        let levels = self.adult_storage_info.levels.read().await.clone();
+        let mut sources = BTreeSet::new();
+        for (adult, level) in &levels {
+            if rebalancing::is_above_high_water(level)
+                && self.reader.elder_closest_to(adult).await == Some(our_name)
+            {
+                let _ = sources.insert(*adult);
+            }
+        }
+        if sources.is_empty() {
+            return vec![];
+        }
+
+        let full_adults = self.full_adults().await;
+        let origin = EndUser::AllClients(self.reader.our_key().await);
+        let mut duties = vec![];
+        // Chunks being moved already are among those, at most as many as moves are under way.
+        for (address, source) in self
+            .chunk_holders
+            .chunks_held_by(&sources, room + MAX_MOVES)
+        {
+            if self.rebalancing.is_moving(&address) {
+                continue;
+            }
+            let holders = self.chunk_holders.holders_of(&address);
+            let destination = self
+                .get_read_holders_for_chunk(address.name())
+                .await
+                .into_iter()
+                .find(|adult| {
+                    !holders.contains(adult)
+                        && !full_adults.contains(adult)
+                        && levels.get(adult).map_or(false, rebalancing::has_room)
+                });
+            let destination = match destination {
+                Some(destination) => destination,
+                None => continue,
+            };
+            let msg_id = match self.rebalancing.start(Move {
+                address,
+                source,
+                destination,
+            }) {
+                Some(msg_id) => msg_id,
+                None => break,
+            };
+            info!(
+                "Moving chunk {:?} from {:?} to {:?}",
+                address, source, destination
+            );
+            let from = iter::once(source).collect();
+            duties.push(self.fetch_for_restoring(address, from, msg_id, origin));
+        }
+        duties
+    }
+
+    // Writes a chunk read from the Adult it's moved off to its destination.
+    fn write_moved(
+        &mut self,
+        fetch_msg_id: &MessageId,
+        response: QueryResponse,
+    ) -> Result<Option<NodeDuty>> {
+        let (msg_id, chunk_move) = match self.rebalancing.fetched(fetch_msg_id)? {
+            Some(fetched) => fetched,
+            None => return Ok(None),
+        };
+        let blob = match response {
+            QueryResponse::GetBlob(Ok(blob)) if *blob.address() == chunk_move.address => blob,
+            _ => {
+                warn!(
+                    "Could not read chunk {:?} to move it off {:?}",
+                    chunk_move.address, chunk_move.source
+                );
+                let _ = self.rebalancing.end_write(&msg_id);
+                return Ok(None);
+            }
+        };
+        let targets = iter::once(chunk_move.destination).collect::<BTreeSet<_>>();
+        if !self
+            .adult_liveness
+            .new_write(msg_id, None, chunk_move.address, targets.clone())
+        {
+            let _ = self.rebalancing.end_write(&msg_id);
+            return Ok(None);
+        }
+        Ok(Some(NodeDuty::SendToNodes {
+            msg: Message::NodeCmd {
+                cmd: NodeCmd::System(NodeSystemCmd::ReplicateChunk(blob)),
+                id: msg_id,
+            },
+            targets,
+            aggregation: Aggregation::None,
+        }))
+    }
+
+    // Once its destination stored a moved chunk, its source lets go of it, and our other Elders
+    // expect it at the destination instead, as we do.
+    async fn conclude_move(&mut self, msg_id: MessageId, succeeded: bool) -> Option<NodeDuty> {
+        let chunk_move = self.rebalancing.end_write(&msg_id)?;
+        if !succeeded {
+            warn!(
+                "{:?} failed to store chunk {:?} moved off {:?}",
+                chunk_move.destination, chunk_move.address, chunk_move.source
+            );
+            return None;
+        }
+        let Move {
+            address,
+            source,
+            destination,
+        } = chunk_move;
+        if let Err(error) = self.record_chunk_moved(address, source, destination) {
+            warn!("Could not record chunk {:?} moved: {:?}", address, error);
+        }
+        let mut targets = self.reader.our_elder_names().await;
+        let _ = targets.remove(&self.reader.our_name().await);
+        let _ = targets.insert(source);
+        Some(NodeDuty::SendStorageMsg {
+            msg: StorageMsg::ChunkMoved {
+                address,
+                from: source,
+                to: destination,
+            },
+            targets,
+        })
+    }
+
+    /// Records that a chunk was moved off `from`, which lets go of it, to `to`.
+    pub(super) fn record_chunk_moved(
+        &mut self,
+        address: BlobAddress,
+        from: XorName,
+        to: XorName,
+    ) -> Result<()> {
+        let from = iter::once(from).collect();
+        self.challenges.remove_holders(&address, &from);
+        self.challenges.add_holder(&address, to);
+        self.chunk_holders.remove_holders(&address, &from)?;
+        self.chunk_holders.add(&address, &iter::once(to).collect())
+    }
+
+    /// Ends the audits of the last round, storing again the chunks found with too few copies, and
+    /// asks the holders of a new sample of chunks whether they hold them.
+    pub(super) async fn audit_redundancy(&mut self) -> NodeDuties {
+        let results = self.audit.expire();
+        let mut duties = self.conclude_audits(results).await;
+        info!("Redundancy audit: {}", self.audit.stats());
+        for address in self.chunk_holders.sample(AUDITS_PER_ROUND) {
+            let mut holders = self
+                .get_read_holders_for_chunk(address.name())
+                .await
+                .into_iter()
+                .collect::<BTreeSet<_>>();
+            holders.extend(self.chunk_holders.holders_of(&address));
+            if holders.is_empty() || !self.audit.start(address, holders.clone()) {
+                continue;
+            }
+            duties.push(NodeDuty::SendStorageMsg {
+                msg: StorageMsg::QueryChunkHeld { address },
+                targets: holders,
+            });
+        }
+        duties
+    }
+
+    pub(super) async fn record_chunk_held(
+        &mut self,
+        address: BlobAddress,
+        held: bool,
+        src: XorName,
+    ) -> NodeDuties {
+        match self.audit.record(&address, src, held) {
+            Some(result) => self.conclude_audits(vec![result]).await,
+            None => vec![],
+        }
+    }
+
+    // A chunk found with fewer copies than holders asked is read from the holders which have it,
+    // and republished to store it at the others again.
+    async fn conclude_audits(&mut self, results: Vec<AuditResult>) -> NodeDuties {
+        let mut duties = vec![];
+        for result in results {
+            // Full Adults among the holders asked can take them beyond the copies needed.
+            if result.held_by.len() >= result.expected.min(CHUNK_COPY_COUNT) {
+                continue;
+            }
+            if result.held_by.is_empty() {
+                error!("No copies left of chunk {:?}", result.address);
+                continue;
+            }
+            warn!(
+                "Only {} of {} copies left of chunk {:?}",
+                result.held_by.len(),
+                result.expected,
+                result.address
+            );
+            self.audit.record_replication();
+            let origin = EndUser::AllClients(self.reader.our_key().await);
+            duties.push(self.fetch_for_restoring(
+                result.address,
+                result.held_by,
+                MessageId::new(),
+                origin,
+            ));
+        }
+        duties
+    }
+
+    /// Rebuilds the erasure-coded blobs which had shards at the lost Adult, to store those
+    /// shards again at their new holders.
+    pub(super) async fn repair_shards(&mut self, lost_adult: XorName) -> Result<NodeDuties> {
+        let mut duties = vec![];
+        for (address, map) in self.shards.maps() {
+            let mut lost = BTreeSet::new();
+            for (index, shard_address) in map.shards.iter().enumerate() {
+                let holders = self
+                    .get_read_holders(shard_address.name(), SHARD_COPY_COUNT)
+                    .await;
+                // The lost Adult held the shard if it's closer to it than the farthest of the
+                // current holders, the closest one with room among them.
+                let was_holder = holders.last().map_or(true, |holder| {
+                    shard_address.name().cmp_distance(&lost_adult, holder) == Ordering::Less
+                });
+                if was_holder {
+                    let _ = lost.insert(index);
+                }
+            }
+            if lost.is_empty() {
+                continue;
+            }
+            info!(
+                "Repairing shards {:?} of {:?} lost with Adult {}",
+                lost, address, lost_adult
+            );
+            duties.extend(
+                self.read_shards(address, map, MessageId::new(), None, lost)
+                    .await?,
+            );
+        }
+        Ok(duties)
+    }
+
+    /// Shard maps of the blobs of `prefix` stored erasure-coded, for other Elders to take over.
+    pub(super) fn shard_maps_of(&self, prefix: Prefix) -> Vec<(BlobAddress, ShardMap)> {
+        self.shards
+            .maps()
+            .into_iter()
+            .filter(|(address, _)| prefix.matches(address.name()))
+            .collect()
+    }
+
+    pub(super) fn update_shard_maps(&mut self, maps: Vec<(BlobAddress, ShardMap)>) -> Result<()> {
+        for (address, map) in maps {
+            self.shards.insert_map(&address, &map)?;
+        }
+        Ok(())
+    }
+
+    /// Chunks of `prefix` with the Adults they were written to, for other Elders to take over.
+    pub(super) fn chunk_holders_of(&self, prefix: Prefix) -> Vec<(BlobAddress, BTreeSet<XorName>)> {
+        self.chunk_holders.of(prefix)
+    }
+
+    pub(super) fn update_chunk_holders(
+        &mut self,
+        holders: Vec<(BlobAddress, BTreeSet<XorName>)>,
+    ) -> Result<()> {
+        for (address, holders) in holders {
+            self.chunk_holders.add(&address, &holders)?;
+        }
+        Ok(())
+    }
+
+    // Reads all shards of a blob, any `DATA_SHARDS` of which rebuild it. Without an `origin`, the
+    // `lost` shards are stored again once rebuilt.
+    async fn read_shards(
+        &mut self,
+        address: BlobAddress,
+        map: ShardMap,
+        msg_id: MessageId,
+        origin: Option<EndUser>,
+        lost: BTreeSet<usize>,
+    ) -> Result<NodeDuties> {
+        let shard_msg_ids =
+            match self
+                .shards
+                .start_read(msg_id, address, map.clone(), origin, lost)?
+            {
+                Some(shard_msg_ids) => shard_msg_ids,
+                None => {
+                    info!(
+                        "Operation with MessageId {:?} is already in progress",
+                        msg_id
+                    );
+                    return Ok(vec![]);
+                }
+            };
+        // Repairs are read on behalf of the section.
+        let query_origin = match origin {
+            Some(origin) => origin,
+            None => EndUser::AllClients(self.reader.our_key().await),
+        };
+
+        let mut duties = vec![];
+        for (shard_address, shard_msg_id) in map.shards.into_iter().zip(shard_msg_ids) {
+            let targets = self
+                .get_read_holders(shard_address.name(), SHARD_COPY_COUNT)
+                .await
+                .into_iter()
+                .collect::<BTreeSet<_>>();
+            if targets.is_empty()
+                || !self
+                    .adult_liveness
+                    .new_read(shard_msg_id, shard_address, None, targets.clone())
+            {
+                duties.extend(self.record_shard(&shard_msg_id, None).await?);
+                continue;
+            }
+            duties.push(NodeDuty::SendToNodes {
+                msg: Message::NodeQuery {
+                    query: NodeQuery::Chunks {
+                        query: BlobRead::Get(shard_address),
+                        origin: query_origin,
+                    },
+                    id: shard_msg_id,
+                },
+                targets,
+                aggregation: Aggregation::None,
+            });
+        }
+        Ok(duties)
+    }
+
+    // Answers the client with the rebuilt blob, or stores the lost shards again for a repair.
+    async fn record_shard(
+        &mut self,
+        shard_msg_id: &MessageId,
+        shard: Option<Blob>,
+    ) -> Result<NodeDuties> {
+        let rebuilt = match self.shards.record_shard(shard_msg_id, shard) {
+            Some(rebuilt) => rebuilt,
+            None => return Ok(vec![]),
+        };
+        let address = rebuilt.address;
+        match (rebuilt.origin, rebuilt.result) {
+            (Some(end_user), result) => {
+                let response = QueryResponse::GetBlob(result.map_err(|error| {
+                    warn!(
+                        "Could not rebuild {:?} from its shards: {:?}",
+                        address, error
+                    );
+                    ErrorMessage::DataNotFound(DataAddress::Blob(address))
+                }));
+                Ok(self.answer_read(address, rebuilt.msg_id, end_user, response))
+            }
+            (None, Ok(blob)) => {
+                let (_, shards) = erasure::encode(&blob)?;
+                let mut duties = vec![];
+                for (index, shard) in shards.into_iter().enumerate() {
+                    if rebuilt.lost.contains(&index) {
+                        duties.extend(self.republish_chunk(shard).await?);
+                    }
+                }
+                Ok(duties)
+            }
+            (None, Err(error)) => {
+                error!(
+                    "Could not rebuild {:?} to repair its shards: {:?}",
+                    address, error
+                );
+                Ok(vec![])
+            }
         }
     }
 
@@ -404,7 +1737,7 @@ impl BlobRecords {
         read: &BlobRead,
         msg_id: MessageId,
         origin: EndUser,
-    ) -> Result<NodeDuty> {
+    ) -> Result<NodeDuties> {
         match read {
             BlobRead::Get(address) => self.get(*address, msg_id, origin).await,
         }
@@ -415,12 +1748,48 @@ impl BlobRecords {
         address: BlobAddress,
         msg_id: MessageId,
         origin: EndUser,
-    ) -> Result<NodeDuty> {
-        let targets = self.get_holders_for_chunk(address.name()).await;
+    ) -> Result<NodeDuties> {
+        if let Some(blob) = self.cache.get(&address) {
+            let response = QueryResponse::GetBlob(Ok(blob));
+            return Ok(self.answer_read(address, msg_id, origin, response));
+        }
+        if self.coalesced.join(address, msg_id, origin) {
+            info!(
+                "Read {:?} of {:?} waits on the read of it under way",
+                msg_id, address
+            );
+            return Ok(vec![]);
+        }
+        if let Some(map) = self.shards.map(&address) {
+            return self
+                .read_shards(address, map, msg_id, Some(origin), BTreeSet::new())
+                .await;
+        }
+        Ok(vec![self.get_chunk(address, msg_id, origin).await?])
+    }
 
-        let targets = targets.into_iter().collect::<BTreeSet<_>>();
+    // Queries the best holder of the chunk, the others being queried only if it fails or is slow.
+    async fn get_chunk(
+        &mut self,
+        address: BlobAddress,
+        msg_id: MessageId,
+        origin: EndUser,
+    ) -> Result<NodeDuty> {
+        // The Adults the chunk was written to go first, the others possibly not having it.
+        let recorded = self.chunk_holders.holders_of(&address);
+        let others = self
+            .get_read_holders_for_chunk(address.name())
+            .await
+            .into_iter()
+            .filter(|holder| !recorded.contains(holder))
+            .collect();
+        let mut holders = self
+            .adult_liveness
+            .rank_holders(recorded.into_iter().collect());
+        holders.extend(self.adult_liveness.rank_holders(others));
 
-        if targets.is_empty() {
+        if holders.is_empty() {
+            let _ = self.coalesced.end(&address, &msg_id);
             return self
                 .send_error(
                     Error::NoAdults(self.reader.our_prefix().await),
@@ -430,38 +1799,118 @@ impl BlobRecords {
                 .await;
         }
 
-        if self
+        match self
+            .hedged
+            .start(msg_id, address, origin, holders, Instant::now())?
+        {
+            Some(attempt) => Ok(self.query_holder(attempt)),
+            None => {
+                info!(
+                    "Operation with MessageId {:?} is already in progress",
+                    msg_id
+                );
+                Ok(NodeDuty::NoOp)
+            }
+        }
+    }
+
+    // Answers the read `msg_id` of the blob at `address`, and the reads which waited on it. Public
+    // blobs read are cached.
+    fn answer_read(
+        &mut self,
+        address: BlobAddress,
+        msg_id: MessageId,
+        origin: EndUser,
+        response: QueryResponse,
+    ) -> NodeDuties {
+        if let QueryResponse::GetBlob(Ok(blob)) = &response {
+            if *blob.address() == address {
+                self.cache.insert(blob);
+            }
+        }
+        iter::once((msg_id, origin))
+            .chain(self.coalesced.end(&address, &msg_id))
+            .map(|(msg_id, origin)| {
+                NodeDuty::Send(OutgoingMsg {
+                    msg: Message::QueryResponse {
+                        response: response.clone(),
+                        id: MessageId::in_response_to(&msg_id),
+                        correlation_id: msg_id,
+                    },
+                    dst: DstLocation::EndUser(origin),
+                    section_source: false,
+                    aggregation: Aggregation::AtDestination,
+                })
+            })
+            .collect()
+    }
+
+    /// Queries the next holders of the chunks being read for clients, where the holders queried
+    /// so far didn't answer in time.
+    pub(super) fn hedge_reads(&mut self) -> Result<NodeDuties> {
+        let attempts = self.hedged.due(Instant::now())?;
+        Ok(attempts
+            .into_iter()
+            .map(|attempt| self.query_holder(attempt))
+            .collect())
+    }
+
+    fn query_holder(&mut self, attempt: Attempt) -> NodeDuty {
+        let Attempt {
+            msg_id,
+            address,
+            origin,
+            holder,
+        } = attempt;
+        let targets = iter::once(holder).collect::<BTreeSet<_>>();
+        if !self
             .adult_liveness
-            .new_read(msg_id, address, origin, targets.clone())
+            .new_read(msg_id, address, Some(origin), targets.clone())
         {
-            let msg = Message::NodeQuery {
+            return NodeDuty::NoOp;
+        }
+        NodeDuty::SendToNodes {
+            msg: Message::NodeQuery {
                 query: NodeQuery::Chunks {
                     query: BlobRead::Get(address),
                     origin,
                 },
                 id: msg_id,
-            };
-
-            Ok(NodeDuty::SendToNodes {
-                msg,
-                targets,
-                aggregation: Aggregation::None,
-            })
-        } else {
-            info!(
-                "Operation with MessageId {:?} is already in progress",
-                msg_id
-            );
-            Ok(NodeDuty::NoOp)
+            },
+            targets,
+            aggregation: Aggregation::None,
         }
     }
 
-    // Returns `XorName`s of the target holders for an Blob chunk.
-    // Used to fetch the list of holders for a new chunk.
-    async fn get_holders_for_chunk(&self, target: &XorName) -> Vec<XorName> {
+    // Returns `XorName`s of the target holders for new copies of a Blob chunk, the emptier Adults
+    // as per the storage levels we heard of going first.
+    async fn get_holders(&self, target: &XorName, count: usize) -> Vec<XorName> {
+        let full_adults = self.adult_storage_info.full_adults.read().await;
+        let levels = self.adult_storage_info.levels.read().await;
+        self.reader
+            .non_full_adults_closest_to(&target, &full_adults, &levels, count)
+            .await
+    }
+
+    // Returns the holders all Elders pick for new copies of a Blob chunk, leaving the storage
+    // levels each heard of out. Used where Elders send the same write without agreeing first.
+    async fn get_agreed_holders(&self, target: &XorName, count: usize) -> Vec<XorName> {
+        let full_adults = self.adult_storage_info.full_adults.read().await;
+        self.reader
+            .non_full_adults_closest_to(&target, &full_adults, &BTreeMap::new(), count)
+            .await
+    }
+
+    // Returns `XorName`s of the Adults holding copies of a Blob chunk, full ones included.
+    // Used to read, delete or check the copies of a stored chunk.
+    async fn get_read_holders_for_chunk(&self, target: &XorName) -> Vec<XorName> {
+        self.get_read_holders(target, CHUNK_COPY_COUNT).await
+    }
+
+    async fn get_read_holders(&self, target: &XorName, count: usize) -> Vec<XorName> {
         let full_adults = self.adult_storage_info.full_adults.read().await;
         self.reader
-            .non_full_adults_closest_to(&target, &full_adults, CHUNK_COPY_COUNT)
+            .holders_closest_to(&target, &full_adults, count)
             .await
     }
 }
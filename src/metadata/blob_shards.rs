@@ -0,0 +1,281 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Bookkeeping of erasure-coded blobs at Elders: where their shards are, and the reads of shards
+//! under way to rebuild them.
+
+use crate::{
+    erasure::{self, ShardMap},
+    to_db_key::{from_db_key, ToDbKey},
+    utils, Error, Result,
+};
+use log::warn;
+use pickledb::PickleDb;
+use sn_data_types::{Blob, BlobAddress};
+use sn_messaging::{EndUser, MessageId};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    path::Path,
+};
+
+const BLOB_SHARDS_DB_NAME: &str = "blob_shards.db";
+
+/// A blob rebuilt from its shards, or the error it couldn't be rebuilt with.
+pub(super) struct RebuiltBlob {
+    /// Id of the read of the blob, which the shard reads were started for.
+    pub(super) msg_id: MessageId,
+    /// The client which asked for the blob, or `None` if lost shards are being repaired.
+    pub(super) origin: Option<EndUser>,
+    pub(super) address: BlobAddress,
+    pub(super) result: Result<Blob>,
+    /// Indices of the shards to store again once the blob is rebuilt.
+    pub(super) lost: BTreeSet<usize>,
+}
+
+struct ShardedRead {
+    address: BlobAddress,
+    map: ShardMap,
+    shards: Vec<Option<Blob>>,
+    unanswered: usize,
+    origin: Option<EndUser>,
+    lost: BTreeSet<usize>,
+}
+
+pub(super) struct BlobShards {
+    // Shard maps of the blobs stored erasure-coded, by the address of the blob.
+    maps: PickleDb,
+    // Addresses of the shards in `maps`.
+    shard_addresses: HashSet<BlobAddress>,
+    reads: HashMap<MessageId, ShardedRead>,
+    // Reads of single shards, to the read of their blob and the index of the shard.
+    shard_reads: HashMap<MessageId, (MessageId, usize)>,
+    // Writes of single shards, to the client's write of their blob.
+    shard_writes: HashMap<MessageId, MessageId>,
+}
+
+impl BlobShards {
+    pub(super) fn new(path: &Path) -> Result<Self> {
+        let mut shards = Self {
+            maps: utils::new_auto_dump_db(path, BLOB_SHARDS_DB_NAME)?,
+            shard_addresses: HashSet::new(),
+            reads: HashMap::new(),
+            shard_reads: HashMap::new(),
+            shard_writes: HashMap::new(),
+        };
+        shards.shard_addresses = shards
+            .maps()
+            .into_iter()
+            .flat_map(|(_, map)| map.shards)
+            .collect();
+        Ok(shards)
+    }
+
+    /// Shard map of the blob at `address`, if it's stored erasure-coded.
+    pub(super) fn map(&self, address: &BlobAddress) -> Option<ShardMap> {
+        self.maps.get(&address.to_db_key().ok()?)
+    }
+
+    pub(super) fn insert_map(&mut self, address: &BlobAddress, map: &ShardMap) -> Result<()> {
+        self.maps
+            .set(&address.to_db_key()?, map)
+            .map_err(Error::PickleDb)?;
+        self.shard_addresses.extend(map.shards.iter().copied());
+        Ok(())
+    }
+
+    pub(super) fn remove_map(&mut self, address: &BlobAddress) -> Result<Option<ShardMap>> {
+        let map = self.map(address);
+        if let Some(map) = &map {
+            let _ = self
+                .maps
+                .rem(&address.to_db_key()?)
+                .map_err(Error::PickleDb)?;
+            for shard in &map.shards {
+                let _ = self.shard_addresses.remove(shard);
+            }
+        }
+        Ok(map)
+    }
+
+    /// Whether the chunk at `address` is a shard of a blob stored erasure-coded, and so stored at
+    /// a single holder. It's told from the shard maps only, never from the content of the chunk,
+    /// which clients choose.
+    pub(super) fn is_shard(&self, address: &BlobAddress) -> bool {
+        self.shard_addresses.contains(address)
+    }
+
+    /// All shard maps, for other Elders to take over.
+    pub(super) fn maps(&self) -> Vec<(BlobAddress, ShardMap)> {
+        self.maps
+            .get_all()
+            .iter()
+            .filter_map(|key| Some((from_db_key(key).ok()?, self.maps.get(key)?)))
+            .collect()
+    }
+
+    /// Starts reading the shards of the blob at `address`, returning the id to read each shard
+    /// with, in the order of `map.shards`. Returns `None` if the read is already under way.
+    pub(super) fn start_read(
+        &mut self,
+        msg_id: MessageId,
+        address: BlobAddress,
+        map: ShardMap,
+        origin: Option<EndUser>,
+        lost: BTreeSet<usize>,
+    ) -> Result<Option<Vec<MessageId>>> {
+        if self.reads.contains_key(&msg_id) {
+            return Ok(None);
+        }
+        let mut shard_msg_ids = Vec::with_capacity(map.shards.len());
+        for index in 0..map.shards.len() {
+            let shard_msg_id = shard_msg_id(msg_id, index)?;
+            let _ = self.shard_reads.insert(shard_msg_id, (msg_id, index));
+            shard_msg_ids.push(shard_msg_id);
+        }
+        let _ = self.reads.insert(
+            msg_id,
+            ShardedRead {
+                address,
+                shards: vec![None; map.shards.len()],
+                unanswered: map.shards.len(),
+                map,
+                origin,
+                lost,
+            },
+        );
+        Ok(Some(shard_msg_ids))
+    }
+
+    pub(super) fn is_shard_read(&self, shard_msg_id: &MessageId) -> bool {
+        self.shard_reads.contains_key(shard_msg_id)
+    }
+
+    /// Records a shard read, returning the blob once enough shards were read to rebuild it, or
+    /// the error once all shards were read without enough of them found.
+    pub(super) fn record_shard(
+        &mut self,
+        shard_msg_id: &MessageId,
+        shard: Option<Blob>,
+    ) -> Option<RebuiltBlob> {
+        let (msg_id, index) = self.shard_reads.remove(shard_msg_id)?;
+        let read = self.reads.get_mut(&msg_id)?;
+        read.unanswered -= 1;
+        if let Some(shard) = shard {
+            if read.map.shards.get(index) == Some(shard.address()) {
+                read.shards[index] = Some(shard);
+            } else {
+                warn!("Got {:?} when reading shard {}", shard.address(), index);
+            }
+        }
+        let found = read.shards.iter().flatten().count();
+        if found < erasure::DATA_SHARDS && read.unanswered > 0 {
+            return None;
+        }
+
+        let read = self.reads.remove(&msg_id)?;
+        self.shard_reads.retain(|_, (id, _)| *id != msg_id);
+        Some(RebuiltBlob {
+            msg_id,
+            origin: read.origin,
+            address: read.address,
+            result: erasure::decode(&read.address, &read.map, read.shards),
+            lost: read.lost,
+        })
+    }
+
+    /// Remembers that the write of a shard is part of the client's write `msg_id`.
+    pub(super) fn start_write(&mut self, shard_msg_id: MessageId, msg_id: MessageId) {
+        let _ = self.shard_writes.insert(shard_msg_id, msg_id);
+    }
+
+    /// The client's write the write of a shard is part of, if it's one.
+    pub(super) fn end_write(&mut self, shard_msg_id: &MessageId) -> Option<MessageId> {
+        self.shard_writes.remove(shard_msg_id)
+    }
+}
+
+/// Deterministic id of the operation on shard `index`, as part of operation `msg_id` on its blob.
+pub(super) fn shard_msg_id(msg_id: MessageId, index: usize) -> Result<MessageId> {
+    Ok(MessageId::from_content(&(msg_id, index))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BlobShards;
+    use crate::{erasure, Error, Result};
+    use sn_data_types::{Blob, PublicBlob};
+    use sn_messaging::MessageId;
+    use tempdir::TempDir;
+
+    fn temp_dir() -> Result<TempDir> {
+        TempDir::new("test").map_err(|e| Error::TempDirCreationFailed(e.to_string()))
+    }
+
+    #[test]
+    fn shard_maps_survive_restarts() -> Result<()> {
+        let root = temp_dir()?;
+        let blob = Blob::Public(PublicBlob::new(vec![1, 2, 3]));
+        let (map, _) = erasure::encode(&blob)?;
+
+        BlobShards::new(root.path())?.insert_map(blob.address(), &map)?;
+        let mut shards = BlobShards::new(root.path())?;
+        assert_eq!(shards.maps(), vec![(*blob.address(), map.clone())]);
+        assert!(map.shards.iter().all(|shard| shards.is_shard(shard)));
+        assert!(!shards.is_shard(blob.address()));
+
+        assert_eq!(shards.remove_map(blob.address())?, Some(map.clone()));
+        assert_eq!(shards.map(blob.address()), None);
+        assert!(!map.shards.iter().any(|shard| shards.is_shard(shard)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn blob_is_rebuilt_once_enough_shards_are_read() -> Result<()> {
+        let root = temp_dir()?;
+        let blob = Blob::Public(PublicBlob::new(vec![7; 100]));
+        let (map, shards) = erasure::encode(&blob)?;
+        let mut blob_shards = BlobShards::new(root.path())?;
+        let msg_id = MessageId::new();
+
+        let shard_msg_ids = blob_shards
+            .start_read(
+                msg_id,
+                *blob.address(),
+                map.clone(),
+                None,
+                Default::default(),
+            )?
+            .unwrap_or_default();
+        assert_eq!(shard_msg_ids.len(), shards.len());
+        assert!(blob_shards
+            .start_read(msg_id, *blob.address(), map, None, Default::default())?
+            .is_none());
+
+        // A missing shard only counts as answered.
+        assert!(blob_shards.record_shard(&shard_msg_ids[0], None).is_none());
+        for (shard_msg_id, shard) in shard_msg_ids[1..erasure::DATA_SHARDS]
+            .iter()
+            .zip(shards.iter().skip(1))
+        {
+            assert!(blob_shards
+                .record_shard(shard_msg_id, Some(shard.clone()))
+                .is_none());
+        }
+        let rebuilt = blob_shards.record_shard(
+            &shard_msg_ids[erasure::DATA_SHARDS],
+            Some(shards[erasure::DATA_SHARDS].clone()),
+        );
+
+        assert_eq!(rebuilt.map(|rebuilt| rebuilt.result.ok()), Some(Some(blob)));
+        // Shards read after the blob was rebuilt are no longer expected.
+        assert!(!blob_shards.is_shard_read(&shard_msg_ids[erasure::DATA_SHARDS + 1]));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,183 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use crate::{
+    to_db_key::{from_db_key, ToDbKey},
+    utils, Error, Result,
+};
+use pickledb::PickleDb;
+use rand::seq::IteratorRandom;
+use sn_data_types::BlobAddress;
+use sn_routing::Prefix;
+use std::{collections::BTreeSet, path::Path};
+use xor_name::XorName;
+
+const CHUNK_HOLDERS_DB_NAME: &str = "chunk_holders.db";
+
+/// The chunks stored in our section, with the Adults they were written to. It's kept on disk, so
+/// it holds after a restart, and handed over to new Elders. Shards are left out, as their holders
+/// are told from the shard maps.
+pub(super) struct ChunkHolders {
+    db: PickleDb,
+}
+
+impl ChunkHolders {
+    pub(super) fn new(path: &Path) -> Result<Self> {
+        Ok(Self {
+            db: utils::new_dump_on_request_db(path, CHUNK_HOLDERS_DB_NAME)?,
+        })
+    }
+
+    /// The Adults the chunk at `address` was written to.
+    pub(super) fn holders_of(&self, address: &BlobAddress) -> BTreeSet<XorName> {
+        address
+            .to_db_key()
+            .ok()
+            .and_then(|key| self.db.get(&key))
+            .unwrap_or_default()
+    }
+
+    /// Records that the chunk at `address` was written to `holders`, along with those it was
+    /// written to before.
+    pub(super) fn add(&mut self, address: &BlobAddress, holders: &BTreeSet<XorName>) -> Result<()> {
+        let mut known = self.holders_of(address);
+        known.extend(holders.iter().copied());
+        self.set(address, &known)?;
+        self.flush()
+    }
+
+    /// Records that `holders` no longer have the chunk at `address`. The chunk is still known
+    /// without any holder, for audits to look for its copies.
+    pub(super) fn remove_holders(
+        &mut self,
+        address: &BlobAddress,
+        holders: &BTreeSet<XorName>,
+    ) -> Result<()> {
+        let mut known = self.holders_of(address);
+        known.retain(|holder| !holders.contains(holder));
+        self.set(address, &known)?;
+        self.flush()
+    }
+
+    /// Forgets a deleted chunk.
+    pub(super) fn remove(&mut self, address: &BlobAddress) -> Result<()> {
+        let _ = self
+            .db
+            .rem(&address.to_db_key()?)
+            .map_err(Error::PickleDb)?;
+        self.flush()
+    }
+
+    /// Picks `count` of the chunks at random.
+    pub(super) fn sample(&self, count: usize) -> Vec<BlobAddress> {
+        self.db
+            .get_all()
+            .iter()
+            .filter_map(|key| from_db_key(key).ok())
+            .choose_multiple(&mut rand::thread_rng(), count)
+    }
+
+    /// Chunks written to any of `adults`, with the one of them holding each, at most `count` of
+    /// them.
+    pub(super) fn chunks_held_by(
+        &self,
+        adults: &BTreeSet<XorName>,
+        count: usize,
+    ) -> Vec<(BlobAddress, XorName)> {
+        self.all()
+            .into_iter()
+            .filter_map(|(address, holders)| {
+                holders
+                    .iter()
+                    .find(|holder| adults.contains(holder))
+                    .map(|holder| (address, *holder))
+            })
+            .take(count)
+            .collect()
+    }
+
+    /// Forgets holders which left.
+    pub(super) fn retain_members_only(&mut self, members: &BTreeSet<XorName>) -> Result<()> {
+        for (address, mut holders) in self.all() {
+            let count = holders.len();
+            holders.retain(|holder| members.contains(holder));
+            if holders.len() != count {
+                self.set(&address, &holders)?;
+            }
+        }
+        self.flush()
+    }
+
+    /// The chunks of `prefix`, with their holders, for other Elders to take over.
+    pub(super) fn of(&self, prefix: Prefix) -> Vec<(BlobAddress, BTreeSet<XorName>)> {
+        self.all()
+            .into_iter()
+            .filter(|(address, _)| prefix.matches(address.name()))
+            .collect()
+    }
+
+    fn all(&self) -> Vec<(BlobAddress, BTreeSet<XorName>)> {
+        self.db
+            .get_all()
+            .iter()
+            .filter_map(|key| Some((from_db_key(key).ok()?, self.db.get(key)?)))
+            .collect()
+    }
+
+    fn set(&mut self, address: &BlobAddress, holders: &BTreeSet<XorName>) -> Result<()> {
+        self.db
+            .set(&address.to_db_key()?, holders)
+            .map_err(Error::PickleDb)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.db.dump().map_err(Error::PickleDb)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ChunkHolders;
+    use crate::{Error, Result};
+    use sn_data_types::{Blob, PublicBlob};
+    use sn_routing::Prefix;
+    use std::collections::BTreeSet;
+    use tempdir::TempDir;
+    use xor_name::XorName;
+
+    #[test]
+    fn holders_survive_restarts() -> Result<()> {
+        let root = TempDir::new("test").map_err(|e| Error::TempDirCreationFailed(e.to_string()))?;
+        let chunk = *Blob::Public(PublicBlob::new(vec![1])).address();
+        let deleted = *Blob::Public(PublicBlob::new(vec![2])).address();
+        let (holder, other, left) = (XorName::random(), XorName::random(), XorName::random());
+
+        let mut holders = ChunkHolders::new(root.path())?;
+        holders.add(&chunk, &vec![holder, left].into_iter().collect())?;
+        holders.add(&chunk, &vec![other].into_iter().collect())?;
+        holders.add(&deleted, &vec![holder].into_iter().collect())?;
+        holders.remove(&deleted)?;
+        holders.retain_members_only(&vec![holder, other].into_iter().collect())?;
+
+        let mut holders = ChunkHolders::new(root.path())?;
+        let expected = vec![holder, other].into_iter().collect::<BTreeSet<_>>();
+        assert_eq!(holders.holders_of(&chunk), expected);
+        assert!(holders.holders_of(&deleted).is_empty());
+        assert_eq!(holders.sample(10), vec![chunk]);
+        assert_eq!(holders.of(Prefix::default()), vec![(chunk, expected)]);
+        let adults = vec![other, left].into_iter().collect();
+        assert_eq!(holders.chunks_held_by(&adults, 10), vec![(chunk, other)]);
+        assert!(holders.chunks_held_by(&adults, 0).is_empty());
+
+        // Chunks whose holders all handed them off are still audited.
+        holders.remove_holders(&chunk, &vec![holder, other].into_iter().collect())?;
+        assert_eq!(holders.sample(10), vec![chunk]);
+
+        Ok(())
+    }
+}
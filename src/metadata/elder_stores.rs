@@ -10,8 +10,9 @@ use super::{
     blob_records::BlobRecords, map_storage::MapStorage, register_storage::RegisterStorage,
     sequence_storage::SequenceStorage,
 };
-use crate::{node_ops::NodeDuty, Error, Result};
+use crate::{node_ops::NodeDuties, Error, Result};
 use log::info;
+use sn_data_types::{register::Address as RegisterAddress, SequenceAddress};
 use sn_messaging::{
     client::{DataCmd, DataExchange, DataQuery},
     EndUser, MessageId,
@@ -47,12 +48,16 @@ impl ElderStores {
         query: DataQuery,
         msg_id: MessageId,
         origin: EndUser,
-    ) -> Result<NodeDuty> {
+    ) -> Result<NodeDuties> {
         match &query {
             DataQuery::Blob(read) => self.blob_records.read(read, msg_id, origin).await,
-            DataQuery::Map(read) => self.map_storage.read(read, msg_id, origin).await,
-            DataQuery::Sequence(read) => self.sequence_storage.read(read, msg_id, origin).await,
-            DataQuery::Register(read) => self.register_storage.read(read, msg_id, origin).await,
+            DataQuery::Map(read) => Ok(vec![self.map_storage.read(read, msg_id, origin).await?]),
+            DataQuery::Sequence(read) => Ok(vec![
+                self.sequence_storage.read(read, msg_id, origin).await?,
+            ]),
+            DataQuery::Register(read) => Ok(vec![
+                self.register_storage.read(read, msg_id, origin).await?,
+            ]),
         }
     }
 
@@ -61,7 +66,7 @@ impl ElderStores {
         cmd: DataCmd,
         msg_id: MessageId,
         origin: EndUser,
-    ) -> Result<NodeDuty> {
+    ) -> Result<NodeDuties> {
         info!("Writing Data");
         match cmd {
             DataCmd::Blob(write) => {
@@ -70,19 +75,27 @@ impl ElderStores {
             }
             DataCmd::Map(write) => {
                 info!("Writing Map");
-                self.map_storage.write(write, msg_id, origin).await
+                Ok(vec![self.map_storage.write(write, msg_id, origin).await?])
             }
             DataCmd::Sequence(write) => {
                 info!("Writing Sequence");
-                self.sequence_storage.write(write, msg_id, origin).await
+                Ok(vec![
+                    self.sequence_storage.write(write, msg_id, origin).await?,
+                ])
             }
             DataCmd::Register(write) => {
                 info!("Writing Register");
-                self.register_storage.write(write, msg_id, origin).await
+                Ok(vec![
+                    self.register_storage.write(write, msg_id, origin).await?,
+                ])
             }
         }
     }
 
+    pub fn blob_records(&self) -> &BlobRecords {
+        &self.blob_records
+    }
+
     pub fn blob_records_mut(&mut self) -> &mut BlobRecords {
         &mut self.blob_records
     }
@@ -91,8 +104,8 @@ impl ElderStores {
     pub async fn get_data_of(&self, prefix: Prefix) -> Result<DataExchange> {
         // Prepare blob_records, map and sequence data
         let blob_data = self.blob_records.get_data_of(prefix).await;
-        let map_data = self.map_storage.get_data_of(prefix);
-        let seq_data = self.sequence_storage.get_data_of(prefix);
+        let map_data = self.map_storage.get_data_of(prefix).await;
+        let seq_data = self.sequence_storage.get_data_of(prefix).await;
 
         Ok(DataExchange {
             blob_data,
@@ -108,4 +121,20 @@ impl ElderStores {
 
         Ok(())
     }
+
+    pub fn tombstones_of(&self, prefix: Prefix) -> (Vec<SequenceAddress>, Vec<RegisterAddress>) {
+        (
+            self.sequence_storage.tombstones_of(prefix),
+            self.register_storage.tombstones_of(prefix),
+        )
+    }
+
+    pub async fn add_tombstones(
+        &mut self,
+        sequences: Vec<SequenceAddress>,
+        registers: Vec<RegisterAddress>,
+    ) -> Result<()> {
+        self.sequence_storage.add_tombstones(sequences).await?;
+        self.register_storage.add_tombstones(registers).await
+    }
 }
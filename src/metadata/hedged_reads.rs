@@ -0,0 +1,255 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Hedged reads of chunks for clients.
+//!
+//! A read queries the best holder of the chunk only. The next holder is queried once the holders
+//! queried so far failed, or didn't answer within `HEDGE_DELAY`, and the first blob read is the
+//! only answer the client gets. Each holder is queried under an id of its own, so its answer is
+//! told apart, and timed, on its own.
+
+use crate::Result;
+use sn_data_types::BlobAddress;
+use sn_messaging::{EndUser, MessageId};
+use sn_routing::XorName;
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+/// How long to wait for the holders queried so far before also querying the next one.
+const HEDGE_DELAY: Duration = Duration::from_secs(2);
+
+/// The query of a single holder, as part of a client's read.
+#[derive(Debug, PartialEq)]
+pub(super) struct Attempt {
+    pub(super) msg_id: MessageId,
+    pub(super) address: BlobAddress,
+    pub(super) origin: EndUser,
+    pub(super) holder: XorName,
+}
+
+/// What to do after a holder answered.
+#[derive(Debug, PartialEq)]
+pub(super) enum ReadStep {
+    /// Answer the client's read `msg_id` with the holder's answer.
+    Answer { msg_id: MessageId, origin: EndUser },
+    /// Query another holder.
+    Query(Attempt),
+}
+
+struct HedgedRead {
+    address: BlobAddress,
+    origin: EndUser,
+    // holders not queried yet, best first
+    holders: VecDeque<XorName>,
+    attempts: usize,
+    unanswered: usize,
+    hedge_at: Instant,
+}
+
+pub(super) struct HedgedReads {
+    reads: HashMap<MessageId, HedgedRead>,
+    // Queries of single holders, to the client's read they're part of.
+    attempts: HashMap<MessageId, MessageId>,
+}
+
+impl HedgedReads {
+    pub(super) fn new() -> Self {
+        Self {
+            reads: HashMap::new(),
+            attempts: HashMap::new(),
+        }
+    }
+
+    /// Starts the read `msg_id`, returning the query of the first of `holders`, which are ordered
+    /// best first. Returns `None` if the read is already under way, or there are no holders.
+    pub(super) fn start(
+        &mut self,
+        msg_id: MessageId,
+        address: BlobAddress,
+        origin: EndUser,
+        holders: Vec<XorName>,
+        now: Instant,
+    ) -> Result<Option<Attempt>> {
+        if self.reads.contains_key(&msg_id) || holders.is_empty() {
+            return Ok(None);
+        }
+        let _ = self.reads.insert(
+            msg_id,
+            HedgedRead {
+                address,
+                origin,
+                holders: holders.into(),
+                attempts: 0,
+                unanswered: 0,
+                hedge_at: now,
+            },
+        );
+        self.next_attempt(msg_id, now)
+    }
+
+    pub(super) fn is_attempt(&self, attempt_msg_id: &MessageId) -> bool {
+        self.attempts.contains_key(attempt_msg_id)
+    }
+
+    /// Records the answer to a query, `valid` if it's the blob. The first blob read answers the
+    /// client, as does the last holder failing.
+    pub(super) fn record(
+        &mut self,
+        attempt_msg_id: &MessageId,
+        valid: bool,
+        now: Instant,
+    ) -> Result<Option<ReadStep>> {
+        let msg_id = match self.attempts.remove(attempt_msg_id) {
+            Some(msg_id) => msg_id,
+            None => return Ok(None),
+        };
+        let read = match self.reads.get_mut(&msg_id) {
+            Some(read) => read,
+            None => return Ok(None),
+        };
+        read.unanswered -= 1;
+        if valid || (read.holders.is_empty() && read.unanswered == 0) {
+            return Ok(self
+                .end(msg_id)
+                .map(|origin| ReadStep::Answer { msg_id, origin }));
+        }
+        Ok(self.next_attempt(msg_id, now)?.map(ReadStep::Query))
+    }
+
+    /// Records that a query timed out, returning the client's read if no holder is left to query.
+    pub(super) fn expire(&mut self, attempt_msg_id: &MessageId) -> Option<(MessageId, EndUser)> {
+        let msg_id = self.attempts.remove(attempt_msg_id)?;
+        let read = self.reads.get_mut(&msg_id)?;
+        read.unanswered -= 1;
+        if !read.holders.is_empty() || read.unanswered > 0 {
+            return None;
+        }
+        self.end(msg_id).map(|origin| (msg_id, origin))
+    }
+
+    /// Queries of the next holders of the reads the holders queried so far didn't answer in time.
+    pub(super) fn due(&mut self, now: Instant) -> Result<Vec<Attempt>> {
+        let due = self
+            .reads
+            .iter()
+            .filter(|(_, read)| read.hedge_at <= now && !read.holders.is_empty())
+            .map(|(msg_id, _)| *msg_id)
+            .collect::<Vec<_>>();
+        let mut attempts = Vec::with_capacity(due.len());
+        for msg_id in due {
+            attempts.extend(self.next_attempt(msg_id, now)?);
+        }
+        Ok(attempts)
+    }
+
+    fn next_attempt(&mut self, msg_id: MessageId, now: Instant) -> Result<Option<Attempt>> {
+        let read = match self.reads.get_mut(&msg_id) {
+            Some(read) => read,
+            None => return Ok(None),
+        };
+        let holder = match read.holders.pop_front() {
+            Some(holder) => holder,
+            None => return Ok(None),
+        };
+        let attempt_msg_id = MessageId::from_content(&(msg_id, read.attempts))?;
+        read.attempts += 1;
+        read.unanswered += 1;
+        read.hedge_at = now + HEDGE_DELAY;
+        let _ = self.attempts.insert(attempt_msg_id, msg_id);
+        Ok(Some(Attempt {
+            msg_id: attempt_msg_id,
+            address: read.address,
+            origin: read.origin,
+            holder,
+        }))
+    }
+
+    fn end(&mut self, msg_id: MessageId) -> Option<EndUser> {
+        let read = self.reads.remove(&msg_id)?;
+        self.attempts.retain(|_, id| *id != msg_id);
+        Some(read.origin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Attempt, HedgedReads, ReadStep, HEDGE_DELAY};
+    use crate::Result;
+    use bls::SecretKey;
+    use sn_data_types::{Blob, PublicBlob, PublicKey};
+    use sn_messaging::{EndUser, MessageId};
+    use sn_routing::XorName;
+    use std::time::Instant;
+
+    fn attempt(step: Option<ReadStep>) -> Attempt {
+        match step {
+            Some(ReadStep::Query(attempt)) => attempt,
+            other => panic!("expected a query, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn holders_are_queried_one_at_a_time_until_the_blob_is_read() -> Result<()> {
+        let address = *Blob::Public(PublicBlob::new(vec![1, 2, 3])).address();
+        let origin = EndUser::AllClients(PublicKey::from(SecretKey::random().public_key()));
+        let holders = (0..4).map(|_| XorName::random()).collect::<Vec<_>>();
+        let mut reads = HedgedReads::new();
+        let msg_id = MessageId::new();
+        let start = Instant::now();
+
+        let first = reads
+            .start(msg_id, address, origin, holders.clone(), start)?
+            .expect("no query");
+        assert_eq!(first.holder, holders[0]);
+        assert!(reads
+            .start(msg_id, address, origin, vec![], start)?
+            .is_none());
+        assert!(reads.due(start)?.is_empty());
+
+        // A failing holder has the next one queried at once.
+        let second = attempt(reads.record(&first.msg_id, false, start)?);
+        assert_eq!(second.holder, holders[1]);
+        assert_ne!(second.msg_id, first.msg_id);
+
+        // A slow one has the next one queried too.
+        let hedged = reads.due(start + HEDGE_DELAY)?;
+        assert_eq!(hedged.len(), 1);
+        assert_eq!(hedged[0].holder, holders[2]);
+
+        // Only the first blob read is answered with.
+        assert_eq!(
+            reads.record(&hedged[0].msg_id, true, start)?,
+            Some(ReadStep::Answer { msg_id, origin })
+        );
+        assert!(!reads.is_attempt(&second.msg_id));
+        assert_eq!(reads.record(&second.msg_id, true, start)?, None);
+        assert!(reads.due(start + 10 * HEDGE_DELAY)?.is_empty());
+
+        // Once all holders failed, the last failure is answered with.
+        let msg_id = MessageId::new();
+        let mut query = reads
+            .start(msg_id, address, origin, holders[..2].to_vec(), start)?
+            .expect("no query");
+        query = attempt(reads.record(&query.msg_id, false, start)?);
+        assert_eq!(
+            reads.record(&query.msg_id, false, start)?,
+            Some(ReadStep::Answer { msg_id, origin })
+        );
+
+        // Or, if the last holder didn't answer, the read times out.
+        let msg_id = MessageId::new();
+        let query = reads
+            .start(msg_id, address, origin, holders[..1].to_vec(), start)?
+            .expect("no query");
+        assert_eq!(reads.expire(&query.msg_id), Some((msg_id, origin)));
+
+        Ok(())
+    }
+}
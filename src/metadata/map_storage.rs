@@ -7,7 +7,7 @@
 // permissions and limitations relating to use of the SAFE Network Software.
 
 use crate::{
-    chunk_store::MapChunkStore,
+    chunk_store::{MapChunkStore, StoreOptions},
     error::convert_to_error_message,
     node_ops::{NodeDuty, OutgoingMsg},
     Error, Result,
@@ -33,21 +33,26 @@ pub(super) struct MapStorage {
 }
 
 impl MapStorage {
-    pub(super) async fn new(path: &Path, max_capacity: u64) -> Result<Self> {
-        let chunks = MapChunkStore::new(path, max_capacity).await?;
+    pub(super) async fn new(
+        path: &Path,
+        max_capacity: u64,
+        options: &StoreOptions,
+    ) -> Result<Self> {
+        let chunks = MapChunkStore::new(path, max_capacity, options).await?;
         Ok(Self { chunks })
     }
 
-    pub(super) fn get_data_of(&self, prefix: Prefix) -> MapDataExchange {
-        let store = &self.chunks;
-        let data = self
-            .chunks
-            .keys()
-            .iter()
-            .filter(|address| prefix.matches(address.name()))
-            .filter_map(|key| store.get(&key).ok())
-            .map(|map| (*map.address(), map))
-            .collect();
+    pub(super) async fn get_data_of(&self, prefix: Prefix) -> MapDataExchange {
+        let mut data = vec![];
+        for address in self.chunks.keys().await {
+            if !prefix.matches(address.name()) {
+                continue;
+            }
+            if let Ok(map) = self.chunks.get(&address).await {
+                data.push((*map.address(), map));
+            }
+        }
+        let data = data.into_iter().collect();
         MapDataExchange(data)
     }
 
@@ -120,8 +125,13 @@ impl MapStorage {
     /// Returns `Some(Result<..>)` if the flow should be continued, returns
     /// `None` if there was a logic error encountered and the flow should be
     /// terminated.
-    fn get_chunk(&self, address: &MapAddress, origin: EndUser, action: MapAction) -> Result<Map> {
-        self.chunks.get(&address).and_then(move |map| {
+    async fn get_chunk(
+        &self,
+        address: &MapAddress,
+        origin: EndUser,
+        action: MapAction,
+    ) -> Result<Map> {
+        self.chunks.get(&address).await.and_then(move |map| {
             map.check_permissions(action, origin.id())
                 .map(move |_| map)
                 .map_err(|error| error.into())
@@ -139,7 +149,7 @@ impl MapStorage {
     where
         F: FnOnce(Map) -> NdResult<Map>,
     {
-        let result = match self.chunks.get(address) {
+        let result = match self.chunks.get(address).await {
             Ok(data) => match mutation_fn(data) {
                 Ok(map) => self.chunks.put(&map).await,
                 Err(error) => Err(error.into()),
@@ -152,7 +162,7 @@ impl MapStorage {
 
     /// Put Map.
     async fn create(&mut self, data: &Map, msg_id: MessageId, origin: EndUser) -> Result<NodeDuty> {
-        let result = if self.chunks.has(data.address()) {
+        let result = if self.chunks.has(data.address()).await {
             Err(Error::DataExists)
         } else {
             self.chunks.put(&data).await
@@ -166,7 +176,7 @@ impl MapStorage {
         msg_id: MessageId,
         origin: EndUser,
     ) -> Result<NodeDuty> {
-        let result = match self.chunks.get(&address) {
+        let result = match self.chunks.get(&address).await {
             Ok(map) => match map.check_is_owner(origin.id()) {
                 Ok(()) => {
                     info!("Deleting Map");
@@ -240,7 +250,7 @@ impl MapStorage {
         msg_id: MessageId,
         origin: EndUser,
     ) -> Result<NodeDuty> {
-        let result = match self.get_chunk(&address, origin, MapAction::Read) {
+        let result = match self.get_chunk(&address, origin, MapAction::Read).await {
             Ok(res) => Ok(res),
             Err(error) => Err(convert_to_error_message(error)?),
         };
@@ -266,6 +276,7 @@ impl MapStorage {
     ) -> Result<NodeDuty> {
         let result = match self
             .get_chunk(&address, origin, MapAction::Read)
+            .await
             .map(|data| data.shell())
         {
             Ok(res) => Ok(res),
@@ -293,6 +304,7 @@ impl MapStorage {
     ) -> Result<NodeDuty> {
         let result = match self
             .get_chunk(&address, origin, MapAction::Read)
+            .await
             .map(|data| data.version())
         {
             Ok(res) => Ok(res),
@@ -319,7 +331,7 @@ impl MapStorage {
         msg_id: MessageId,
         origin: EndUser,
     ) -> Result<NodeDuty> {
-        let res = self.get_chunk(&address, origin, MapAction::Read);
+        let res = self.get_chunk(&address, origin, MapAction::Read).await;
         let result = match res.and_then(|data| match data {
             Map::Seq(map) => map
                 .get(key)
@@ -357,6 +369,7 @@ impl MapStorage {
     ) -> Result<NodeDuty> {
         let result = match self
             .get_chunk(&address, origin, MapAction::Read)
+            .await
             .map(|data| data.keys())
         {
             Ok(res) => Ok(res),
@@ -382,7 +395,7 @@ impl MapStorage {
         msg_id: MessageId,
         origin: EndUser,
     ) -> Result<NodeDuty> {
-        let res = self.get_chunk(&address, origin, MapAction::Read);
+        let res = self.get_chunk(&address, origin, MapAction::Read).await;
         let result = match res.map(|data| match data {
             Map::Seq(map) => map.values().into(),
             Map::Unseq(map) => map.values().into(),
@@ -410,7 +423,7 @@ impl MapStorage {
         msg_id: MessageId,
         origin: EndUser,
     ) -> Result<NodeDuty> {
-        let res = self.get_chunk(&address, origin, MapAction::Read);
+        let res = self.get_chunk(&address, origin, MapAction::Read).await;
         let result = match res.map(|data| match data {
             Map::Seq(map) => map.entries().clone().into(),
             Map::Unseq(map) => map.entries().clone().into(),
@@ -440,6 +453,7 @@ impl MapStorage {
     ) -> Result<NodeDuty> {
         let result = match self
             .get_chunk(&address, origin, MapAction::Read)
+            .await
             .map(|data| data.permissions())
         {
             Ok(res) => Ok(res),
@@ -468,6 +482,7 @@ impl MapStorage {
     ) -> Result<NodeDuty> {
         let result = match self
             .get_chunk(&address, origin, MapAction::Read)
+            .await
             .and_then(|data| {
                 data.user_permissions(&user)
                     .map_err(|e| e.into())
@@ -521,3 +536,40 @@ impl Display for MapStorage {
         write!(formatter, "MapStorage")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::MapStorage;
+    use crate::{chunk_store::StoreOptions, Error, Result, StorageBackend};
+    use bls::SecretKey;
+    use sn_data_types::{Map, PublicKey, SeqMap};
+    use sn_messaging::client::MapDataExchange;
+    use sn_routing::Prefix;
+    use std::iter;
+    use tempdir::TempDir;
+    use xor_name::XorName;
+
+    #[tokio::test]
+    async fn maps_are_handed_over_to_their_section() -> Result<()> {
+        let root = TempDir::new("test").map_err(|e| Error::TempDirCreationFailed(e.to_string()))?;
+        let options = StoreOptions {
+            backend: StorageBackend::Memory,
+            ..Default::default()
+        };
+        let mut storage = MapStorage::new(root.path(), 1024 * 1024, &options).await?;
+        let owner = PublicKey::from(SecretKey::random().public_key());
+        let map = Map::Seq(SeqMap::new(XorName::random(), 0, owner));
+        let address = *map.address();
+
+        storage
+            .update(MapDataExchange(iter::once((address, map)).collect()))
+            .await?;
+        let ours = Prefix::default().pushed(address.name().bit(0));
+        let sibling = Prefix::default().pushed(!address.name().bit(0));
+        let MapDataExchange(handed_over) = storage.get_data_of(ours).await;
+        assert_eq!(handed_over.keys().collect::<Vec<_>>(), vec![&address]);
+        assert!(storage.get_data_of(sibling).await.0.is_empty());
+
+        Ok(())
+    }
+}
@@ -8,22 +8,41 @@
 
 mod adult_liveness;
 pub mod adult_reader;
+mod blob_cache;
 mod blob_records;
+mod blob_shards;
+mod chunk_holders;
 mod elder_stores;
+mod hedged_reads;
 mod map_storage;
+mod placements;
+mod read_coalescing;
+mod rebalancing;
+mod redundancy_audit;
 mod register_storage;
 mod sequence_storage;
+mod storage_challenges;
+mod tombstones;
+mod write_acks;
 
 use self::adult_reader::AdultReader;
-use super::node_ops::NodeDuty;
-use crate::{capacity::AdultsStorageInfo, node_ops::NodeDuties, Result};
+use crate::{
+    capacity::{AdultsStorageInfo, StorageLevel},
+    chunk_store::StoreOptions,
+    erasure::ShardMap,
+    node_ops::NodeDuties,
+    storage_msg::{ChunkProof, Nonce},
+    Result, WriteAckPolicy,
+};
 use blob_records::BlobRecords;
-pub(crate) use blob_records::CHUNK_COPY_COUNT;
+pub(crate) use blob_records::{CHUNK_COPY_COUNT, SHARD_COPY_COUNT};
 use elder_stores::ElderStores;
 use map_storage::MapStorage;
 use register_storage::RegisterStorage;
 use sequence_storage::SequenceStorage;
-use sn_data_types::{Blob, PublicKey};
+use sn_data_types::{
+    register::Address as RegisterAddress, Blob, BlobAddress, PublicKey, SequenceAddress,
+};
 use sn_messaging::{
     client::{CmdError, DataCmd, DataExchange, DataQuery, QueryResponse},
     EndUser, MessageId,
@@ -50,13 +69,22 @@ impl Metadata {
     pub async fn new(
         path: &Path,
         max_capacity: u64,
+        options: &StoreOptions,
         adult_storage_info: AdultsStorageInfo,
         reader: AdultReader,
+        erasure_coding: bool,
+        write_ack_policy: WriteAckPolicy,
     ) -> Result<Self> {
-        let blob_records = BlobRecords::new(adult_storage_info, reader);
-        let map_storage = MapStorage::new(path, max_capacity).await?;
-        let sequence_storage = SequenceStorage::new(path, max_capacity).await?;
-        let register_storage = RegisterStorage::new(path, max_capacity).await?;
+        let blob_records = BlobRecords::new(
+            path,
+            adult_storage_info,
+            reader,
+            erasure_coding,
+            write_ack_policy,
+        )?;
+        let map_storage = MapStorage::new(path, max_capacity, options).await?;
+        let sequence_storage = SequenceStorage::new(path, max_capacity, options).await?;
+        let register_storage = RegisterStorage::new(path, max_capacity, options).await?;
         let elder_stores = ElderStores::new(
             blob_records,
             map_storage,
@@ -71,7 +99,7 @@ impl Metadata {
         query: DataQuery,
         id: MessageId,
         origin: EndUser,
-    ) -> Result<NodeDuty> {
+    ) -> Result<NodeDuties> {
         self.elder_stores.read(query, id, origin).await
     }
 
@@ -99,12 +127,11 @@ impl Metadata {
             .await
     }
 
-    pub async fn retain_members_only(&mut self, members: BTreeSet<XorName>) -> Result<()> {
+    pub async fn retain_members_only(&mut self, members: BTreeSet<XorName>) -> Result<NodeDuties> {
         self.elder_stores
             .blob_records_mut()
             .retain_members_only(members)
-            .await?;
-        Ok(())
+            .await
     }
 
     pub async fn write(
@@ -112,7 +139,7 @@ impl Metadata {
         cmd: DataCmd,
         id: MessageId,
         origin: EndUser,
-    ) -> Result<NodeDuty> {
+    ) -> Result<NodeDuties> {
         self.elder_stores.write(cmd, id, origin).await
     }
 
@@ -124,14 +151,168 @@ impl Metadata {
             .await
     }
 
-    // When receiving the chunk from remaining holders, we ask new holders to store it.
-    pub async fn republish_chunk(&mut self, data: Blob) -> Result<NodeDuty> {
+    /// Removes a given node from the list of full nodes, if it's there.
+    pub async fn decrease_full_node_count_if_present(&mut self, node_name: XorName) -> Result<()> {
+        self.elder_stores
+            .blob_records_mut()
+            .decrease_full_node_count_if_present(node_name)
+            .await
+    }
+
+    // When receiving the chunk from an Adult handing it off, we ask new holders to store it, and
+    // confirm it to the Adult once they did.
+    pub async fn hand_off_chunk(&mut self, data: Blob, src: XorName) -> Result<NodeDuties> {
+        self.elder_stores
+            .blob_records_mut()
+            .hand_off_chunk(data, src)
+            .await
+    }
+
+    // When a holder found its copy of a chunk corrupt, we fetch it from the other holders and
+    // store it at that holder again.
+    pub async fn restore_chunk(&mut self, address: BlobAddress, holder: XorName) -> NodeDuties {
+        self.elder_stores
+            .blob_records_mut()
+            .restore(address, holder)
+            .await
+    }
+
+    // Holders of a sample of chunks are challenged to prove they still store them.
+    pub async fn challenge_holders(&mut self) -> NodeDuties {
+        self.elder_stores
+            .blob_records_mut()
+            .challenge_holders()
+            .await
+    }
+
+    pub async fn expire_pending_ops(&mut self) -> Result<NodeDuties> {
+        self.elder_stores
+            .blob_records_mut()
+            .expire_pending_ops()
+            .await
+    }
+
+    pub fn hedge_reads(&mut self) -> Result<NodeDuties> {
+        self.elder_stores.blob_records_mut().hedge_reads()
+    }
+
+    pub async fn audit_redundancy(&mut self) -> NodeDuties {
+        self.elder_stores
+            .blob_records_mut()
+            .audit_redundancy()
+            .await
+    }
+
+    pub async fn record_chunk_held(
+        &mut self,
+        address: BlobAddress,
+        held: bool,
+        src: XorName,
+    ) -> NodeDuties {
+        self.elder_stores
+            .blob_records_mut()
+            .record_chunk_held(address, held, src)
+            .await
+    }
+
+    pub async fn record_chunk_proof(
+        &mut self,
+        address: BlobAddress,
+        nonce: Nonce,
+        proof: Option<ChunkProof>,
+        src: XorName,
+    ) -> NodeDuties {
+        self.elder_stores
+            .blob_records_mut()
+            .record_chunk_proof(address, nonce, proof, src)
+            .await
+    }
+
+    // Erasure-coded blobs with shards at a lost Adult are rebuilt, to store those shards again.
+    pub async fn repair_shards(&mut self, lost_adult: XorName) -> Result<NodeDuties> {
         self.elder_stores
             .blob_records_mut()
-            .republish_chunk(data)
+            .repair_shards(lost_adult)
             .await
     }
 
+    pub(crate) async fn full_adults(&self) -> BTreeSet<XorName> {
+        self.elder_stores.blob_records().full_adults().await
+    }
+
+    // Chunks are moved off the Adults nearing capacity.
+    pub async fn rebalance(&mut self) -> NodeDuties {
+        self.elder_stores.blob_records_mut().rebalance().await
+    }
+
+    pub(crate) fn record_chunk_moved(
+        &mut self,
+        address: BlobAddress,
+        from: XorName,
+        to: XorName,
+    ) -> Result<()> {
+        self.elder_stores
+            .blob_records_mut()
+            .record_chunk_moved(address, from, to)
+    }
+
+    pub(crate) async fn record_storage_level(&self, adult: XorName, level: StorageLevel) {
+        self.elder_stores
+            .blob_records()
+            .record_storage_level(adult, level)
+            .await
+    }
+
+    pub(crate) fn shard_maps_of(&self, prefix: Prefix) -> Vec<(BlobAddress, ShardMap)> {
+        self.elder_stores.blob_records().shard_maps_of(prefix)
+    }
+
+    pub(crate) async fn record_placement(
+        &mut self,
+        msg_id: MessageId,
+        address: BlobAddress,
+        holders: Vec<BTreeSet<XorName>>,
+        src: XorName,
+    ) -> Result<NodeDuties> {
+        self.elder_stores
+            .blob_records_mut()
+            .record_placement(msg_id, address, holders, src)
+            .await
+    }
+
+    pub(crate) fn update_shard_maps(&mut self, maps: Vec<(BlobAddress, ShardMap)>) -> Result<()> {
+        self.elder_stores.blob_records_mut().update_shard_maps(maps)
+    }
+
+    pub(crate) fn chunk_holders_of(&self, prefix: Prefix) -> Vec<(BlobAddress, BTreeSet<XorName>)> {
+        self.elder_stores.blob_records().chunk_holders_of(prefix)
+    }
+
+    pub(crate) fn update_chunk_holders(
+        &mut self,
+        holders: Vec<(BlobAddress, BTreeSet<XorName>)>,
+    ) -> Result<()> {
+        self.elder_stores
+            .blob_records_mut()
+            .update_chunk_holders(holders)
+    }
+
+    pub(crate) fn tombstones_of(
+        &self,
+        prefix: Prefix,
+    ) -> (Vec<SequenceAddress>, Vec<RegisterAddress>) {
+        self.elder_stores.tombstones_of(prefix)
+    }
+
+    // Data deleted at other Elders is deleted here too, and not taken back from a `DataExchange`.
+    pub(crate) async fn add_tombstones(
+        &mut self,
+        sequences: Vec<SequenceAddress>,
+        registers: Vec<RegisterAddress>,
+    ) -> Result<()> {
+        self.elder_stores.add_tombstones(sequences, registers).await
+    }
+
     pub async fn get_data_exchange_packet(&self, prefix: Prefix) -> Result<DataExchange> {
         self.elder_stores.get_data_of(prefix).await
     }
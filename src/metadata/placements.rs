@@ -0,0 +1,202 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Agreement of Elders on the holders of new blobs.
+//!
+//! Writes reach holders aggregated at destination, so every Elder has to send the same ones. The
+//! holders are picked by the Elder closest to a blob, with the storage levels it heard of, which
+//! tells our other Elders of its pick. They hold the client's write until it arrives. Should it not
+//! arrive in time, they pick holders on the state all Elders agree on alone.
+
+use sn_data_types::{Blob, BlobAddress};
+use sn_messaging::{EndUser, MessageId};
+use std::{
+    collections::{BTreeSet, HashMap},
+    time::{Duration, Instant},
+};
+use xor_name::XorName;
+
+/// How long a write waits for the holders picked by the Elder closest to its blob.
+pub(super) const PLACEMENT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Holders of each piece of a blob: its chunk, or each of its shards in order.
+pub(super) type Placement = Vec<BTreeSet<XorName>>;
+
+struct HeldWrite {
+    data: Blob,
+    origin: EndUser,
+    since: Instant,
+}
+
+pub(super) struct Placements {
+    // client writes waiting for the holders of their blob
+    held: HashMap<MessageId, HeldWrite>,
+    // placements which arrived before the write they're for
+    received: HashMap<MessageId, (BlobAddress, Placement, Instant)>,
+}
+
+impl Placements {
+    pub(super) fn new() -> Self {
+        Self {
+            held: HashMap::new(),
+            received: HashMap::new(),
+        }
+    }
+
+    /// Holds the write `msg_id` until the holders of its blob arrive. Returns them if they did
+    /// already, along with the write.
+    pub(super) fn hold(
+        &mut self,
+        msg_id: MessageId,
+        data: Blob,
+        origin: EndUser,
+        now: Instant,
+    ) -> Option<(Blob, EndUser, Placement)> {
+        match self.received.remove(&msg_id) {
+            Some((address, placement, _)) if address == *data.address() => {
+                Some((data, origin, placement))
+            }
+            _ => {
+                let _ = self.held.entry(msg_id).or_insert(HeldWrite {
+                    data,
+                    origin,
+                    since: now,
+                });
+                None
+            }
+        }
+    }
+
+    /// Records the holders of the blob at `address` of the write `msg_id`. Returns the write, if
+    /// it was held.
+    pub(super) fn place(
+        &mut self,
+        msg_id: MessageId,
+        address: BlobAddress,
+        placement: Placement,
+        now: Instant,
+    ) -> Option<(Blob, EndUser, Placement)> {
+        match self.held.get(&msg_id) {
+            Some(write) if *write.data.address() == address => {
+                let write = self.held.remove(&msg_id)?;
+                Some((write.data, write.origin, placement))
+            }
+            Some(_) => None,
+            None => {
+                let _ = self.received.insert(msg_id, (address, placement, now));
+                None
+            }
+        }
+    }
+
+    /// Writes which waited for their holders for too long, to place on agreed state alone.
+    /// Placements whose writes never arrived are dropped.
+    pub(super) fn expire(&mut self, now: Instant) -> Vec<(MessageId, Blob, EndUser)> {
+        let is_expired = |since: Instant| now.saturating_duration_since(since) >= PLACEMENT_TIMEOUT;
+        self.received.retain(|_, (_, _, since)| !is_expired(*since));
+        let expired = self
+            .held
+            .iter()
+            .filter(|(_, write)| is_expired(write.since))
+            .map(|(msg_id, _)| *msg_id)
+            .collect::<Vec<_>>();
+        expired
+            .into_iter()
+            .filter_map(|msg_id| {
+                let write = self.held.remove(&msg_id)?;
+                Some((msg_id, write.data, write.origin))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Placement, Placements, PLACEMENT_TIMEOUT};
+    use crate::capacity::{select_write_holders, StorageLevel};
+    use bls::SecretKey;
+    use sn_data_types::{Blob, PublicBlob, PublicKey};
+    use sn_messaging::{EndUser, MessageId};
+    use std::{
+        collections::{BTreeMap, BTreeSet},
+        time::Instant,
+    };
+    use xor_name::XorName;
+
+    const COPIES: usize = 4;
+
+    fn client() -> EndUser {
+        EndUser::AllClients(PublicKey::from(SecretKey::random().public_key()))
+    }
+
+    fn place(
+        blob: &Blob,
+        adults: &[XorName],
+        levels: &BTreeMap<XorName, StorageLevel>,
+    ) -> Placement {
+        let holders = select_write_holders(blob.name(), adults, &BTreeSet::new(), levels, COPIES);
+        vec![holders.into_iter().collect()]
+    }
+
+    #[test]
+    fn elders_hearing_of_different_levels_use_the_same_holders() {
+        let blob = Blob::Public(PublicBlob::new(vec![1, 2, 3]));
+        let adults = (0..10).map(|_| XorName::random()).collect::<Vec<_>>();
+        let mut by_distance = adults.clone();
+        by_distance.sort_by(|lhs, rhs| blob.name().cmp_distance(lhs, rhs));
+        let level = |used| StorageLevel { used, max: 100 };
+        // The leader heard the closest Adults filled up, the other Elder didn't yet.
+        let other_levels = adults
+            .iter()
+            .map(|adult| (*adult, level(10)))
+            .collect::<BTreeMap<_, _>>();
+        let mut leader_levels = other_levels.clone();
+        for adult in by_distance.iter().take(2) {
+            let _ = leader_levels.insert(*adult, level(90));
+        }
+        let leader_placement = place(&blob, &adults, &leader_levels);
+        assert_ne!(leader_placement, place(&blob, &adults, &other_levels));
+
+        let (msg_id, origin, now) = (MessageId::new(), client(), Instant::now());
+        let mut placements = Placements::new();
+        assert!(placements.hold(msg_id, blob.clone(), origin, now).is_none());
+        let (_, _, placement) = placements
+            .place(msg_id, *blob.address(), leader_placement.clone(), now)
+            .expect("held write");
+        assert_eq!(placement, leader_placement);
+
+        // The placement arriving first, the write isn't held at all.
+        let other_msg_id = MessageId::new();
+        assert!(placements
+            .place(other_msg_id, *blob.address(), leader_placement.clone(), now)
+            .is_none());
+        let (_, _, placement) = placements
+            .hold(other_msg_id, blob, origin, now)
+            .expect("placed write");
+        assert_eq!(placement, leader_placement);
+    }
+
+    #[test]
+    fn writes_left_without_holders_expire() {
+        let blob = Blob::Public(PublicBlob::new(vec![1, 2, 3]));
+        let (msg_id, origin, now) = (MessageId::new(), client(), Instant::now());
+        let mut placements = Placements::new();
+        assert!(placements.hold(msg_id, blob.clone(), origin, now).is_none());
+        // A placement for another blob doesn't release the write.
+        let other = Blob::Public(PublicBlob::new(vec![4, 5, 6]));
+        assert!(placements
+            .place(msg_id, *other.address(), vec![BTreeSet::new()], now)
+            .is_none());
+
+        assert!(placements.expire(now).is_empty());
+        let expired = placements.expire(now + PLACEMENT_TIMEOUT);
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].0, msg_id);
+        assert!(placements.expire(now + PLACEMENT_TIMEOUT).is_empty());
+    }
+}
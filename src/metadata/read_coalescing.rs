@@ -0,0 +1,117 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Coalescing of concurrent reads of the same public blob.
+//!
+//! The first read of a blob is the only one its holders are queried for. Reads of the blob
+//! arriving before that one is answered wait on it, and get the same answer. Private blobs are
+//! read for each client on its own, as their holders check the client owns them.
+
+use sn_data_types::BlobAddress;
+use sn_messaging::{EndUser, MessageId};
+use std::collections::{hash_map::Entry, HashMap};
+
+struct CoalescedRead {
+    // the read the holders were queried for
+    leader: MessageId,
+    followers: Vec<(MessageId, EndUser)>,
+}
+
+pub(super) struct CoalescedReads {
+    reads: HashMap<BlobAddress, CoalescedRead>,
+}
+
+impl CoalescedReads {
+    pub(super) fn new() -> Self {
+        Self {
+            reads: HashMap::new(),
+        }
+    }
+
+    /// Has the read `msg_id` wait on the read of the same blob under way. Returns false if there's
+    /// none, the holders being queried for `msg_id` then.
+    pub(super) fn join(
+        &mut self,
+        address: BlobAddress,
+        msg_id: MessageId,
+        origin: EndUser,
+    ) -> bool {
+        if !address.is_public() {
+            return false;
+        }
+        match self.reads.entry(address) {
+            Entry::Vacant(entry) => {
+                let _ = entry.insert(CoalescedRead {
+                    leader: msg_id,
+                    followers: vec![],
+                });
+                false
+            }
+            Entry::Occupied(mut entry) => {
+                let read = entry.get_mut();
+                if read.leader != msg_id && read.followers.iter().all(|(id, _)| *id != msg_id) {
+                    read.followers.push((msg_id, origin));
+                }
+                true
+            }
+        }
+    }
+
+    /// Ends the read `msg_id` of the blob at `address`, returning the reads which waited on it.
+    pub(super) fn end(
+        &mut self,
+        address: &BlobAddress,
+        msg_id: &MessageId,
+    ) -> Vec<(MessageId, EndUser)> {
+        match self.reads.get(address) {
+            Some(read) if read.leader == *msg_id => self
+                .reads
+                .remove(address)
+                .map(|read| read.followers)
+                .unwrap_or_default(),
+            _ => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CoalescedReads;
+    use bls::SecretKey;
+    use sn_data_types::{Blob, PrivateBlob, PublicBlob, PublicKey};
+    use sn_messaging::{EndUser, MessageId};
+
+    fn client() -> EndUser {
+        EndUser::AllClients(PublicKey::from(SecretKey::random().public_key()))
+    }
+
+    #[test]
+    fn reads_under_way_are_joined_until_answered() {
+        let address = *Blob::Public(PublicBlob::new(vec![1, 2, 3])).address();
+        let mut reads = CoalescedReads::new();
+        let (first, second, third) = (MessageId::new(), MessageId::new(), MessageId::new());
+        let (origin, other) = (client(), client());
+
+        assert!(!reads.join(address, first, origin));
+        assert!(reads.join(address, second, other));
+        // Reads seen again wait only once.
+        assert!(reads.join(address, second, other));
+        assert!(reads.join(address, first, origin));
+
+        // Only the read the holders were queried for ends the others.
+        assert!(reads.end(&address, &second).is_empty());
+        assert_eq!(reads.end(&address, &first), vec![(second, other)]);
+        assert!(!reads.join(address, third, origin));
+
+        // Private blobs are read for each client on its own.
+        let owner = PublicKey::from(SecretKey::random().public_key());
+        let private = *Blob::Private(PrivateBlob::new(vec![1, 2, 3], owner)).address();
+        assert!(!reads.join(private, first, origin));
+        assert!(!reads.join(private, second, origin));
+    }
+}
@@ -0,0 +1,185 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Moves of chunks off Adults nearing capacity.
+//!
+//! A chunk is read from the Adult it's moved off, the source, then written to an emptier Adult
+//! among the candidate holders of the chunk, the destination. The source lets go of its copy only
+//! once the destination confirmed storing it, so the chunk never has fewer copies meanwhile.
+
+use crate::{capacity::StorageLevel, Result};
+use sn_data_types::BlobAddress;
+use sn_messaging::MessageId;
+use std::collections::{BTreeSet, HashMap};
+use xor_name::XorName;
+
+/// Percentage of its storage an Adult uses past which chunks are moved off it.
+const HIGH_WATER_PERCENT: u128 = 40;
+
+/// Percentage of its storage an Adult uses at most to have chunks moved to it. The gap to
+/// `HIGH_WATER_PERCENT` keeps chunks from being moved back and forth.
+const LOW_WATER_PERCENT: u128 = 30;
+
+/// Number of chunks being moved at most at any time.
+pub(super) const MAX_MOVES: usize = 8;
+
+/// Whether chunks are to be moved off an Adult at `level`.
+pub(super) fn is_above_high_water(level: &StorageLevel) -> bool {
+    level.used as u128 * 100 > level.max as u128 * HIGH_WATER_PERCENT
+}
+
+/// Whether chunks can be moved to an Adult at `level`.
+pub(super) fn has_room(level: &StorageLevel) -> bool {
+    level.used as u128 * 100 <= level.max as u128 * LOW_WATER_PERCENT
+}
+
+/// A chunk being moved from `source` to `destination`.
+#[derive(Clone, Debug, PartialEq)]
+pub(super) struct Move {
+    pub(super) address: BlobAddress,
+    pub(super) source: XorName,
+    pub(super) destination: XorName,
+}
+
+pub(super) struct Rebalancing {
+    // moves by the id of the read of the chunk from its source
+    fetching: HashMap<MessageId, Move>,
+    // moves by the id of the write of the chunk to its destination
+    writing: HashMap<MessageId, Move>,
+}
+
+impl Rebalancing {
+    pub(super) fn new() -> Self {
+        Self {
+            fetching: HashMap::new(),
+            writing: HashMap::new(),
+        }
+    }
+
+    /// Number of moves which can still be started.
+    pub(super) fn room(&self) -> usize {
+        MAX_MOVES.saturating_sub(self.fetching.len() + self.writing.len())
+    }
+
+    pub(super) fn is_moving(&self, address: &BlobAddress) -> bool {
+        self.fetching
+            .values()
+            .chain(self.writing.values())
+            .any(|chunk_move| chunk_move.address == *address)
+    }
+
+    /// Starts moving a chunk, returning the id to read it from its source under. Returns `None` if
+    /// the chunk is being moved already, or too many chunks are.
+    pub(super) fn start(&mut self, chunk_move: Move) -> Option<MessageId> {
+        if self.room() == 0 || self.is_moving(&chunk_move.address) {
+            return None;
+        }
+        let msg_id = MessageId::new();
+        let _ = self.fetching.insert(msg_id, chunk_move);
+        Some(msg_id)
+    }
+
+    pub(super) fn is_fetch(&self, msg_id: &MessageId) -> bool {
+        self.fetching.contains_key(msg_id)
+    }
+
+    /// The chunk read under `fetch_msg_id` was read, returning the id to write it to its
+    /// destination under, with the move.
+    pub(super) fn fetched(
+        &mut self,
+        fetch_msg_id: &MessageId,
+    ) -> Result<Option<(MessageId, Move)>> {
+        let chunk_move = match self.fetching.remove(fetch_msg_id) {
+            Some(chunk_move) => chunk_move,
+            None => return Ok(None),
+        };
+        let msg_id = MessageId::from_content(&(*fetch_msg_id, chunk_move.destination))?;
+        let _ = self.writing.insert(msg_id, chunk_move.clone());
+        Ok(Some((msg_id, chunk_move)))
+    }
+
+    /// Ends the move the read or write `msg_id` is part of, returning it.
+    pub(super) fn end(&mut self, msg_id: &MessageId) -> Option<Move> {
+        self.fetching
+            .remove(msg_id)
+            .or_else(|| self.writing.remove(msg_id))
+    }
+
+    /// Ends the move the write `msg_id` is part of, returning it.
+    pub(super) fn end_write(&mut self, msg_id: &MessageId) -> Option<Move> {
+        self.writing.remove(msg_id)
+    }
+
+    /// Drops the moves from or to Adults which left.
+    pub(super) fn retain_members_only(&mut self, members: &BTreeSet<XorName>) {
+        let is_member = |chunk_move: &Move| {
+            members.contains(&chunk_move.source) && members.contains(&chunk_move.destination)
+        };
+        self.fetching.retain(|_, chunk_move| is_member(chunk_move));
+        self.writing.retain(|_, chunk_move| is_member(chunk_move));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{has_room, is_above_high_water, Move, Rebalancing, MAX_MOVES};
+    use crate::{capacity::StorageLevel, Result};
+    use sn_data_types::{Blob, PublicBlob};
+    use std::collections::BTreeSet;
+    use xor_name::XorName;
+
+    fn move_of(byte: u8) -> Move {
+        Move {
+            address: *Blob::Public(PublicBlob::new(vec![byte])).address(),
+            source: XorName::random(),
+            destination: XorName::random(),
+        }
+    }
+
+    #[test]
+    fn chunks_are_moved_once_read_then_written() -> Result<()> {
+        let mut rebalancing = Rebalancing::new();
+        let chunk_move = move_of(0);
+        let fetch = rebalancing.start(chunk_move.clone()).expect("not started");
+        assert!(rebalancing.is_fetch(&fetch));
+        // A chunk is moved once at a time.
+        assert_eq!(rebalancing.start(chunk_move.clone()), None);
+
+        let (write, fetched) = rebalancing.fetched(&fetch)?.expect("not fetched");
+        assert_eq!(fetched, chunk_move);
+        assert!(!rebalancing.is_fetch(&fetch));
+        assert!(rebalancing.is_moving(&chunk_move.address));
+        assert_eq!(rebalancing.end_write(&fetch), None);
+        assert_eq!(rebalancing.end_write(&write), Some(chunk_move.clone()));
+        assert!(!rebalancing.is_moving(&chunk_move.address));
+
+        // Only so many chunks are moved at once.
+        for byte in 1..=MAX_MOVES as u8 {
+            assert!(rebalancing.start(move_of(byte)).is_some());
+        }
+        assert_eq!(rebalancing.room(), 0);
+        assert_eq!(rebalancing.start(move_of(0)), None);
+
+        // Moves between Adults which left are dropped.
+        rebalancing.retain_members_only(&BTreeSet::new());
+        assert_eq!(rebalancing.room(), MAX_MOVES);
+
+        Ok(())
+    }
+
+    #[test]
+    fn chunks_move_from_nearly_full_adults_to_emptier_ones() {
+        let level = |used| StorageLevel { used, max: 100 };
+        assert!(is_above_high_water(&level(41)));
+        assert!(!is_above_high_water(&level(40)));
+        assert!(has_room(&level(30)));
+        assert!(!has_room(&level(31)));
+        // Adults in between are neither.
+        assert!(!is_above_high_water(&level(35)) && !has_room(&level(35)));
+    }
+}
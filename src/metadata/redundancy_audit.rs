@@ -0,0 +1,221 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Audits of how many copies of a chunk its holders actually store.
+//!
+//! Churn and client reads only reveal missing copies by chance. An audit asks all the expected
+//! holders of a randomly picked chunk whether they hold it, so chunks below `CHUNK_COPY_COUNT`
+//! copies are found, and stored again, before the last copy goes.
+
+use sn_data_types::BlobAddress;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::{self, Display, Formatter},
+};
+use xor_name::XorName;
+
+/// Number of chunks audited on each round.
+pub(super) const AUDITS_PER_ROUND: usize = 8;
+
+struct Audit {
+    holders: BTreeSet<XorName>,
+    answers: BTreeMap<XorName, bool>,
+}
+
+/// Outcome of an audit. Holders which didn't answer in time count as not holding the chunk.
+#[derive(Debug, PartialEq)]
+pub(super) struct AuditResult {
+    pub(super) address: BlobAddress,
+    /// Number of holders asked, which is less than `CHUNK_COPY_COUNT` in sections with fewer
+    /// Adults.
+    pub(super) expected: usize,
+    pub(super) held_by: BTreeSet<XorName>,
+}
+
+/// Copies found of the chunks audited so far.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(super) struct RedundancyStats {
+    /// Number of chunks audited, by the number of copies found.
+    pub(super) by_copies: BTreeMap<usize, u64>,
+    /// Number of chunks found with too few copies, and stored again.
+    pub(super) replicated: u64,
+}
+
+impl RedundancyStats {
+    fn audited(&self) -> u64 {
+        self.by_copies.values().sum()
+    }
+
+    fn lost(&self) -> u64 {
+        self.by_copies.get(&0).copied().unwrap_or(0)
+    }
+}
+
+impl Display for RedundancyStats {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        let copies: u64 = self
+            .by_copies
+            .iter()
+            .map(|(copies, count)| *copies as u64 * count)
+            .sum();
+        let audited = self.audited();
+        let average = if audited == 0 {
+            0.0
+        } else {
+            copies as f64 / audited as f64
+        };
+        write!(
+            formatter,
+            "{} chunks audited, {:.2} copies on average, {} replicated, {} lost, by copies: {:?}",
+            audited,
+            average,
+            self.replicated,
+            self.lost(),
+            self.by_copies
+        )
+    }
+}
+
+pub(super) struct RedundancyAudit {
+    pending: HashMap<BlobAddress, Audit>,
+    stats: RedundancyStats,
+}
+
+impl RedundancyAudit {
+    pub(super) fn new() -> Self {
+        Self {
+            pending: HashMap::new(),
+            stats: RedundancyStats::default(),
+        }
+    }
+
+    /// Starts auditing the chunk at `address`, returning false if it's already being audited.
+    pub(super) fn start(&mut self, address: BlobAddress, holders: BTreeSet<XorName>) -> bool {
+        if self.pending.contains_key(&address) {
+            return false;
+        }
+        let _ = self.pending.insert(
+            address,
+            Audit {
+                holders,
+                answers: BTreeMap::new(),
+            },
+        );
+        true
+    }
+
+    /// Records the answer of a holder, returning the result once all holders answered.
+    pub(super) fn record(
+        &mut self,
+        address: &BlobAddress,
+        holder: XorName,
+        held: bool,
+    ) -> Option<AuditResult> {
+        let audit = self.pending.get_mut(address)?;
+        if !audit.holders.contains(&holder) {
+            return None;
+        }
+        let _ = audit.answers.insert(holder, held);
+        if audit.answers.len() < audit.holders.len() {
+            return None;
+        }
+        let audit = self.pending.remove(address)?;
+        Some(self.conclude(*address, audit))
+    }
+
+    /// Ends all pending audits.
+    pub(super) fn expire(&mut self) -> Vec<AuditResult> {
+        let pending = self.pending.drain().collect::<Vec<_>>();
+        pending
+            .into_iter()
+            .map(|(address, audit)| self.conclude(address, audit))
+            .collect()
+    }
+
+    /// Counts a chunk found with too few copies as stored again.
+    pub(super) fn record_replication(&mut self) {
+        self.stats.replicated += 1;
+    }
+
+    /// Stops expecting answers from holders which left.
+    pub(super) fn retain_members_only(&mut self, members: &BTreeSet<XorName>) {
+        for audit in self.pending.values_mut() {
+            audit.holders.retain(|holder| members.contains(holder));
+            audit.answers.retain(|holder, _| members.contains(holder));
+        }
+    }
+
+    pub(super) fn stats(&self) -> &RedundancyStats {
+        &self.stats
+    }
+
+    fn conclude(&mut self, address: BlobAddress, audit: Audit) -> AuditResult {
+        let held_by = audit
+            .answers
+            .iter()
+            .filter(|(_, held)| **held)
+            .map(|(holder, _)| *holder)
+            .collect::<BTreeSet<_>>();
+        *self.stats.by_copies.entry(held_by.len()).or_insert(0) += 1;
+        AuditResult {
+            address,
+            expected: audit.holders.len(),
+            held_by,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AuditResult, RedundancyAudit};
+    use sn_data_types::{Blob, PublicBlob};
+    use std::collections::BTreeSet;
+    use xor_name::XorName;
+
+    #[test]
+    fn copies_are_counted_from_the_answers() {
+        let address = *Blob::Public(PublicBlob::new(vec![1, 2, 3])).address();
+        let other = *Blob::Public(PublicBlob::new(vec![4, 5, 6])).address();
+        let holders = (0..4).map(|_| XorName::random()).collect::<Vec<_>>();
+        let mut audit = RedundancyAudit::new();
+
+        assert!(audit.start(address, holders.iter().copied().collect()));
+        assert!(!audit.start(address, BTreeSet::new()));
+        assert!(audit.start(other, holders.iter().copied().collect()));
+
+        assert_eq!(audit.record(&address, holders[0], true), None);
+        // Only the expected holders are asked.
+        assert_eq!(audit.record(&address, XorName::random(), true), None);
+        assert_eq!(audit.record(&address, holders[1], false), None);
+        assert_eq!(audit.record(&address, holders[2], true), None);
+        let result = audit.record(&address, holders[3], false);
+        assert_eq!(
+            result,
+            Some(AuditResult {
+                address,
+                expected: 4,
+                held_by: vec![holders[0], holders[2]].into_iter().collect(),
+            })
+        );
+
+        // Holders which never answer don't hold the chunk.
+        assert_eq!(audit.record(&other, holders[0], true), None);
+        assert_eq!(
+            audit.expire(),
+            vec![AuditResult {
+                address: other,
+                expected: 4,
+                held_by: vec![holders[0]].into_iter().collect(),
+            }]
+        );
+        assert_eq!(
+            audit.stats().by_copies,
+            vec![(1, 1), (2, 1)].into_iter().collect()
+        );
+    }
+}
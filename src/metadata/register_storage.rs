@@ -6,8 +6,9 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
+use super::tombstones::Tombstones;
 use crate::{
-    chunk_store::RegisterChunkStore,
+    chunk_store::{RegisterChunkStore, StoreOptions},
     error::convert_to_error_message,
     node_ops::{NodeDuty, OutgoingMsg},
     Error, Result,
@@ -18,22 +19,50 @@ use sn_messaging::{
     client::{CmdError, Message, QueryResponse, RegisterRead, RegisterWrite},
     Aggregation, DstLocation, EndUser, MessageId,
 };
+use sn_routing::Prefix;
 
 use std::{
     fmt::{self, Display, Formatter},
     path::Path,
 };
 
+const REGISTER_TOMBSTONES_DB_NAME: &str = "register_tombstones.db";
+
 /// Operations over the data type Register.
 pub(super) struct RegisterStorage {
     chunks: RegisterChunkStore,
+    // Registers deleted, which aren't stored again.
+    tombstones: Tombstones,
 }
 
 impl RegisterStorage {
-    pub(super) async fn new(path: &Path, max_capacity: u64) -> Result<Self> {
-        let chunks = RegisterChunkStore::new(path, max_capacity).await?;
+    pub(super) async fn new(
+        path: &Path,
+        max_capacity: u64,
+        options: &StoreOptions,
+    ) -> Result<Self> {
+        let chunks = RegisterChunkStore::new(path, max_capacity, options).await?;
+        let tombstones = Tombstones::new(path, REGISTER_TOMBSTONES_DB_NAME)?;
+
+        Ok(Self { chunks, tombstones })
+    }
 
-        Ok(Self { chunks })
+    /// Addresses of the Registers of `prefix` which were deleted.
+    pub(super) fn tombstones_of(&self, prefix: Prefix) -> Vec<Address> {
+        self.tombstones
+            .all::<Address>()
+            .into_iter()
+            .filter(|address| prefix.matches(address.name()))
+            .collect()
+    }
+
+    /// Takes the tombstones of Registers deleted at another Elder, deleting those we still hold.
+    pub(super) async fn add_tombstones(&mut self, addresses: Vec<Address>) -> Result<()> {
+        for address in addresses {
+            self.tombstones.add(&address)?;
+            self.chunks.delete(&address).await?;
+        }
+        Ok(())
     }
 
     pub(super) async fn read(
@@ -79,8 +108,13 @@ impl RegisterStorage {
         msg_id: MessageId,
         origin: EndUser,
     ) -> Result<NodeDuty> {
-        let result = if self.chunks.has(data.address()) {
+        let result = if self.chunks.has(data.address()).await {
             Err(Error::DataExists)
+        } else if self.tombstones.contains(data.address()) {
+            Err(Error::InvalidOperation(format!(
+                "Register at {:?} was deleted",
+                data.address()
+            )))
         } else {
             self.chunks.put(&data).await
         };
@@ -88,7 +122,7 @@ impl RegisterStorage {
     }
 
     async fn get(&self, address: Address, msg_id: MessageId, origin: EndUser) -> Result<NodeDuty> {
-        let result = match self.get_chunk(address, Action::Read, origin) {
+        let result = match self.get_chunk(address, Action::Read, origin).await {
             Ok(res) => Ok(res),
             Err(error) => Err(convert_to_error_message(error)?),
         };
@@ -105,8 +139,13 @@ impl RegisterStorage {
         }))
     }
 
-    fn get_chunk(&self, address: Address, action: Action, origin: EndUser) -> Result<Register> {
-        let data = self.chunks.get(&address)?;
+    async fn get_chunk(
+        &self,
+        address: Address,
+        action: Action,
+        origin: EndUser,
+    ) -> Result<Register> {
+        let data = self.chunks.get(&address).await?;
         data.check_permission(action, Some(*origin.id()))?;
         Ok(data)
     }
@@ -117,23 +156,16 @@ impl RegisterStorage {
         msg_id: MessageId,
         origin: EndUser,
     ) -> Result<NodeDuty> {
-        let result = match self.chunks.get(&address).and_then(|register| {
-            // TODO - Register::check_permission() doesn't support Delete yet in safe-nd
-            if register.address().is_public() {
-                return Err(Error::InvalidMessage(
-                    msg_id,
-                    "Cannot delete public Register".to_string(),
-                ));
-            }
-
-            let public_key = *origin.id();
-            if public_key != register.owner() {
-                Err(Error::InvalidOwners(public_key))
-            } else {
-                Ok(())
-            }
-        }) {
-            Ok(()) => self.chunks.delete(&address).await,
+        let result = match self
+            .chunks
+            .get(&address)
+            .await
+            .and_then(|register| check_delete_permission(&register, origin))
+        {
+            Ok(()) => match self.tombstones.add(&address) {
+                Ok(()) => self.chunks.delete(&address).await,
+                Err(error) => Err(error),
+            },
             Err(error) => Err(error),
         };
 
@@ -148,6 +180,7 @@ impl RegisterStorage {
     ) -> Result<NodeDuty> {
         let result = match self
             .get_chunk(address, Action::Read, origin)
+            .await
             .and_then(|register| register.read(Some(*origin.id())).map_err(Error::from))
         {
             Ok(res) => Ok(res),
@@ -172,7 +205,7 @@ impl RegisterStorage {
         msg_id: MessageId,
         origin: EndUser,
     ) -> Result<NodeDuty> {
-        let result = match self.get_chunk(address, Action::Read, origin) {
+        let result = match self.get_chunk(address, Action::Read, origin).await {
             Ok(res) => Ok(res.owner()),
             Err(error) => Err(convert_to_error_message(error)?),
         };
@@ -198,6 +231,7 @@ impl RegisterStorage {
     ) -> Result<NodeDuty> {
         let result = match self
             .get_chunk(address, Action::Read, origin)
+            .await
             .and_then(|register| {
                 register
                     .permissions(user, Some(*origin.id()))
@@ -227,6 +261,7 @@ impl RegisterStorage {
     ) -> Result<NodeDuty> {
         let result = match self
             .get_chunk(address, Action::Read, origin)
+            .await
             .and_then(|register| {
                 register
                     .policy(Some(*origin.id()))
@@ -284,7 +319,7 @@ impl RegisterStorage {
         F: FnOnce(Register) -> Result<Register>,
     {
         info!("Getting Register chunk for Edit");
-        let result = self.get_chunk(address, action, origin)?;
+        let result = self.get_chunk(address, action, origin).await?;
         let sequence = write_fn(result)?;
         info!("Edited Register chunk successfully");
         self.chunks.put(&sequence).await
@@ -316,8 +351,65 @@ impl RegisterStorage {
     }
 }
 
+/// Public Registers can't be deleted, as others may rely on them staying around. Private ones can
+/// be deleted by their owner, and by the keys the owner delegated full access to, reading and
+/// writing.
+fn check_delete_permission(register: &Register, origin: EndUser) -> Result<()> {
+    if register.address().is_public() {
+        return Err(Error::InvalidOperation(
+            "Cannot delete public Register".to_string(),
+        ));
+    }
+    let public_key = Some(*origin.id());
+    register.check_permission(Action::Read, public_key)?;
+    register.check_permission(Action::Write, public_key)?;
+    Ok(())
+}
+
 impl Display for RegisterStorage {
     fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
         write!(formatter, "RegisterStorage")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::RegisterStorage;
+    use crate::{chunk_store::StoreOptions, Error, Result, StorageBackend};
+    use bls::SecretKey;
+    use sn_data_types::{register::Register, PublicKey};
+    use sn_messaging::{client::RegisterWrite, EndUser, MessageId};
+    use sn_routing::Prefix;
+    use tempdir::TempDir;
+    use xor_name::XorName;
+
+    const MAX_CAPACITY: u64 = 1024 * 1024;
+
+    #[tokio::test]
+    async fn deleted_registers_are_not_stored_again() -> Result<()> {
+        let root = TempDir::new("test").map_err(|e| Error::TempDirCreationFailed(e.to_string()))?;
+        let options = StoreOptions {
+            backend: StorageBackend::Memory,
+            ..Default::default()
+        };
+        let mut storage = RegisterStorage::new(root.path(), MAX_CAPACITY, &options).await?;
+        let owner = PublicKey::from(SecretKey::random().public_key());
+        let origin = EndUser::AllClients(owner);
+        let register = Register::new_private(owner, XorName::random(), 0, None);
+        let address = *register.address();
+
+        let new = RegisterWrite::New(register.clone());
+        let _ = storage.write(new, MessageId::new(), origin).await?;
+        assert!(storage.chunks.has(&address).await);
+        let delete = RegisterWrite::Delete(address);
+        let _ = storage.write(delete, MessageId::new(), origin).await?;
+        assert!(!storage.chunks.has(&address).await);
+        assert_eq!(storage.tombstones_of(Prefix::default()), vec![address]);
+
+        let new = RegisterWrite::New(register);
+        let _ = storage.write(new, MessageId::new(), origin).await?;
+        assert!(!storage.chunks.has(&address).await);
+
+        Ok(())
+    }
+}
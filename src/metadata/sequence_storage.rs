@@ -6,8 +6,9 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
+use super::tombstones::Tombstones;
 use crate::{
-    chunk_store::SequenceChunkStore,
+    chunk_store::{SequenceChunkStore, StoreOptions},
     error::convert_to_error_message,
     node_ops::{NodeDuty, OutgoingMsg},
     Error, Result,
@@ -27,27 +28,37 @@ use std::{
     path::Path,
 };
 
+const SEQUENCE_TOMBSTONES_DB_NAME: &str = "sequence_tombstones.db";
+
 /// Operations over the data type Sequence.
 pub(super) struct SequenceStorage {
     chunks: SequenceChunkStore,
+    // Sequences deleted, which aren't stored again, nor taken back from other Elders.
+    tombstones: Tombstones,
 }
 
 impl SequenceStorage {
-    pub(super) async fn new(path: &Path, max_capacity: u64) -> Result<Self> {
-        let chunks = SequenceChunkStore::new(path, max_capacity).await?;
-        Ok(Self { chunks })
+    pub(super) async fn new(
+        path: &Path,
+        max_capacity: u64,
+        options: &StoreOptions,
+    ) -> Result<Self> {
+        let chunks = SequenceChunkStore::new(path, max_capacity, options).await?;
+        let tombstones = Tombstones::new(path, SEQUENCE_TOMBSTONES_DB_NAME)?;
+        Ok(Self { chunks, tombstones })
     }
 
-    pub fn get_data_of(&self, prefix: Prefix) -> SequenceDataExchange {
-        let store = &self.chunks;
-        let data = self
-            .chunks
-            .keys()
-            .iter()
-            .filter(|address| prefix.matches(address.name()))
-            .filter_map(|key| store.get(&key).ok())
-            .map(|seq| (*seq.address(), seq))
-            .collect();
+    pub async fn get_data_of(&self, prefix: Prefix) -> SequenceDataExchange {
+        let mut data = vec![];
+        for address in self.chunks.keys().await {
+            if !prefix.matches(address.name()) || self.tombstones.contains(&address) {
+                continue;
+            }
+            if let Ok(seq) = self.chunks.get(&address).await {
+                data.push((*seq.address(), seq));
+            }
+        }
+        let data = data.into_iter().collect();
         SequenceDataExchange(data)
     }
 
@@ -56,13 +67,35 @@ impl SequenceStorage {
         let chunkstore = &mut self.chunks;
         let SequenceDataExchange(data) = seq_data;
 
-        for (_key, value) in data {
+        for (key, value) in data {
+            if self.tombstones.contains(&key) {
+                debug!("Not taking back deleted Sequence {:?}", key);
+                continue;
+            }
             chunkstore.put(&value).await?;
         }
 
         Ok(())
     }
 
+    /// Addresses of the Sequences of `prefix` which were deleted.
+    pub(super) fn tombstones_of(&self, prefix: Prefix) -> Vec<SequenceAddress> {
+        self.tombstones
+            .all::<SequenceAddress>()
+            .into_iter()
+            .filter(|address| prefix.matches(address.name()))
+            .collect()
+    }
+
+    /// Takes the tombstones of Sequences deleted at another Elder, deleting those we still hold.
+    pub(super) async fn add_tombstones(&mut self, addresses: Vec<SequenceAddress>) -> Result<()> {
+        for address in addresses {
+            self.tombstones.add(&address)?;
+            self.chunks.delete(&address).await?;
+        }
+        Ok(())
+    }
+
     pub(super) async fn read(
         &self,
         read: &SequenceRead,
@@ -107,8 +140,13 @@ impl SequenceStorage {
         msg_id: MessageId,
         origin: EndUser,
     ) -> Result<NodeDuty> {
-        let result = if self.chunks.has(data.address()) {
+        let result = if self.chunks.has(data.address()).await {
             Err(Error::DataExists)
+        } else if self.tombstones.contains(data.address()) {
+            Err(Error::InvalidOperation(format!(
+                "Sequence at {:?} was deleted",
+                data.address()
+            )))
         } else {
             self.chunks.put(&data).await
         };
@@ -121,7 +159,7 @@ impl SequenceStorage {
         msg_id: MessageId,
         origin: EndUser,
     ) -> Result<NodeDuty> {
-        let result = match self.get_chunk(address, SequenceAction::Read, origin) {
+        let result = match self.get_chunk(address, SequenceAction::Read, origin).await {
             Ok(res) => Ok(res),
             Err(error) => Err(convert_to_error_message(error)?),
         };
@@ -137,13 +175,13 @@ impl SequenceStorage {
         }))
     }
 
-    fn get_chunk(
+    async fn get_chunk(
         &self,
         address: SequenceAddress,
         action: SequenceAction,
         origin: EndUser,
     ) -> Result<Sequence> {
-        let data = self.chunks.get(&address)?;
+        let data = self.chunks.get(&address).await?;
         data.check_permission(action, Some(*origin.id()))?;
         Ok(data)
     }
@@ -154,25 +192,16 @@ impl SequenceStorage {
         msg_id: MessageId,
         origin: EndUser,
     ) -> Result<NodeDuty> {
-        let result = match self.chunks.get(&address).and_then(|sequence| {
-            // TODO - Sequence::check_permission() doesn't support Delete yet in safe-nd
-            if sequence.address().is_public() {
-                return Err(Error::InvalidMessage(
-                    msg_id,
-                    "Sequence::check_permission() doesn't support Delete yet in safe-nd"
-                        .to_string(),
-                ));
-            }
-
-            let public_key = *origin.id();
-            let policy = sequence.private_policy(Some(public_key))?;
-            if public_key != policy.owner {
-                Err(Error::InvalidOwners(public_key))
-            } else {
-                Ok(())
-            }
-        }) {
-            Ok(()) => self.chunks.delete(&address).await,
+        let result = match self
+            .chunks
+            .get(&address)
+            .await
+            .and_then(|sequence| check_delete_permission(&sequence, origin))
+        {
+            Ok(()) => match self.tombstones.add(&address) {
+                Ok(()) => self.chunks.delete(&address).await,
+                Err(error) => Err(error),
+            },
             Err(error) => Err(error),
         };
 
@@ -188,6 +217,7 @@ impl SequenceStorage {
     ) -> Result<NodeDuty> {
         let result = match self
             .get_chunk(address, SequenceAction::Read, origin)
+            .await
             .and_then(|sequence| {
                 sequence
                     .in_range(range.0, range.1, Some(*origin.id()))?
@@ -216,6 +246,7 @@ impl SequenceStorage {
     ) -> Result<NodeDuty> {
         let result = match self
             .get_chunk(address, SequenceAction::Read, origin)
+            .await
             .and_then(|sequence| match sequence.last_entry(Some(*origin.id()))? {
                 Some(entry) => Ok((sequence.len(Some(*origin.id()))? - 1, entry.to_vec())),
                 None => Err(Error::NetworkData(DtError::NoSuchEntry)),
@@ -244,6 +275,7 @@ impl SequenceStorage {
     ) -> Result<NodeDuty> {
         let result = match self
             .get_chunk(address, SequenceAction::Read, origin)
+            .await
             .and_then(|sequence| {
                 sequence
                     .permissions(user, Some(*origin.id()))
@@ -272,6 +304,7 @@ impl SequenceStorage {
     ) -> Result<NodeDuty> {
         let result = match self
             .get_chunk(address, SequenceAction::Read, origin)
+            .await
             .and_then(|sequence| {
                 let res = if sequence.is_public() {
                     let policy = sequence.public_policy()?;
@@ -304,6 +337,7 @@ impl SequenceStorage {
     ) -> Result<NodeDuty> {
         let result = match self
             .get_chunk(address, SequenceAction::Read, origin)
+            .await
             .and_then(|sequence| {
                 let res = if !sequence.is_public() {
                     let policy = sequence.private_policy(Some(*origin.id()))?;
@@ -366,7 +400,7 @@ impl SequenceStorage {
         F: FnOnce(Sequence) -> Result<Sequence>,
     {
         info!("Getting Sequence chunk for Edit");
-        let result = self.get_chunk(address, action, origin)?;
+        let result = self.get_chunk(address, action, origin).await?;
         let sequence = write_fn(result)?;
         info!("Edited Sequence chunk successfully");
         self.chunks.put(&sequence).await
@@ -398,8 +432,84 @@ impl SequenceStorage {
     }
 }
 
+/// Public Sequences can't be deleted, as others may rely on them staying around. Private ones can
+/// be deleted by their owner, and by the keys the owner delegated full access to, reading and
+/// appending.
+fn check_delete_permission(sequence: &Sequence, origin: EndUser) -> Result<()> {
+    if sequence.is_public() {
+        return Err(Error::InvalidOperation(
+            "Cannot delete public Sequence".to_string(),
+        ));
+    }
+    let public_key = Some(*origin.id());
+    sequence.check_permission(SequenceAction::Read, public_key)?;
+    sequence.check_permission(SequenceAction::Append, public_key)?;
+    Ok(())
+}
+
 impl Display for SequenceStorage {
     fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
         write!(formatter, "SequenceStorage")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::SequenceStorage;
+    use crate::{chunk_store::StoreOptions, Error, Result, StorageBackend};
+    use bls::SecretKey;
+    use sn_data_types::{PublicKey, Sequence};
+    use sn_messaging::{client::SequenceDataExchange, EndUser, MessageId};
+    use sn_routing::Prefix;
+    use std::iter;
+    use tempdir::TempDir;
+    use xor_name::XorName;
+
+    const MAX_CAPACITY: u64 = 1024 * 1024;
+
+    fn temp_dir() -> Result<TempDir> {
+        TempDir::new("test").map_err(|e| Error::TempDirCreationFailed(e.to_string()))
+    }
+
+    fn exchange_of(sequence: &Sequence) -> SequenceDataExchange {
+        SequenceDataExchange(iter::once((*sequence.address(), sequence.clone())).collect())
+    }
+
+    #[tokio::test]
+    async fn deleted_sequences_are_not_taken_back() -> Result<()> {
+        let root = temp_dir()?;
+        let options = StoreOptions {
+            backend: StorageBackend::Memory,
+            ..Default::default()
+        };
+        let mut storage = SequenceStorage::new(root.path(), MAX_CAPACITY, &options).await?;
+        let owner = PublicKey::from(SecretKey::random().public_key());
+        let new_sequence =
+            || Sequence::new_private(owner, "actor".to_string(), XorName::random(), 0, None);
+        let sequence = new_sequence();
+        let address = *sequence.address();
+
+        storage.update(exchange_of(&sequence)).await?;
+        assert!(storage.chunks.has(&address).await);
+        let _ = storage
+            .delete(address, MessageId::new(), EndUser::AllClients(owner))
+            .await?;
+        assert!(!storage.chunks.has(&address).await);
+
+        // An Elder which missed the delete hands the Sequence over.
+        storage.update(exchange_of(&sequence)).await?;
+        assert!(!storage.chunks.has(&address).await);
+        assert!(storage.get_data_of(Prefix::default()).await.0.is_empty());
+        assert_eq!(storage.tombstones_of(Prefix::default()), vec![address]);
+
+        // The tombstones of other Elders delete the Sequences we still hold.
+        let other = new_sequence();
+        storage.update(exchange_of(&other)).await?;
+        storage.add_tombstones(vec![*other.address()]).await?;
+        assert!(!storage.chunks.has(other.address()).await);
+        storage.update(exchange_of(&other)).await?;
+        assert!(!storage.chunks.has(other.address()).await);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,387 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Proofs of storage, asked of the holders of randomly picked chunks.
+//!
+//! Elders only see the content of a chunk while it's written, so that's when they compute the
+//! proofs for a few nonces, which the answers to the next challenges are checked against. Each
+//! nonce is used once, for holders not to keep its proof in place of the chunk. Once they're used
+//! up, until the chunk is written again, all holders are challenged with the same nonce, and the
+//! proof most of the holders that answered agree on is taken as the right one.
+//!
+//! Only the Adults a chunk was written to are challenged. Holders are picked among a few more
+//! Adults than the chunk has copies, so the others among those aren't expected to have it.
+
+use crate::storage_msg::{chunk_proof, ChunkProof, Nonce};
+use rand::seq::IteratorRandom;
+use sn_data_types::{Blob, BlobAddress};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use xor_name::XorName;
+
+/// Number of chunks whose holders are challenged on each round.
+const CHALLENGES_PER_ROUND: usize = 4;
+
+/// Number of chunks remembered for challenging. Past that, arbitrary ones are forgotten.
+const MAX_KNOWN_CHUNKS: usize = 10_000;
+
+/// Number of proofs computed when a chunk is written, each for a challenge of its holders.
+const PRECOMPUTED_PROOFS: usize = 4;
+
+struct Challenge {
+    address: BlobAddress,
+    holders: BTreeSet<XorName>,
+    proofs: BTreeMap<XorName, Option<ChunkProof>>,
+    // The proof computed when the chunk was written, if there was one left.
+    expected: Option<ChunkProof>,
+}
+
+/// Outcome of a challenge. Holders which neither passed nor failed gave a proof which wasn't known
+/// beforehand, and not enough other holders agreed on.
+#[derive(Debug, PartialEq)]
+pub(super) struct Verdict {
+    pub(super) address: BlobAddress,
+    pub(super) passed: BTreeSet<XorName>,
+    pub(super) failed: BTreeSet<XorName>,
+}
+
+pub(super) struct StorageChallenges {
+    // Known chunks, with the Adults they were written to.
+    known_chunks: HashMap<BlobAddress, BTreeSet<XorName>>,
+    // Proofs of known chunks for the nonces not used yet, computed when they were written.
+    precomputed: HashMap<BlobAddress, Vec<(Nonce, ChunkProof)>>,
+    pending: HashMap<Nonce, Challenge>,
+}
+
+impl StorageChallenges {
+    pub(super) fn new() -> Self {
+        Self {
+            known_chunks: HashMap::new(),
+            precomputed: HashMap::new(),
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Remembers a stored chunk, and the `holders` it was written to, so they can be challenged.
+    /// Proofs are computed for the next challenges, unless some are left from an earlier write.
+    pub(super) fn add_chunk(&mut self, chunk: &Blob, holders: &BTreeSet<XorName>) {
+        let address = *chunk.address();
+        if self.known_chunks.len() >= MAX_KNOWN_CHUNKS && !self.known_chunks.contains_key(&address)
+        {
+            if let Some(forgotten) = self.known_chunks.keys().next().copied() {
+                let _ = self.known_chunks.remove(&forgotten);
+                let _ = self.precomputed.remove(&forgotten);
+            }
+        }
+        self.known_chunks
+            .entry(address)
+            .or_default()
+            .extend(holders.iter().copied());
+        let proofs = self.precomputed.entry(address).or_default();
+        if proofs.is_empty() {
+            proofs.extend((0..PRECOMPUTED_PROOFS).map(|_| {
+                let nonce = rand::random();
+                (nonce, chunk_proof(&nonce, chunk))
+            }));
+        }
+    }
+
+    /// Records that a known chunk was written to `holder` too.
+    pub(super) fn add_holder(&mut self, address: &BlobAddress, holder: XorName) {
+        if let Some(holders) = self.known_chunks.get_mut(address) {
+            let _ = holders.insert(holder);
+        }
+    }
+
+    /// Records that `holders` no longer have a known chunk, having handed it off.
+    pub(super) fn remove_holders(&mut self, address: &BlobAddress, holders: &BTreeSet<XorName>) {
+        if let Some(known) = self.known_chunks.get_mut(address) {
+            known.retain(|holder| !holders.contains(holder));
+        }
+    }
+
+    /// The Adults a known chunk was written to.
+    pub(super) fn holders_of(&self, address: &BlobAddress) -> BTreeSet<XorName> {
+        self.known_chunks.get(address).cloned().unwrap_or_default()
+    }
+
+    /// Forgets a deleted chunk, whose holders aren't expected to have it anymore.
+    pub(super) fn remove_chunk(&mut self, address: &BlobAddress) {
+        let _ = self.known_chunks.remove(address);
+        let _ = self.precomputed.remove(address);
+        self.pending
+            .retain(|_, challenge| challenge.address != *address);
+    }
+
+    /// Picks the chunks whose holders are challenged this round.
+    pub(super) fn sample(&self) -> Vec<BlobAddress> {
+        self.sample_chunks(CHALLENGES_PER_ROUND)
+    }
+
+    /// Picks `count` of the known chunks at random.
+    pub(super) fn sample_chunks(&self, count: usize) -> Vec<BlobAddress> {
+        self.known_chunks
+            .keys()
+            .copied()
+            .choose_multiple(&mut rand::thread_rng(), count)
+    }
+
+    /// Challenges the holders of the chunk at `address`, returning the nonce to send them. The
+    /// nonce is one a proof was computed for, if any is left.
+    pub(super) fn start(&mut self, address: BlobAddress, holders: BTreeSet<XorName>) -> Nonce {
+        let (nonce, expected) = match self.precomputed.get_mut(&address).and_then(Vec::pop) {
+            Some((nonce, proof)) => (nonce, Some(proof)),
+            None => (rand::random(), None),
+        };
+        let _ = self.pending.insert(
+            nonce,
+            Challenge {
+                address,
+                holders,
+                proofs: BTreeMap::new(),
+                expected,
+            },
+        );
+        nonce
+    }
+
+    /// Records the answer of a holder, returning the verdict once all holders answered.
+    pub(super) fn record_proof(
+        &mut self,
+        address: &BlobAddress,
+        nonce: &Nonce,
+        holder: XorName,
+        proof: Option<ChunkProof>,
+    ) -> Option<Verdict> {
+        let challenge = self.pending.get_mut(nonce)?;
+        if challenge.address != *address || !challenge.holders.contains(&holder) {
+            return None;
+        }
+        let _ = challenge.proofs.insert(holder, proof);
+        if challenge.proofs.len() < challenge.holders.len() {
+            return None;
+        }
+        self.pending.remove(nonce).map(Challenge::verdict)
+    }
+
+    /// Ends all pending challenges. Holders which didn't answer in time fail them.
+    pub(super) fn expire(&mut self) -> Vec<Verdict> {
+        self.pending
+            .drain()
+            .map(|(_, challenge)| challenge.verdict())
+            .collect()
+    }
+
+    /// Forgets holders which left, and stops expecting answers from them.
+    pub(super) fn retain_members_only(&mut self, members: &BTreeSet<XorName>) {
+        for holders in self.known_chunks.values_mut() {
+            holders.retain(|holder| members.contains(holder));
+        }
+        for challenge in self.pending.values_mut() {
+            challenge.holders.retain(|holder| members.contains(holder));
+            challenge
+                .proofs
+                .retain(|holder, _| members.contains(holder));
+        }
+    }
+}
+
+impl Challenge {
+    fn verdict(self) -> Verdict {
+        let valid_proof = self.expected.or_else(|| self.majority_proof());
+
+        let mut passed = BTreeSet::new();
+        let mut failed = BTreeSet::new();
+        for holder in self.holders {
+            match self.proofs.get(&holder) {
+                Some(Some(proof)) if Some(*proof) == valid_proof => {
+                    let _ = passed.insert(holder);
+                }
+                // With no proof known or agreed on, the ones given can't be told right from wrong.
+                Some(Some(_)) if valid_proof.is_none() => (),
+                _ => {
+                    let _ = failed.insert(holder);
+                }
+            }
+        }
+        Verdict {
+            address: self.address,
+            passed,
+            failed,
+        }
+    }
+
+    // The proof most of the holders which answered agree on, if any.
+    fn majority_proof(&self) -> Option<ChunkProof> {
+        let mut votes = BTreeMap::new();
+        for proof in self.proofs.values().flatten() {
+            *votes.entry(*proof).or_insert(0) += 1;
+        }
+        let answered: usize = votes.values().sum();
+        votes
+            .into_iter()
+            .find(|(_, count)| 2 * count > answered)
+            .map(|(proof, _)| proof)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{StorageChallenges, Verdict, PRECOMPUTED_PROOFS};
+    use crate::storage_msg::chunk_proof;
+    use sn_data_types::{Blob, PublicBlob};
+    use std::collections::BTreeSet;
+    use xor_name::XorName;
+
+    #[test]
+    fn holders_disagreeing_with_the_majority_fail() {
+        let chunk = Blob::Public(PublicBlob::new(vec![1, 2, 3]));
+        let address = *chunk.address();
+        let holders = (0..4).map(|_| XorName::random()).collect::<Vec<_>>();
+        // With no proofs computed beforehand, the majority decides.
+        let mut challenges = StorageChallenges::new();
+
+        let nonce = challenges.start(address, holders.iter().copied().collect());
+        let proof = chunk_proof(&nonce, &chunk);
+        assert_eq!(
+            challenges.record_proof(&address, &nonce, holders[0], Some(proof)),
+            None
+        );
+        assert_eq!(
+            challenges.record_proof(&address, &nonce, holders[1], Some(proof)),
+            None
+        );
+        assert_eq!(
+            challenges.record_proof(&address, &nonce, holders[2], Some([0; 32])),
+            None
+        );
+        let verdict = challenges.record_proof(&address, &nonce, holders[3], None);
+
+        assert_eq!(
+            verdict,
+            Some(Verdict {
+                address,
+                passed: holders[..2].iter().copied().collect(),
+                failed: holders[2..].iter().copied().collect(),
+            })
+        );
+        assert!(challenges.expire().is_empty());
+    }
+
+    #[test]
+    fn proofs_computed_on_writing_outweigh_the_majority() {
+        let chunk = Blob::Public(PublicBlob::new(vec![1, 2, 3]));
+        let address = *chunk.address();
+        let holders = (0..3).map(|_| XorName::random()).collect::<Vec<_>>();
+        let mut challenges = StorageChallenges::new();
+        challenges.add_chunk(&chunk, &holders.iter().copied().collect());
+        assert_eq!(challenges.sample(), vec![address]);
+
+        // Two holders agreeing on a wrong proof.
+        let mut challenge = |challenges: &mut StorageChallenges| {
+            let nonce = challenges.start(address, holders.iter().copied().collect());
+            let proof = chunk_proof(&nonce, &chunk);
+            let _ = challenges.record_proof(&address, &nonce, holders[0], Some(proof));
+            let _ = challenges.record_proof(&address, &nonce, holders[1], Some([0; 32]));
+            challenges.record_proof(&address, &nonce, holders[2], Some([0; 32]))
+        };
+        for _ in 0..PRECOMPUTED_PROOFS {
+            assert_eq!(
+                challenge(&mut challenges),
+                Some(Verdict {
+                    address,
+                    passed: holders[..1].iter().copied().collect(),
+                    failed: holders[1..].iter().copied().collect(),
+                })
+            );
+        }
+
+        // Once those are used up, the majority decides again.
+        assert_eq!(
+            challenge(&mut challenges),
+            Some(Verdict {
+                address,
+                passed: holders[1..].iter().copied().collect(),
+                failed: holders[..1].iter().copied().collect(),
+            })
+        );
+    }
+
+    #[test]
+    fn holders_not_answering_in_time_fail() {
+        let chunk = Blob::Public(PublicBlob::new(vec![1, 2, 3]));
+        let address = *chunk.address();
+        let holders = (0..2).map(|_| XorName::random()).collect::<Vec<_>>();
+        let mut challenges = StorageChallenges::new();
+
+        let nonce = challenges.start(address, holders.iter().copied().collect());
+        let proof = chunk_proof(&nonce, &chunk);
+        // Answers to other challenges, or from other nodes, are ignored.
+        assert_eq!(
+            challenges.record_proof(&address, &[0; 32], holders[1], Some(proof)),
+            None
+        );
+        assert_eq!(
+            challenges.record_proof(&address, &nonce, XorName::random(), Some(proof)),
+            None
+        );
+        assert_eq!(
+            challenges.record_proof(&address, &nonce, holders[0], Some(proof)),
+            None
+        );
+
+        assert_eq!(
+            challenges.expire(),
+            vec![Verdict {
+                address,
+                passed: holders[..1].iter().copied().collect(),
+                failed: holders[1..].iter().copied().collect(),
+            }]
+        );
+    }
+
+    #[test]
+    fn deleted_chunks_are_not_challenged() {
+        let chunk = Blob::Public(PublicBlob::new(vec![1, 2, 3]));
+        let address = *chunk.address();
+        let mut challenges = StorageChallenges::new();
+        challenges.add_chunk(&chunk, &BTreeSet::new());
+        let _ = challenges.start(address, BTreeSet::new());
+
+        challenges.remove_chunk(&address);
+
+        assert!(challenges.sample().is_empty());
+        assert!(challenges.expire().is_empty());
+    }
+
+    #[test]
+    fn holders_are_recorded_until_they_leave_or_hand_chunks_off() {
+        let chunk = Blob::Public(PublicBlob::new(vec![1, 2, 3]));
+        let address = *chunk.address();
+        let holders = (0..4).map(|_| XorName::random()).collect::<Vec<_>>();
+        let mut challenges = StorageChallenges::new();
+        challenges.add_chunk(&chunk, &holders[..2].iter().copied().collect());
+        challenges.add_chunk(&chunk, &holders[1..3].iter().copied().collect());
+        challenges.add_holder(&address, holders[3]);
+        assert_eq!(
+            challenges.holders_of(&address),
+            holders.iter().copied().collect()
+        );
+
+        challenges.remove_holders(&address, &holders[..1].iter().copied().collect());
+        challenges.retain_members_only(&holders[..3].iter().copied().collect());
+        assert_eq!(
+            challenges.holders_of(&address),
+            holders[1..3].iter().copied().collect()
+        );
+
+        // Holders of unknown chunks aren't recorded.
+        let other = *Blob::Public(PublicBlob::new(vec![4, 5, 6])).address();
+        challenges.add_holder(&other, holders[0]);
+        assert!(challenges.holders_of(&other).is_empty());
+    }
+}
@@ -0,0 +1,78 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Tombstones of deleted data.
+//!
+//! Elders which missed a delete still hold the data, and hand it over in their `DataExchange`.
+//! The addresses of the data deleted are kept, so it isn't brought back then. Elders hand their
+//! tombstones over to new Elders along with their data, for those to keep them too.
+
+use crate::{
+    to_db_key::{from_db_key, ToDbKey},
+    utils, Error, Result,
+};
+use pickledb::PickleDb;
+use serde::de::DeserializeOwned;
+use std::path::Path;
+
+pub(super) struct Tombstones {
+    db: PickleDb,
+}
+
+impl Tombstones {
+    pub(super) fn new(path: &Path, db_name: &str) -> Result<Self> {
+        Ok(Self {
+            db: utils::new_auto_dump_db(path, db_name)?,
+        })
+    }
+
+    /// Records that the data at `address` was deleted.
+    pub(super) fn add<K: ToDbKey>(&mut self, address: &K) -> Result<()> {
+        self.db
+            .set(&address.to_db_key()?, &true)
+            .map_err(Error::PickleDb)
+    }
+
+    /// Whether the data at `address` was deleted.
+    pub(super) fn contains<K: ToDbKey>(&self, address: &K) -> bool {
+        address
+            .to_db_key()
+            .map_or(false, |key| self.db.exists(&key))
+    }
+
+    /// Addresses of all the data deleted.
+    pub(super) fn all<K: DeserializeOwned>(&self) -> Vec<K> {
+        self.db
+            .get_all()
+            .iter()
+            .filter_map(|key| from_db_key(key).ok())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Tombstones;
+    use crate::{Error, Result};
+    use tempdir::TempDir;
+    use xor_name::XorName;
+
+    #[test]
+    fn tombstones_survive_restarts() -> Result<()> {
+        let root = TempDir::new("test").map_err(|e| Error::TempDirCreationFailed(e.to_string()))?;
+        let (deleted, other) = (XorName::random(), XorName::random());
+
+        Tombstones::new(root.path(), "tombstones.db")?.add(&deleted)?;
+        let tombstones = Tombstones::new(root.path(), "tombstones.db")?;
+        assert!(tombstones.contains(&deleted));
+        assert!(!tombstones.contains(&other));
+        assert_eq!(tombstones.all::<XorName>(), vec![deleted]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,225 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Confirmation of blob writes to clients.
+//!
+//! A write is confirmed once as many holders stored the blob as the `WriteAckPolicy` asks for of
+//! the copies the blob is meant to have, and fails once too few holders are left to get there,
+//! having failed or not answered in time. Deletes of private blobs are confirmed the same way.
+
+use crate::WriteAckPolicy;
+use log::warn;
+use sn_messaging::{EndUser, MessageId};
+use std::collections::HashMap;
+
+/// How a client's write ended.
+#[derive(Debug, PartialEq)]
+pub(super) enum WriteOutcome {
+    /// Enough holders stored the blob.
+    Stored(EndUser),
+    /// Too few holders can still store the blob.
+    Failed(EndUser),
+}
+
+struct PendingWrite {
+    origin: EndUser,
+    required: usize,
+    stored: usize,
+    unanswered: usize,
+    deleting: bool,
+}
+
+pub(super) struct WriteAcks {
+    policy: WriteAckPolicy,
+    writes: HashMap<MessageId, PendingWrite>,
+}
+
+impl WriteAcks {
+    pub(super) fn new(policy: WriteAckPolicy) -> Self {
+        Self {
+            policy,
+            writes: HashMap::new(),
+        }
+    }
+
+    /// Starts waiting on `holders` to store the blob of the write `msg_id`, which is meant to have
+    /// `copies` copies. The policy asks for its share of the `copies`, and at least `min_stored`
+    /// however lenient it is. With fewer holders than that, the write can only fail.
+    pub(super) fn start(
+        &mut self,
+        msg_id: MessageId,
+        origin: EndUser,
+        copies: usize,
+        holders: usize,
+        min_stored: usize,
+    ) {
+        self.insert(msg_id, origin, copies, holders, min_stored, false)
+    }
+
+    /// Starts waiting on `holders` to delete the blob of the delete `msg_id`, which is meant to
+    /// have `copies` copies.
+    pub(super) fn start_delete(
+        &mut self,
+        msg_id: MessageId,
+        origin: EndUser,
+        copies: usize,
+        holders: usize,
+    ) {
+        self.insert(msg_id, origin, copies, holders, 1, true)
+    }
+
+    fn insert(
+        &mut self,
+        msg_id: MessageId,
+        origin: EndUser,
+        copies: usize,
+        holders: usize,
+        min_stored: usize,
+        deleting: bool,
+    ) {
+        let required = match self.policy {
+            WriteAckPolicy::One => 1,
+            WriteAckPolicy::Majority => copies / 2 + 1,
+            WriteAckPolicy::All => copies,
+        }
+        .max(min_stored);
+        if holders < required {
+            warn!(
+                "Only {} holders for {:?}, of the {} the write ack policy requires",
+                holders, msg_id, required
+            );
+        }
+        let _ = self.writes.insert(
+            msg_id,
+            PendingWrite {
+                origin,
+                required,
+                stored: 0,
+                unanswered: holders,
+                deleting,
+            },
+        );
+    }
+
+    /// Whether `msg_id` is a pending delete rather than a pending write.
+    pub(super) fn is_delete(&self, msg_id: &MessageId) -> bool {
+        self.writes
+            .get(msg_id)
+            .map_or(false, |write| write.deleting)
+    }
+
+    /// Records whether a holder stored the blob of the write `msg_id`, a holder not answering in
+    /// time counting as failing. Returns how the write ended, once only.
+    pub(super) fn record(&mut self, msg_id: &MessageId, stored: bool) -> Option<WriteOutcome> {
+        let write = self.writes.get_mut(msg_id)?;
+        write.unanswered = write.unanswered.saturating_sub(1);
+        if stored {
+            write.stored += 1;
+        }
+        self.end_if_decided(msg_id)
+    }
+
+    /// Records that a holder had no copy to delete, which counts neither way, as it may never
+    /// have had one. Returns how the delete ended, once only.
+    pub(super) fn record_skipped(&mut self, msg_id: &MessageId) -> Option<WriteOutcome> {
+        let write = self.writes.get_mut(msg_id)?;
+        write.unanswered = write.unanswered.saturating_sub(1);
+        self.end_if_decided(msg_id)
+    }
+
+    fn end_if_decided(&mut self, msg_id: &MessageId) -> Option<WriteOutcome> {
+        let write = self.writes.get(msg_id)?;
+        let outcome = if write.stored >= write.required {
+            WriteOutcome::Stored(write.origin)
+        } else if write.stored + write.unanswered < write.required {
+            WriteOutcome::Failed(write.origin)
+        } else {
+            return None;
+        };
+        let _ = self.writes.remove(msg_id);
+        Some(outcome)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{WriteAcks, WriteOutcome};
+    use crate::WriteAckPolicy;
+    use bls::SecretKey;
+    use sn_data_types::PublicKey;
+    use sn_messaging::{EndUser, MessageId};
+
+    // Records the answers of the holders of a write of a blob meant to have `copies` copies,
+    // returning after how many it ended, and whether it was stored.
+    fn run(policy: WriteAckPolicy, copies: usize, answers: &[bool]) -> Option<(usize, bool)> {
+        let origin = EndUser::AllClients(PublicKey::from(SecretKey::random().public_key()));
+        let mut acks = WriteAcks::new(policy);
+        let msg_id = MessageId::new();
+        acks.start(msg_id, origin, copies, answers.len(), 1);
+        let mut ended = None;
+        for (count, stored) in answers.iter().enumerate() {
+            if let Some(outcome) = acks.record(&msg_id, *stored) {
+                assert!(ended.is_none(), "write ended twice");
+                let stored = outcome == WriteOutcome::Stored(origin);
+                assert!(stored || outcome == WriteOutcome::Failed(origin));
+                ended = Some((count + 1, stored));
+            }
+        }
+        ended
+    }
+
+    #[test]
+    fn writes_end_once_the_policy_is_met_or_out_of_reach() {
+        use WriteAckPolicy::*;
+        assert_eq!(run(One, 4, &[false, true, true, true]), Some((2, true)));
+        assert_eq!(run(One, 4, &[false, false, false, false]), Some((4, false)));
+        assert_eq!(
+            run(Majority, 4, &[true, false, true, true]),
+            Some((4, true))
+        );
+        assert_eq!(
+            run(Majority, 4, &[false, true, false, true]),
+            Some((3, false))
+        );
+        assert_eq!(run(All, 4, &[true, true, true, true]), Some((4, true)));
+        assert_eq!(run(All, 4, &[true, false, true, true]), Some((2, false)));
+    }
+
+    #[test]
+    fn policies_count_against_the_copies_blobs_are_meant_to_have() {
+        use WriteAckPolicy::*;
+        // Two holders of a blob meant to have four copies don't make a majority of them.
+        assert_eq!(run(One, 4, &[true, true]), Some((1, true)));
+        assert_eq!(run(Majority, 4, &[true, true]), Some((1, false)));
+        assert_eq!(run(All, 4, &[true, true]), Some((1, false)));
+        // Holders beyond the copies count towards the policy too.
+        assert_eq!(
+            run(All, 4, &[true, false, true, true, true]),
+            Some((5, true))
+        );
+    }
+
+    #[test]
+    fn deletes_skip_holders_without_a_copy() {
+        let origin = EndUser::AllClients(PublicKey::from(SecretKey::random().public_key()));
+        let mut acks = WriteAcks::new(WriteAckPolicy::All);
+        let msg_id = MessageId::new();
+        // Elders send deletes to more candidates than the copies, most of which have none.
+        acks.start_delete(msg_id, origin, 2, 6);
+        assert!(acks.is_delete(&msg_id));
+        for _ in 0..3 {
+            assert_eq!(acks.record_skipped(&msg_id), None);
+        }
+        assert_eq!(acks.record(&msg_id, true), None);
+        assert_eq!(
+            acks.record(&msg_id, true),
+            Some(WriteOutcome::Stored(origin))
+        );
+        assert_eq!(acks.record_skipped(&msg_id), None);
+    }
+}
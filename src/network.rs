@@ -251,7 +251,6 @@ impl Network {
             .collect::<Vec<_>>()
     }
 
-    #[allow(unused)]
     pub async fn our_elder_names_sorted_by_distance_to(
         &self,
         name: &XorName,
@@ -296,13 +295,4 @@ impl Network {
             .map(|p2p_node| *p2p_node.name())
             .collect::<BTreeSet<_>>()
     }
-
-    pub async fn our_adults_sorted_by_distance_to(&self, name: &XorName) -> Vec<XorName> {
-        self.routing
-            .our_adults_sorted_by_distance_to(name)
-            .await
-            .into_iter()
-            .map(|p2p_node| *p2p_node.name())
-            .collect::<Vec<_>>()
-    }
 }
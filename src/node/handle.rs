@@ -7,8 +7,10 @@
 // permissions and limitations relating to use of the SAFE Network Software.
 
 use super::{
-    interaction::push_state,
-    messaging::{send, send_to_nodes},
+    interaction::{
+        push_chunk_holders, push_full_adults, push_shard_maps, push_state, push_tombstones,
+    },
+    messaging::{send, send_storage_msg, send_to_nodes},
     role::{AdultRole, Role},
 };
 use crate::{
@@ -23,6 +25,7 @@ use sn_messaging::{
     Aggregation, DstLocation, MessageId,
 };
 use sn_routing::ELDER_SIZE;
+use std::collections::BTreeSet;
 use xor_name::XorName;
 
 impl Node {
@@ -60,11 +63,18 @@ impl Node {
                     let elder = self.role.as_elder_mut()?;
                     let msg_id =
                         MessageId::combine(vec![our_prefix.name(), XorName::from(our_key)]);
-                    let ops = vec![push_state(elder, our_prefix, msg_id, new_elders).await?];
-                    elder
-                        .meta_data
-                        .retain_members_only(self.network_api.our_adults().await)
-                        .await?;
+                    let mut ops = vec![
+                        push_shard_maps(elder, our_prefix, new_elders.clone()),
+                        push_chunk_holders(elder, our_prefix, new_elders.clone()),
+                        push_tombstones(elder, our_prefix, new_elders.clone()),
+                        push_state(elder, our_prefix, msg_id, new_elders).await?,
+                    ];
+                    ops.extend(
+                        elder
+                            .meta_data
+                            .retain_members_only(self.network_api.our_adults().await)
+                            .await?,
+                    );
                     Ok(ops)
                 }
             }
@@ -74,11 +84,15 @@ impl Node {
                 remaining,
             } => {
                 let our_name = self.our_name().await;
-                Ok(self
-                    .role
-                    .as_adult_mut()?
-                    .reorganize_chunks(our_name, added, removed, remaining)
-                    .await)
+                match &mut self.role {
+                    Role::Adult(adult) => {
+                        adult
+                            .reorganize_chunks(our_name, added, removed, remaining)
+                            .await
+                    }
+                    // New Adults need to know which Adults are full to pick holders as we do.
+                    Role::Elder(elder) => Ok(vec![push_full_adults(elder, added).await]),
+                }
             }
             NodeDuty::SectionSplit {
                 our_key,
@@ -185,11 +199,13 @@ impl Node {
                 info!("Member Lost: {:?}", name);
                 let elder = self.role.as_elder_mut()?;
                 elder.section_funds.remove_node_wallet(name);
-                elder
+                let mut ops = elder
                     .meta_data
                     .retain_members_only(self.network_api.our_adults().await)
                     .await?;
-                Ok(vec![NodeDuty::SetNodeJoinsAllowed(true)])
+                ops.extend(elder.meta_data.repair_shards(name).await?);
+                ops.push(NodeDuty::SetNodeJoinsAllowed(true));
+                Ok(ops)
             }
             //
             // ---------- Levelling --------------
@@ -205,7 +221,14 @@ impl Node {
                 info!("Getting Demoted");
                 let capacity = self.used_space.max_capacity().await;
                 self.role = Role::Adult(AdultRole {
-                    chunks: Chunks::new(self.node_info.root_dir.as_path(), capacity).await?,
+                    chunks: Chunks::new(
+                        self.node_info.root_dir.as_path(),
+                        capacity,
+                        &self.node_info.store_options,
+                        self.node_info.replication_rate,
+                    )
+                    .await?,
+                    full_adults: BTreeSet::new(),
                 });
                 Ok(vec![])
             }
@@ -273,7 +296,7 @@ impl Node {
             // -------- Immutable chunks --------
             NodeDuty::ReadChunk { read, msg_id, .. } => {
                 let adult = self.role.as_adult_mut()?;
-                let mut ops = adult.chunks.read(&read, msg_id);
+                let mut ops = adult.chunks.read(&read, msg_id).await;
                 ops.extend(adult.chunks.check_storage().await?);
                 Ok(ops)
             }
@@ -287,20 +310,264 @@ impl Node {
                 ops.extend(adult.chunks.check_storage().await?);
                 Ok(ops)
             }
-            NodeDuty::ProcessRepublish { chunk, msg_id, .. } => {
+            NodeDuty::ProcessRepublish { chunk, msg_id, src } => {
                 info!("Processing republish with MessageId: {:?}", msg_id);
                 let elder = self.role.as_elder_mut()?;
-                Ok(vec![elder.meta_data.republish_chunk(chunk).await?])
+                elder.meta_data.hand_off_chunk(chunk, src).await
+            }
+            NodeDuty::RetryHandoffs => match &mut self.role {
+                Role::Adult(adult) => adult.retry_handoffs().await,
+                Role::Elder(_) => Ok(vec![]),
+            },
+            NodeDuty::ReplicateQueuedChunks => match &mut self.role {
+                Role::Adult(adult) => adult.replicate_queued().await,
+                Role::Elder(_) => Ok(vec![]),
+            },
+            NodeDuty::CompleteHandoff { address, src } => {
+                if !self.network_api.our_elder_names().await.contains(&src) {
+                    return Err(Error::InvalidOperation(format!(
+                        "Handoff confirmation from {}, which is not one of our Elders",
+                        src
+                    )));
+                }
+                let adult = self.role.as_adult_mut()?;
+                adult.chunks.complete_handoff(&address).await?;
+                Ok(adult.chunks.check_storage().await?)
+            }
+            NodeDuty::MarkShard { address, src } => {
+                if !self.network_api.our_elder_names().await.contains(&src) {
+                    return Err(Error::InvalidOperation(format!(
+                        "Shard mark from {}, which is not one of our Elders",
+                        src
+                    )));
+                }
+                let adult = self.role.as_adult_mut()?;
+                adult.chunks.mark_shard(&address)?;
+                Ok(vec![])
             }
             NodeDuty::ReachingMaxCapacity => Ok(vec![self.notify_section_of_our_storage().await?]),
+            NodeDuty::StorageAvailable => {
+                Ok(vec![self.notify_section_of_available_storage().await])
+            }
+            NodeDuty::ReportStorageLevel => {
+                let elders = self.network_api.our_elder_names().await;
+                match &self.role {
+                    Role::Adult(adult) => Ok(vec![adult.chunks.report_storage_level(elders).await]),
+                    Role::Elder(_) => Ok(vec![]),
+                }
+            }
+            NodeDuty::RecordStorageLevel { level, src } => {
+                if !self.network_api.our_adults().await.contains(&src) {
+                    return Err(Error::InvalidOperation(format!(
+                        "Storage level from {}, which is not one of our Adults",
+                        src
+                    )));
+                }
+                let elder = self.role.as_elder_mut()?;
+                elder.meta_data.record_storage_level(src, level).await;
+                Ok(vec![])
+            }
+            NodeDuty::ScrubChunks => {
+                let elders = self.network_api.our_elder_names().await;
+                match &mut self.role {
+                    Role::Adult(adult) => {
+                        let mut ops = adult.chunks.scrub(elders).await?;
+                        // Also catches space freed by handoffs, or a raised max capacity.
+                        ops.extend(adult.chunks.check_storage().await?);
+                        Ok(ops)
+                    }
+                    Role::Elder(_) => Ok(vec![]),
+                }
+            }
+            NodeDuty::ChallengeHolders => match &mut self.role {
+                Role::Elder(elder) => Ok(elder.meta_data.challenge_holders().await),
+                Role::Adult(_) => Ok(vec![]),
+            },
+            NodeDuty::ProveChunk {
+                address,
+                nonce,
+                elder,
+            } => {
+                if !self.network_api.our_elder_names().await.contains(&elder) {
+                    return Err(Error::InvalidOperation(format!(
+                        "Storage challenge from {}, which is not one of our Elders",
+                        elder
+                    )));
+                }
+                let adult = self.role.as_adult()?;
+                Ok(vec![adult.chunks.prove(address, nonce, elder).await])
+            }
+            NodeDuty::ExpirePendingOps => match &mut self.role {
+                Role::Elder(elder) => elder.meta_data.expire_pending_ops().await,
+                Role::Adult(_) => Ok(vec![]),
+            },
+            NodeDuty::HedgeReads => match &mut self.role {
+                Role::Elder(elder) => elder.meta_data.hedge_reads(),
+                Role::Adult(_) => Ok(vec![]),
+            },
+            NodeDuty::AuditRedundancy => match &mut self.role {
+                Role::Elder(elder) => Ok(elder.meta_data.audit_redundancy().await),
+                Role::Adult(_) => Ok(vec![]),
+            },
+            NodeDuty::ReportChunkHeld { address, elder } => {
+                if !self.network_api.our_elder_names().await.contains(&elder) {
+                    return Err(Error::InvalidOperation(format!(
+                        "Chunk held query from {}, which is not one of our Elders",
+                        elder
+                    )));
+                }
+                let adult = self.role.as_adult()?;
+                Ok(vec![adult.chunks.report_held(address, elder).await])
+            }
+            NodeDuty::RecordChunkHeld { address, held, src } => {
+                let elder = self.role.as_elder_mut()?;
+                Ok(elder.meta_data.record_chunk_held(address, held, src).await)
+            }
+            NodeDuty::RecordChunkProof {
+                address,
+                nonce,
+                proof,
+                src,
+            } => {
+                let elder = self.role.as_elder_mut()?;
+                Ok(elder
+                    .meta_data
+                    .record_chunk_proof(address, nonce, proof, src)
+                    .await)
+            }
+            NodeDuty::RestoreChunk { address, src } => {
+                if !self.network_api.our_adults().await.contains(&src) {
+                    return Err(Error::InvalidOperation(format!(
+                        "Corrupt chunk reported by {}, which is not one of our Adults",
+                        src
+                    )));
+                }
+                let elder = self.role.as_elder_mut()?;
+                Ok(elder.meta_data.restore_chunk(address, src).await)
+            }
+            NodeDuty::RebalanceChunks => match &mut self.role {
+                Role::Elder(elder) => Ok(elder.meta_data.rebalance().await),
+                Role::Adult(_) => Ok(vec![]),
+            },
+            NodeDuty::RecordChunkMoved {
+                address,
+                from,
+                to,
+                src,
+            } => {
+                if !self.network_api.our_elder_names().await.contains(&src) {
+                    return Err(Error::InvalidOperation(format!(
+                        "Chunk move from {}, which is not one of our Elders",
+                        src
+                    )));
+                }
+                let our_name = self.our_name().await;
+                match &mut self.role {
+                    Role::Elder(elder) => {
+                        elder.meta_data.record_chunk_moved(address, from, to)?;
+                        Ok(vec![])
+                    }
+                    Role::Adult(adult) if from == our_name => {
+                        adult.chunks.release_moved(&address).await?;
+                        Ok(adult.chunks.check_storage().await?)
+                    }
+                    Role::Adult(_) => Ok(vec![]),
+                }
+            }
+            NodeDuty::RecordPlacement {
+                msg_id,
+                address,
+                holders,
+                src,
+            } => {
+                if !self.network_api.our_elder_names().await.contains(&src) {
+                    return Err(Error::InvalidOperation(format!(
+                        "Blob holders from {}, which is not one of our Elders",
+                        src
+                    )));
+                }
+                let elder = self.role.as_elder_mut()?;
+                elder
+                    .meta_data
+                    .record_placement(msg_id, address, holders, src)
+                    .await
+            }
+            NodeDuty::UpdateShardMaps { maps, src } => {
+                if !self.network_api.our_elder_names().await.contains(&src) {
+                    return Err(Error::InvalidOperation(format!(
+                        "Shard maps from {}, which is not one of our Elders",
+                        src
+                    )));
+                }
+                let elder = self.role.as_elder_mut()?;
+                elder.meta_data.update_shard_maps(maps)?;
+                Ok(vec![])
+            }
+            NodeDuty::UpdateChunkHolders { holders, src } => {
+                if !self.network_api.our_elder_names().await.contains(&src) {
+                    return Err(Error::InvalidOperation(format!(
+                        "Chunk holders from {}, which is not one of our Elders",
+                        src
+                    )));
+                }
+                let elder = self.role.as_elder_mut()?;
+                elder.meta_data.update_chunk_holders(holders)?;
+                Ok(vec![])
+            }
+            NodeDuty::UpdateTombstones {
+                sequences,
+                registers,
+                src,
+            } => {
+                if !self.network_api.our_elder_names().await.contains(&src) {
+                    return Err(Error::InvalidOperation(format!(
+                        "Tombstones from {}, which is not one of our Elders",
+                        src
+                    )));
+                }
+                let elder = self.role.as_elder_mut()?;
+                elder.meta_data.add_tombstones(sequences, registers).await?;
+                Ok(vec![])
+            }
+            NodeDuty::UpdateFullAdults { full_adults, src } => {
+                if !self.network_api.our_elder_names().await.contains(&src) {
+                    return Err(Error::InvalidOperation(format!(
+                        "Full Adults from {}, which is not one of our Elders",
+                        src
+                    )));
+                }
+                let our_name = self.our_name().await;
+                let adults = self.network_api.our_adults().await;
+                match &mut self.role {
+                    Role::Adult(adult) => {
+                        adult
+                            .update_full_adults(our_name, full_adults, adults)
+                            .await
+                    }
+                    Role::Elder(_) => Ok(vec![]),
+                }
+            }
             //
             // ------- Misc ------------
             NodeDuty::IncrementFullNodeCount { node_id } => {
                 let elder = self.role.as_elder_mut()?;
-                let propose_offline = NodeDuty::ProposeOffline(vec![node_id.into()]);
+                // The full node stays on as a read-only holder of the chunks it stored.
                 elder.meta_data.increase_full_node_count(node_id).await?;
-                // Accept a new node in place for the full node.
-                Ok(vec![NodeDuty::SetNodeJoinsAllowed(true), propose_offline])
+                let adults = self.network_api.our_adults().await;
+                // Accept a new node to make up for the capacity it no longer has.
+                Ok(vec![
+                    NodeDuty::SetNodeJoinsAllowed(true),
+                    push_full_adults(elder, adults).await,
+                ])
+            }
+            NodeDuty::DecrementFullNodeCount { node_name } => {
+                let elder = self.role.as_elder_mut()?;
+                elder
+                    .meta_data
+                    .decrease_full_node_count_if_present(node_name)
+                    .await?;
+                let adults = self.network_api.our_adults().await;
+                Ok(vec![push_full_adults(elder, adults).await])
             }
             NodeDuty::Send(msg) => {
                 send(msg, &self.network_api).await?;
@@ -314,6 +581,10 @@ impl Node {
                 send_to_nodes(&msg, targets, aggregation, &self.network_api).await?;
                 Ok(vec![])
             }
+            NodeDuty::SendStorageMsg { msg, targets } => {
+                send_storage_msg(&msg, targets, &self.network_api).await?;
+                Ok(vec![])
+            }
             NodeDuty::SetNodeJoinsAllowed(joins_allowed) => {
                 self.network_api.set_joins_allowed(joins_allowed).await?;
                 Ok(vec![])
@@ -331,7 +602,7 @@ impl Node {
                     .matches(&data_section_addr)
                 {
                     let elder = self.role.as_elder_mut()?;
-                    Ok(vec![elder.meta_data.read(query, id, origin).await?])
+                    elder.meta_data.read(query, id, origin).await
                 } else {
                     Ok(vec![NodeDuty::Send(OutgoingMsg {
                         msg: Message::NodeQuery {
@@ -346,7 +617,7 @@ impl Node {
             }
             NodeDuty::ProcessWrite { cmd, id, origin } => {
                 let elder = self.role.as_elder_mut()?;
-                Ok(vec![elder.meta_data.write(cmd, id, origin).await?])
+                elder.meta_data.write(cmd, id, origin).await
             }
             // --- Completion of Adult operations ---
             NodeDuty::RecordAdultWriteLiveness {
@@ -8,6 +8,7 @@
 
 use crate::{
     node_ops::{NodeDuties, NodeDuty, OutgoingMsg},
+    storage_msg::StorageMsg,
     Node, Result,
 };
 use sn_data_types::{CreditAgreementProof, CreditId, PublicKey, SectionElders};
@@ -90,6 +91,14 @@ impl Node {
         }))
     }
 
+    /// Lets our Elders know we can take new chunks again, after we reported we were full.
+    pub(crate) async fn notify_section_of_available_storage(&self) -> NodeDuty {
+        NodeDuty::SendStorageMsg {
+            msg: StorageMsg::StorageAvailable,
+            targets: self.network_api.our_elder_names().await,
+        }
+    }
+
     ///
     pub(crate) async fn register_wallet(&self) -> OutgoingMsg {
         let address = self.network_api.our_prefix().await.name();
@@ -105,6 +114,69 @@ impl Node {
     }
 }
 
+/// Hands the shard maps of the erasure-coded blobs of `prefix` over to `peers`.
+pub(crate) fn push_shard_maps(
+    elder: &ElderRole,
+    prefix: Prefix,
+    peers: BTreeSet<XorName>,
+) -> NodeDuty {
+    let maps = elder.meta_data.shard_maps_of(prefix);
+    if maps.is_empty() {
+        return NodeDuty::NoOp;
+    }
+    NodeDuty::SendStorageMsg {
+        msg: StorageMsg::ShardMaps(maps),
+        targets: peers,
+    }
+}
+
+/// Hands the chunks of `prefix`, with the Adults they were written to, over to `peers`.
+pub(crate) fn push_chunk_holders(
+    elder: &ElderRole,
+    prefix: Prefix,
+    peers: BTreeSet<XorName>,
+) -> NodeDuty {
+    let holders = elder.meta_data.chunk_holders_of(prefix);
+    if holders.is_empty() {
+        return NodeDuty::NoOp;
+    }
+    NodeDuty::SendStorageMsg {
+        msg: StorageMsg::ChunkHolders(holders),
+        targets: peers,
+    }
+}
+
+/// Hands the addresses of the data of `prefix` deleted over to `peers`, so they don't take it
+/// back from Elders which missed the deletes.
+pub(crate) fn push_tombstones(
+    elder: &ElderRole,
+    prefix: Prefix,
+    peers: BTreeSet<XorName>,
+) -> NodeDuty {
+    let (sequences, registers) = elder.meta_data.tombstones_of(prefix);
+    if sequences.is_empty() && registers.is_empty() {
+        return NodeDuty::NoOp;
+    }
+    NodeDuty::SendStorageMsg {
+        msg: StorageMsg::Tombstones {
+            sequences,
+            registers,
+        },
+        targets: peers,
+    }
+}
+
+/// Tells `adults` which Adults are full, so they pick chunk holders the way we do.
+pub(crate) async fn push_full_adults(elder: &ElderRole, adults: BTreeSet<XorName>) -> NodeDuty {
+    if adults.is_empty() {
+        return NodeDuty::NoOp;
+    }
+    NodeDuty::SendStorageMsg {
+        msg: StorageMsg::FullAdults(elder.meta_data.full_adults().await),
+        targets: adults,
+    }
+}
+
 /// Push our state to the given dst
 pub(crate) async fn push_state(
     elder: &mut ElderRole,
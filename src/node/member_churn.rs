@@ -46,8 +46,11 @@ impl Node {
         let meta_data = Metadata::new(
             &self.node_info.path(),
             capacity,
+            &self.node_info.store_options,
             adult_storage_info.clone(),
             reader,
+            self.node_info.erasure_coding,
+            self.node_info.write_ack_policy,
         )
         .await?;
 
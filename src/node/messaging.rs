@@ -6,8 +6,9 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
-use crate::{network::Network, Result};
+use crate::{network::Network, storage_msg::StorageMsg, Result};
 use crate::{node_ops::OutgoingMsg, Error};
+use bytes::Bytes;
 use log::{error, trace};
 use sn_messaging::{client::Message, Aggregation, DstLocation, Itinerary, SrcLocation};
 use sn_routing::XorName;
@@ -51,8 +52,27 @@ pub(crate) async fn send_to_nodes(
         msg
     );
 
+    send_bytes_to_nodes(msg.serialize()?, targets, aggregation, network).await;
+    Ok(())
+}
+
+pub(crate) async fn send_storage_msg(
+    msg: &StorageMsg,
+    targets: BTreeSet<XorName>,
+    network: &Network,
+) -> Result<()> {
+    trace!("Sending storage msg to nodes: {:?}: {:?}", targets, msg);
+    send_bytes_to_nodes(msg.serialize()?, targets, Aggregation::None, network).await;
+    Ok(())
+}
+
+async fn send_bytes_to_nodes(
+    bytes: Bytes,
+    targets: BTreeSet<XorName>,
+    aggregation: Aggregation,
+    network: &Network,
+) {
     let name = network.our_name().await;
-    let bytes = &msg.serialize()?;
     for target in targets {
         network
             .send_message(
@@ -71,5 +91,4 @@ pub(crate) async fn send_to_nodes(
                 |()| {},
             );
     }
-    Ok(())
 }
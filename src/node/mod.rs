@@ -14,13 +14,14 @@ mod role;
 mod split;
 
 use crate::{
-    chunk_store::UsedSpace,
-    chunks::Chunks,
+    chunk_store::{StoreOptions, UsedSpace},
+    chunks::{Chunks, ReplicationRate},
+    encryption,
     event_mapping::{map_routing_event, LazyError, Mapping, MsgContext},
     network::Network,
     node_ops::NodeDuty,
     state_db::{get_reward_pk, store_new_reward_keypair},
-    Config, Error, Result,
+    Config, Error, Result, WriteAckPolicy,
 };
 use log::{error, info};
 use rand::rngs::OsRng;
@@ -30,10 +31,44 @@ use sn_routing::{
     EventStream, {Prefix, XorName},
 };
 use std::{
+    collections::BTreeSet,
     fmt::{self, Display, Formatter},
     net::SocketAddr,
     path::{Path, PathBuf},
+    time::Duration,
 };
+use tokio::time::interval;
+
+/// How often a batch of stored chunks is verified against their checksums.
+const CHUNK_SCRUB_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How often Elders challenge chunk holders to prove they still store them. Holders not
+/// answering by the next round fail the challenge.
+const CHUNK_CHALLENGE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often Elders time out the operations on chunks whose holders didn't answer in time.
+const OP_EXPIRY_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often Elders check for reads of chunks whose holders are too slow, to query their next
+/// holders.
+const READ_HEDGE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often Elders audit how many copies are left of a sample of chunks. Holders not answering
+/// by the next round count as not holding the chunk.
+const REDUNDANCY_AUDIT_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// How often Adults check for chunk handoffs due to be retried.
+const HANDOFF_RETRY_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often Adults send the chunks their replication rate allows, off their replication queue.
+const REPLICATION_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often Adults report to Elders how much storage they use.
+const STORAGE_REPORT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often Elders move chunks off the Adults nearing capacity. Chunks not moved by the next
+/// round are left where they are.
+const REBALANCE_INTERVAL: Duration = Duration::from_secs(2 * 60);
 
 /// Static info about the node.
 #[derive(Clone)]
@@ -42,6 +77,15 @@ pub struct NodeInfo {
     pub root_dir: PathBuf,
     /// The key used by the node to receive earned rewards.
     pub reward_key: PublicKey,
+    /// How the node's `ChunkStore`s hold their chunks, and the key its stores are encrypted with.
+    pub store_options: StoreOptions,
+    /// Whether blobs are stored erasure-coded while the node is an Elder.
+    pub erasure_coding: bool,
+    /// How many holders must store a blob before its write is confirmed to the client while the
+    /// node is an Elder.
+    pub write_ack_policy: WriteAckPolicy,
+    /// How fast chunks are sent to be republished after churn while the node is an Adult.
+    pub replication_rate: ReplicationRate,
 }
 
 impl NodeInfo {
@@ -77,16 +121,37 @@ impl Node {
             }
         };
 
+        // Fails early if the key isn't the one the stored data was encrypted with.
+        let encryption_key = encryption::load_key(config, root_dir)?;
+
         let (network_api, network_events) = Network::new(root_dir, config).await?;
 
         let node_info = NodeInfo {
             root_dir: root_dir_buf,
             reward_key,
+            store_options: StoreOptions {
+                backend: config.storage_backend(),
+                compress: config.compress_chunks(),
+                encryption_key,
+            },
+            erasure_coding: config.erasure_coding(),
+            write_ack_policy: config.write_ack_policy(),
+            replication_rate: ReplicationRate {
+                bytes_per_sec: config.replication_bandwidth(),
+                chunks_per_sec: config.replication_msg_rate(),
+            },
         };
 
         let node = Self {
             role: Role::Adult(AdultRole {
-                chunks: Chunks::new(node_info.root_dir.as_path(), config.max_capacity()).await?,
+                chunks: Chunks::new(
+                    node_info.root_dir.as_path(),
+                    config.max_capacity(),
+                    &node_info.store_options,
+                    node_info.replication_rate,
+                )
+                .await?,
+                full_adults: BTreeSet::new(),
             }),
             node_info,
             used_space: UsedSpace::new(config.max_capacity()),
@@ -118,11 +183,34 @@ impl Node {
     /// Blocks until the node is terminated, which is done
     /// by client sending in a `Command` to free it.
     pub async fn run(&mut self) -> Result<()> {
-        while let Some(event) = self.network_events.next().await {
-            // tokio spawn should only be needed around intensive tasks, ie sign/verify
-            match map_routing_event(event, &self.network_api).await {
-                Mapping::Ok { op, ctx } => self.process_while_any(op, ctx).await,
-                Mapping::Error(error) => handle_error(error),
+        let mut scrub_timer = interval(CHUNK_SCRUB_INTERVAL);
+        let mut challenge_timer = interval(CHUNK_CHALLENGE_INTERVAL);
+        let mut expiry_timer = interval(OP_EXPIRY_INTERVAL);
+        let mut hedge_timer = interval(READ_HEDGE_INTERVAL);
+        let mut audit_timer = interval(REDUNDANCY_AUDIT_INTERVAL);
+        let mut handoff_timer = interval(HANDOFF_RETRY_INTERVAL);
+        let mut replication_timer = interval(REPLICATION_INTERVAL);
+        let mut storage_report_timer = interval(STORAGE_REPORT_INTERVAL);
+        let mut rebalance_timer = interval(REBALANCE_INTERVAL);
+        loop {
+            tokio::select! {
+                event = self.network_events.next() => match event {
+                    // tokio spawn should only be needed around intensive tasks, ie sign/verify
+                    Some(event) => match map_routing_event(event, &self.network_api).await {
+                        Mapping::Ok { op, ctx } => self.process_while_any(op, ctx).await,
+                        Mapping::Error(error) => handle_error(error),
+                    },
+                    None => break,
+                },
+                _ = scrub_timer.tick() => self.process_while_any(NodeDuty::ScrubChunks, None).await,
+                _ = challenge_timer.tick() => self.process_while_any(NodeDuty::ChallengeHolders, None).await,
+                _ = expiry_timer.tick() => self.process_while_any(NodeDuty::ExpirePendingOps, None).await,
+                _ = hedge_timer.tick() => self.process_while_any(NodeDuty::HedgeReads, None).await,
+                _ = audit_timer.tick() => self.process_while_any(NodeDuty::AuditRedundancy, None).await,
+                _ = handoff_timer.tick() => self.process_while_any(NodeDuty::RetryHandoffs, None).await,
+                _ = replication_timer.tick() => self.process_while_any(NodeDuty::ReplicateQueuedChunks, None).await,
+                _ = storage_report_timer.tick() => self.process_while_any(NodeDuty::ReportStorageLevel, None).await,
+                _ = rebalance_timer.tick() => self.process_while_any(NodeDuty::RebalanceChunks, None).await,
             }
         }
 
@@ -7,11 +7,12 @@
 // permissions and limitations relating to use of the SAFE Network Software.
 
 use crate::{
+    capacity::select_read_holders,
     chunks::Chunks,
-    metadata::CHUNK_COPY_COUNT,
+    metadata::{CHUNK_COPY_COUNT, SHARD_COPY_COUNT},
     node_ops::{NodeDuties, NodeDuty, OutgoingMsg},
+    Result,
 };
-use itertools::Itertools;
 use log::{info, trace, warn};
 use sn_data_types::{Blob, BlobAddress};
 use sn_messaging::{
@@ -24,6 +25,22 @@ use std::collections::BTreeSet;
 pub(crate) struct AdultRole {
     // immutable chunks
     pub chunks: Chunks,
+    // Adults our Elders told us are full, which keep their chunks but aren't picked for new ones
+    pub full_adults: BTreeSet<XorName>,
+}
+
+// The Adults chunk holders are picked from, before or after a change.
+struct AdultsView<'a> {
+    adults: &'a BTreeSet<XorName>,
+    full_adults: &'a BTreeSet<XorName>,
+}
+
+impl AdultsView<'_> {
+    fn holders(&self, addr: &BlobAddress, copy_count: usize) -> BTreeSet<XorName> {
+        select_read_holders(addr.name(), self.adults, self.full_adults, copy_count)
+            .into_iter()
+            .collect()
+    }
 }
 
 impl AdultRole {
@@ -33,77 +50,178 @@ impl AdultRole {
         new_adults: BTreeSet<XorName>,
         lost_adults: BTreeSet<XorName>,
         remaining: BTreeSet<XorName>,
-    ) -> NodeDuties {
-        let keys = self.chunks.keys();
-        let mut data_for_replication = BTreeSet::new();
+    ) -> Result<NodeDuties> {
+        let old_adult_list = remaining.union(&lost_adults).copied().collect();
+        let new_adult_list = remaining.union(&new_adults).copied().collect();
+        let full_adults = self.full_adults.clone();
+        let old = AdultsView {
+            adults: &old_adult_list,
+            full_adults: &full_adults,
+        };
+        let new = AdultsView {
+            adults: &new_adult_list,
+            full_adults: &full_adults,
+        };
+        self.reorganize(our_name, &old, &new).await
+    }
+
+    /// Takes the Adults our Elders report full, republishing the chunks whose holders change
+    /// with them.
+    ///
+    /// Adults filling up keep holding their chunks, so no chunk needs new copies then. Only
+    /// Adults with room again take back the chunks stored in their place meanwhile.
+    pub async fn update_full_adults(
+        &mut self,
+        our_name: XorName,
+        full_adults: BTreeSet<XorName>,
+        adults: BTreeSet<XorName>,
+    ) -> Result<NodeDuties> {
+        if full_adults == self.full_adults {
+            return Ok(vec![]);
+        }
+        info!("Full Adults are now {:?}", full_adults);
+        let room_again = !self.full_adults.is_subset(&full_adults);
+        let old_full_adults = std::mem::replace(&mut self.full_adults, full_adults.clone());
+        if !room_again {
+            return Ok(vec![]);
+        }
+        let old = AdultsView {
+            adults: &adults,
+            full_adults: &old_full_adults,
+        };
+        let new = AdultsView {
+            adults: &adults,
+            full_adults: &full_adults,
+        };
+        self.reorganize(our_name, &old, &new).await
+    }
+
+    async fn reorganize(
+        &mut self,
+        our_name: XorName,
+        old: &AdultsView<'_>,
+        new: &AdultsView<'_>,
+    ) -> Result<NodeDuties> {
+        let keys = self.chunks.keys().await;
+        let mut data_for_replication = vec![];
         for addr in keys.iter() {
-            if let Some(data) = self
-                .republish_and_cache(addr, &our_name, &new_adults, &lost_adults, &remaining)
-                .await
+            if let Some(copies_left) = self.republish_and_hand_off(addr, &our_name, old, new).await
             {
-                let _ = data_for_replication.insert(data);
+                data_for_replication.push((*addr, copies_left));
             }
         }
-        data_for_replication
+        info!(
+            "Queueing {} chunks for republishing",
+            data_for_replication.len()
+        );
+        self.chunks.queue_replication(data_for_replication)?;
+        self.replicate_queued().await
+    }
+
+    /// Asks Elders again to republish the chunks being handed off whose new holders weren't
+    /// confirmed in time.
+    pub async fn retry_handoffs(&mut self) -> Result<NodeDuties> {
+        // Nothing was lost, we still have the chunks.
+        self.chunks.queue_due_handoffs(CHUNK_COPY_COUNT)?;
+        self.replicate_queued().await
+    }
+
+    /// Sends queued chunks to Elders to be republished, as fast as the replication rate allows.
+    pub async fn replicate_queued(&mut self) -> Result<NodeDuties> {
+        Ok(self
+            .chunks
+            .next_replications()
+            .await?
             .into_iter()
-            .map(|data| {
-                let data_name = *data.name();
-                NodeDuty::Send(OutgoingMsg {
-                    msg: Message::NodeCmd {
-                        cmd: NodeCmd::System(NodeSystemCmd::RepublishChunk(data)),
-                        id: MessageId::new(),
-                    },
-                    dst: DstLocation::Section(data_name),
-                    section_source: false,
-                    aggregation: Aggregation::None,
-                })
-            })
-            .collect::<Vec<_>>()
+            .map(republish_msg)
+            .collect())
     }
 
-    async fn republish_and_cache(
+    // Returns the number of copies left of a chunk to be republished, counting the old holders
+    // still around.
+    //
+    // A chunk we no longer hold is kept, marked as leaving, until Elders confirm that its new
+    // holders stored it.
+    async fn republish_and_hand_off(
         &mut self,
         addr: &BlobAddress,
         our_name: &XorName,
-        new_adults: &BTreeSet<XorName>,
-        lost_adults: &BTreeSet<XorName>,
-        remaining: &BTreeSet<XorName>,
-    ) -> Option<Blob> {
-        let old_adult_list = remaining.union(lost_adults).copied().collect();
-        let new_adult_list = remaining.union(new_adults).copied().collect();
-        let new_holders = self.compute_holders(addr, &new_adult_list);
-        let old_holders = self.compute_holders(addr, &old_adult_list);
+        old: &AdultsView<'_>,
+        new: &AdultsView<'_>,
+    ) -> Option<usize> {
+        let changes = |copy_count| {
+            let new_holders = new.holders(addr, copy_count);
+            let old_holders = old.holders(addr, copy_count);
+            (
+                !new_holders.contains(our_name),
+                !new_holders.is_subset(&old_holders),
+                !old_holders.is_subset(new.adults),
+                old_holders.intersection(new.adults).count(),
+            )
+        };
 
-        let we_are_not_holder_anymore = !new_holders.contains(our_name);
-        let new_adult_is_holder = !new_holders.is_disjoint(new_adults);
-        let lost_old_holder = !old_holders.is_disjoint(lost_adults);
+        let (
+            mut we_are_not_holder_anymore,
+            mut new_holder_picked,
+            mut lost_old_holder,
+            mut copies_left,
+        ) = changes(CHUNK_COPY_COUNT);
+        let leaving = self.chunks.is_leaving(addr);
+        if !(we_are_not_holder_anymore || new_holder_picked || lost_old_holder || leaving) {
+            return None;
+        }
+        if !self.chunks.has_chunk(addr).await {
+            return None;
+        }
+        // The single holder of a shard is among the holders of a full copy, so its changes
+        // are among theirs.
+        if self.chunks.is_shard(addr) {
+            let (not_holder, new_holder, lost_holder, left) = changes(SHARD_COPY_COUNT);
+            we_are_not_holder_anymore = not_holder;
+            new_holder_picked = new_holder;
+            lost_old_holder = lost_holder;
+            copies_left = left;
+        }
+        if leaving && !we_are_not_holder_anymore {
+            info!("Holding chunk at {:?} again", addr);
+            if let Err(error) = self.chunks.cancel_handoff(addr) {
+                warn!(
+                    "Could not cancel the handoff of chunk {:?}: {:?}",
+                    addr, error
+                );
+            }
+        }
+        if !(we_are_not_holder_anymore || new_holder_picked || lost_old_holder) {
+            return None;
+        }
 
-        if we_are_not_holder_anymore || new_adult_is_holder || lost_old_holder {
-            info!("Republishing chunk at {:?}", addr);
-            trace!("We are not a holder anymore? {}, New Adult is Holder? {}, Lost Adult was holder? {}", we_are_not_holder_anymore, new_adult_is_holder, lost_old_holder);
-            let chunk = self.chunks.get_chunk(addr).ok()?;
-            if we_are_not_holder_anymore {
-                if let Err(err) = self.chunks.remove_chunk(addr).await {
-                    warn!("Error deleting chunk during republish: {:?}", err);
-                }
+        info!("Republishing chunk at {:?}", addr);
+        trace!(
+            "We are not a holder anymore? {}, New holder picked? {}, Lost Adult was holder? {}",
+            we_are_not_holder_anymore,
+            new_holder_picked,
+            lost_old_holder
+        );
+        if we_are_not_holder_anymore {
+            match self.chunks.start_handoff(*addr) {
+                Ok(true) => info!("Handing off chunk at {:?}", addr),
+                Ok(false) => (),
+                Err(error) => warn!("Could not hand off chunk {:?}: {:?}", addr, error),
             }
-            // TODO: Push to LRU cache
-            Some(chunk)
-        } else {
-            None
         }
+        Some(copies_left)
     }
+}
 
-    fn compute_holders(
-        &self,
-        addr: &BlobAddress,
-        adult_list: &BTreeSet<XorName>,
-    ) -> BTreeSet<XorName> {
-        adult_list
-            .iter()
-            .sorted_by(|lhs, rhs| addr.name().cmp_distance(lhs, rhs))
-            .take(CHUNK_COPY_COUNT)
-            .cloned()
-            .collect()
-    }
+fn republish_msg(chunk: Blob) -> NodeDuty {
+    let data_name = *chunk.name();
+    NodeDuty::Send(OutgoingMsg {
+        msg: Message::NodeCmd {
+            cmd: NodeCmd::System(NodeSystemCmd::RepublishChunk(chunk)),
+            id: MessageId::new(),
+        },
+        dst: DstLocation::Section(data_name),
+        section_source: false,
+        aggregation: Aggregation::None,
+    })
 }
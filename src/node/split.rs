@@ -7,7 +7,7 @@
 // permissions and limitations relating to use of the SAFE Network Software.
 
 use crate::{
-    node::interaction::push_state,
+    node::interaction::{push_chunk_holders, push_shard_maps, push_state, push_tombstones},
     node_ops::NodeDuties,
     section_funds::{self, SectionFunds},
     transfers::get_replicas::replica_info,
@@ -127,17 +127,41 @@ impl Node {
 
         // replicate state to our new elders
         let msg_id = MessageId::combine(vec![our_prefix.name(), XorName::from(our_key)]);
+        ops.push(push_shard_maps(elder, our_prefix, our_new_elders.clone()));
+        ops.push(push_chunk_holders(
+            elder,
+            our_prefix,
+            our_new_elders.clone(),
+        ));
+        ops.push(push_tombstones(elder, our_prefix, our_new_elders.clone()));
         ops.push(push_state(elder, our_prefix, msg_id, our_new_elders).await?);
 
         // replicate state to our neighbour's new elders
         let msg_id = MessageId::combine(vec![sibling_prefix.name(), XorName::from(sibling_key)]);
+        ops.push(push_shard_maps(
+            elder,
+            sibling_prefix,
+            their_new_elders.clone(),
+        ));
+        ops.push(push_chunk_holders(
+            elder,
+            sibling_prefix,
+            their_new_elders.clone(),
+        ));
+        ops.push(push_tombstones(
+            elder,
+            sibling_prefix,
+            their_new_elders.clone(),
+        ));
         ops.push(push_state(elder, sibling_prefix, msg_id, their_new_elders).await?);
 
         // drop metadata state
-        elder
-            .meta_data
-            .retain_members_only(self.network_api.our_adults().await)
-            .await?;
+        ops.extend(
+            elder
+                .meta_data
+                .retain_members_only(self.network_api.our_adults().await)
+                .await?,
+        );
 
         // drop transfers state
         elder.transfers.keep_keys_of(our_prefix).await?;
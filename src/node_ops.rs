@@ -6,11 +6,17 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
+use crate::{
+    capacity::StorageLevel,
+    erasure::ShardMap,
+    storage_msg::{ChunkProof, Nonce, StorageMsg},
+};
 #[cfg(feature = "simulated-payouts")]
 use sn_data_types::Transfer;
 use sn_data_types::{
-    ActorHistory, Blob, CreditAgreementProof, NodeAge, PublicKey, RewardAccumulation,
-    RewardProposal, SignedTransfer, TransferAgreementProof,
+    register::Address as RegisterAddress, ActorHistory, Blob, BlobAddress, CreditAgreementProof,
+    NodeAge, PublicKey, RewardAccumulation, RewardProposal, SequenceAddress, SignedTransfer,
+    TransferAgreementProof,
 };
 use sn_messaging::{
     client::{BlobRead, BlobWrite, CmdError, DataExchange, Message, QueryResponse},
@@ -90,6 +96,7 @@ pub enum NodeDuty {
     ProcessRepublish {
         chunk: Blob,
         msg_id: MessageId,
+        src: XorName,
     },
     /// Run at data-section Elders on receiving the result of
     /// write operations from Adults
@@ -188,11 +195,141 @@ pub enum NodeDuty {
     },
     /// Storage reaching max capacity.
     ReachingMaxCapacity,
+    /// Storage back under the level at which it's available for new chunks.
+    StorageAvailable,
+    /// Tell our Elders how much storage we use.
+    /// This is run at Adults.
+    ReportStorageLevel,
+    /// Run at data-section Elders on receiving
+    /// the storage level an Adult reported
+    RecordStorageLevel {
+        level: StorageLevel,
+        src: XorName,
+    },
+    /// Verify the next batch of stored chunks against their checksums.
+    /// This is run at Adults.
+    ScrubChunks,
+    /// Challenge the holders of a sample of chunks to prove they still store them.
+    /// This is run at Elders.
+    ChallengeHolders,
+    /// Prove to an Elder that we still store a chunk.
+    /// This is run at Adults.
+    ProveChunk {
+        address: BlobAddress,
+        nonce: Nonce,
+        elder: XorName,
+    },
+    /// Run at data-section Elders on receiving the proof
+    /// of storage of a chunk from one of its holders
+    RecordChunkProof {
+        address: BlobAddress,
+        nonce: Nonce,
+        proof: Option<ChunkProof>,
+        src: XorName,
+    },
+    /// Time out the operations on chunks whose holders didn't answer in time.
+    /// This is run at Elders.
+    ExpirePendingOps,
+    /// Query the next holders of the chunks being read for clients, where the holders queried so
+    /// far are slow.
+    /// This is run at Elders.
+    HedgeReads,
+    /// Ask the holders of a sample of chunks whether they hold them, storing again those with too
+    /// few copies.
+    /// This is run at Elders.
+    AuditRedundancy,
+    /// Tell an Elder whether we hold a chunk.
+    /// This is run at Adults.
+    ReportChunkHeld {
+        address: BlobAddress,
+        elder: XorName,
+    },
+    /// Run at data-section Elders on receiving the answer
+    /// of a holder to whether it holds a chunk
+    RecordChunkHeld {
+        address: BlobAddress,
+        held: bool,
+        src: XorName,
+    },
+    /// Ask Elders again to republish the chunks being handed off whose new holders weren't
+    /// confirmed in time.
+    /// This is run at Adults.
+    RetryHandoffs,
+    /// Send queued chunks to Elders to be republished, as fast as the replication rate allows.
+    /// This is run at Adults.
+    ReplicateQueuedChunks,
+    /// Record that a chunk Elders write to us is a shard, which we hold alone.
+    /// This is run at Adults.
+    MarkShard {
+        address: BlobAddress,
+        src: XorName,
+    },
+    /// Let go of a chunk handed off, whose new holders Elders confirmed.
+    /// This is run at Adults.
+    CompleteHandoff {
+        address: BlobAddress,
+        src: XorName,
+    },
+    /// Restore a chunk at the holder which found its copy corrupt.
+    /// This is run at Elders.
+    RestoreChunk {
+        address: BlobAddress,
+        src: XorName,
+    },
+    /// Move chunks off the Adults nearing capacity to emptier ones.
+    /// This is run at Elders.
+    RebalanceChunks,
+    /// Run at Elders, and at the Adult a chunk was moved off,
+    /// on an Elder confirming the Adult it was moved to stored it
+    RecordChunkMoved {
+        address: BlobAddress,
+        from: XorName,
+        to: XorName,
+        src: XorName,
+    },
+    /// Store a new blob at the holders the Elder closest to it picked.
+    /// This is run at Elders.
+    RecordPlacement {
+        msg_id: MessageId,
+        address: BlobAddress,
+        holders: Vec<BTreeSet<XorName>>,
+        src: XorName,
+    },
+    /// Take over the shard maps of erasure-coded blobs from another Elder.
+    /// This is run at Elders.
+    UpdateShardMaps {
+        maps: Vec<(BlobAddress, ShardMap)>,
+        src: XorName,
+    },
+    /// Take over the chunks stored in the section, with their holders, from another Elder.
+    /// This is run at Elders.
+    UpdateChunkHolders {
+        holders: Vec<(BlobAddress, BTreeSet<XorName>)>,
+        src: XorName,
+    },
+    /// Take over the addresses of the data deleted from another Elder.
+    /// This is run at Elders.
+    UpdateTombstones {
+        sequences: Vec<SequenceAddress>,
+        registers: Vec<RegisterAddress>,
+        src: XorName,
+    },
+    /// Take the Adults our Elders report full, and republish the chunks whose holders change.
+    /// This is run at Adults.
+    UpdateFullAdults {
+        full_adults: BTreeSet<XorName>,
+        src: XorName,
+    },
     /// Increment count of full nodes in the network
     IncrementFullNodeCount {
         /// Node ID of node that reached max capacity.
         node_id: PublicKey,
     },
+    /// Decrement count of full nodes in the network
+    DecrementFullNodeCount {
+        /// Name of node that has storage available again.
+        node_name: XorName,
+    },
     /// Sets joining allowed to true or false.
     SetNodeJoinsAllowed(bool),
     /// Send a message to the specified dst.
@@ -203,6 +340,11 @@ pub enum NodeDuty {
         targets: BTreeSet<XorName>,
         aggregation: Aggregation,
     },
+    /// Send a storage message to each individual node.
+    SendStorageMsg {
+        msg: StorageMsg,
+        targets: BTreeSet<XorName>,
+    },
     /// Process read of data
     ProcessRead {
         query: sn_messaging::client::DataQuery,
@@ -287,9 +429,38 @@ impl Debug for NodeDuty {
             Self::GetSectionElders { .. } => write!(f, "GetSectionElders"),
             Self::NoOp => write!(f, "No op."),
             Self::ReachingMaxCapacity => write!(f, "ReachingMaxCapacity"),
+            Self::StorageAvailable => write!(f, "StorageAvailable"),
+            Self::ReportStorageLevel => write!(f, "ReportStorageLevel"),
+            Self::RecordStorageLevel { level, src } => write!(
+                f,
+                "RecordStorageLevel {{ level: {:?}, src: {} }}",
+                level, src
+            ),
+            Self::ScrubChunks => write!(f, "ScrubChunks"),
+            Self::ChallengeHolders => write!(f, "ChallengeHolders"),
+            Self::ProveChunk { .. } => write!(f, "ProveChunk"),
+            Self::RecordChunkProof { .. } => write!(f, "RecordChunkProof"),
+            Self::ExpirePendingOps => write!(f, "ExpirePendingOps"),
+            Self::HedgeReads => write!(f, "HedgeReads"),
+            Self::AuditRedundancy => write!(f, "AuditRedundancy"),
+            Self::ReportChunkHeld { .. } => write!(f, "ReportChunkHeld"),
+            Self::RecordChunkHeld { .. } => write!(f, "RecordChunkHeld"),
+            Self::UpdateShardMaps { .. } => write!(f, "UpdateShardMaps"),
+            Self::UpdateChunkHolders { .. } => write!(f, "UpdateChunkHolders"),
+            Self::UpdateTombstones { .. } => write!(f, "UpdateTombstones"),
+            Self::UpdateFullAdults { .. } => write!(f, "UpdateFullAdults"),
+            Self::RetryHandoffs => write!(f, "RetryHandoffs"),
+            Self::ReplicateQueuedChunks => write!(f, "ReplicateQueuedChunks"),
+            Self::MarkShard { .. } => write!(f, "MarkShard"),
+            Self::CompleteHandoff { .. } => write!(f, "CompleteHandoff"),
+            Self::RestoreChunk { .. } => write!(f, "RestoreChunk"),
+            Self::RebalanceChunks => write!(f, "RebalanceChunks"),
+            Self::RecordChunkMoved { .. } => write!(f, "RecordChunkMoved"),
+            Self::RecordPlacement { .. } => write!(f, "RecordPlacement"),
             Self::ProcessLostMember { .. } => write!(f, "ProcessLostMember"),
             //Self::ProcessRelocatedMember { .. } => write!(f, "ProcessRelocatedMember"),
             Self::IncrementFullNodeCount { .. } => write!(f, "IncrementFullNodeCount"),
+            Self::DecrementFullNodeCount { .. } => write!(f, "DecrementFullNodeCount"),
             Self::SetNodeJoinsAllowed(_) => write!(f, "SetNodeJoinsAllowed"),
             Self::Send(msg) => write!(f, "Send [ msg: {:?} ]", msg),
             Self::SendToNodes {
@@ -301,6 +472,11 @@ impl Debug for NodeDuty {
                 "SendToNodes [ msg: {:?}, targets: {:?}, aggregation: {:?} ]",
                 msg, targets, aggregation
             ),
+            Self::SendStorageMsg { msg, targets } => write!(
+                f,
+                "SendStorageMsg [ msg: {:?}, targets: {:?} ]",
+                msg, targets
+            ),
             Self::ProcessRead { .. } => write!(f, "ProcessRead"),
             Self::ProcessWrite { .. } => write!(f, "ProcessWrite"),
             Self::ProcessDataPayment { .. } => write!(f, "ProcessDataPayment"),
@@ -0,0 +1,140 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Messages between Elders and Adults about the chunks the Adults store.
+//!
+//! `sn_messaging` has no variants for these, so they are sent over routing on their own. A
+//! serialised `StorageMsg` is `MAGIC` followed by the bincode of the message, which tells it apart
+//! from a `Message` on receipt.
+
+use crate::{capacity::StorageLevel, erasure::ShardMap, utils, Error, Result};
+use bytes::{BufMut, Bytes, BytesMut};
+use serde::{Deserialize, Serialize};
+use sn_data_types::{register::Address as RegisterAddress, Blob, BlobAddress, SequenceAddress};
+use sn_messaging::MessageId;
+use std::collections::BTreeSet;
+use xor_name::XorName;
+
+const MAGIC: [u8; 4] = *b"snsm";
+
+/// Random value a proof of storage is computed over, so it can't be precomputed.
+pub(crate) type Nonce = [u8; 32];
+
+/// Hash of a nonce and the content of a chunk, proving the chunk is held.
+pub(crate) type ChunkProof = [u8; 32];
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) enum StorageMsg {
+    /// Elders ask a holder to prove it still stores a chunk.
+    ChallengeChunk { address: BlobAddress, nonce: Nonce },
+    /// A holder answers a `ChallengeChunk`, with no proof if it doesn't have the chunk.
+    ChunkProof {
+        address: BlobAddress,
+        nonce: Nonce,
+        proof: Option<ChunkProof>,
+    },
+    /// Elders ask an expected holder whether it holds a chunk, to count its copies.
+    QueryChunkHeld { address: BlobAddress },
+    /// A holder answers a `QueryChunkHeld`.
+    ChunkHeld { address: BlobAddress, held: bool },
+    /// A holder found its copy of a chunk corrupt, and asks Elders to restore it.
+    ChunkCorrupted { address: BlobAddress },
+    /// An Adult which reported it was full has storage available again.
+    StorageAvailable,
+    /// An Adult reports how much storage it uses.
+    StorageLevel(StorageLevel),
+    /// Elders tell the Adults they write a shard to that it's a shard, which they hold alone.
+    MarkShard { address: BlobAddress },
+    /// Elders confirm to Adults handing a chunk off that its new holders stored it.
+    HandoffComplete { address: BlobAddress },
+    /// Elders tell the Adult a chunk was moved off, and their other Elders, that the Adult it was
+    /// moved to stored it.
+    ChunkMoved {
+        address: BlobAddress,
+        from: XorName,
+        to: XorName,
+    },
+    /// The Elder closest to a new blob tells our other Elders the holders it picked for the write
+    /// `msg_id` of it: those of its chunk, or of each of its shards.
+    Placement {
+        msg_id: MessageId,
+        address: BlobAddress,
+        holders: Vec<BTreeSet<XorName>>,
+    },
+    /// Elders tell Adults which Adults are full, so they pick chunk holders the way Elders do.
+    FullAdults(BTreeSet<XorName>),
+    /// Elders hand the shard maps of erasure-coded blobs over to new Elders.
+    ShardMaps(Vec<(BlobAddress, ShardMap)>),
+    /// Elders hand the chunks stored in the section, with the Adults they were written to, over
+    /// to new Elders.
+    ChunkHolders(Vec<(BlobAddress, BTreeSet<XorName>)>),
+    /// Elders hand the addresses of the data deleted over to new Elders.
+    Tombstones {
+        sequences: Vec<SequenceAddress>,
+        registers: Vec<RegisterAddress>,
+    },
+}
+
+impl StorageMsg {
+    pub(crate) fn serialize(&self) -> Result<Bytes> {
+        let serialised = utils::serialise(self)?;
+        let mut bytes = BytesMut::with_capacity(MAGIC.len() + serialised.len());
+        bytes.put_slice(&MAGIC);
+        bytes.put_slice(&serialised);
+        Ok(bytes.freeze())
+    }
+
+    /// Returns `None` if `bytes` isn't a `StorageMsg` at all.
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Option<Result<Self>> {
+        if !bytes.starts_with(&MAGIC) {
+            return None;
+        }
+        Some(bincode::deserialize(&bytes[MAGIC.len()..]).map_err(Error::Bincode))
+    }
+}
+
+/// Proof that `chunk` is held, for the challenge with `nonce`.
+pub(crate) fn chunk_proof(nonce: &Nonce, chunk: &Blob) -> ChunkProof {
+    XorName::from_content(&[&nonce[..], &chunk.value()[..]]).0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{chunk_proof, StorageMsg};
+    use crate::Result;
+    use sn_data_types::{Blob, PublicBlob};
+
+    #[test]
+    fn round_trip() -> Result<()> {
+        let chunk = Blob::Public(PublicBlob::new(vec![1, 2, 3]));
+        let nonce = rand::random();
+        let msg = StorageMsg::ChunkProof {
+            address: *chunk.address(),
+            nonce,
+            proof: Some(chunk_proof(&nonce, &chunk)),
+        };
+
+        let bytes = msg.serialize()?;
+        assert_eq!(StorageMsg::from_bytes(&bytes).transpose()?, Some(msg));
+        assert!(StorageMsg::from_bytes(&bytes[1..]).is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn proofs_depend_on_nonce_and_content() {
+        let chunk = Blob::Public(PublicBlob::new(vec![1, 2, 3]));
+        let other_chunk = Blob::Public(PublicBlob::new(vec![1, 2, 4]));
+        let (nonce, other_nonce) = (rand::random(), rand::random());
+
+        let proof = chunk_proof(&nonce, &chunk);
+        assert_eq!(proof, chunk_proof(&nonce, &chunk));
+        assert_ne!(proof, chunk_proof(&other_nonce, &chunk));
+        assert_ne!(proof, chunk_proof(&nonce, &other_chunk));
+    }
+}
@@ -7,7 +7,7 @@
 // permissions and limitations relating to use of the SAFE Network Software.
 
 use super::{replica_signing::ReplicaSigning, store::TransferStore};
-use crate::{Error, Result};
+use crate::{encryption::EncryptionKey, Error, Result};
 use bls::PublicKeySet;
 use dashmap::DashMap;
 use futures::lock::Mutex;
@@ -49,6 +49,7 @@ where
     T: ReplicaSigning,
 {
     root_dir: PathBuf,
+    encryption_key: Option<EncryptionKey>,
     info: ReplicaInfo<T>,
     locks: WalletLocks,
     self_lock: Arc<Mutex<usize>>,
@@ -57,11 +58,13 @@ where
 impl<T: ReplicaSigning> Replicas<T> {
     pub(crate) async fn new(
         root_dir: PathBuf,
+        encryption_key: Option<EncryptionKey>,
         info: ReplicaInfo<T>,
         user_wallets: BTreeMap<PublicKey, ActorHistory>,
     ) -> Result<Self> {
         let instance = Self {
             root_dir,
+            encryption_key,
             info,
             locks: DashMap::new(),
             self_lock: Arc::new(Mutex::new(0)),
@@ -143,8 +146,10 @@ impl<T: ReplicaSigning> Replicas<T> {
             .locks
             .iter()
             .map(|r| *r.key())
-            .filter_map(|id| TransferStore::new(id.into(), &self.root_dir).ok())
+            .filter_map(|id| self.open_store(id).ok())
             .map(|store| store.get_all())
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
             .flatten()
             .collect();
         Ok(events)
@@ -152,7 +157,7 @@ impl<T: ReplicaSigning> Replicas<T> {
 
     /// History of actor
     pub fn history(&self, id: PublicKey) -> Result<ActorHistory> {
-        let store = TransferStore::new(id.into(), &self.root_dir);
+        let store = self.open_store(id);
 
         if let Err(error) = store {
             // hmm.. can we handle this in a better way?
@@ -169,7 +174,7 @@ impl<T: ReplicaSigning> Replicas<T> {
         };
 
         let store = store?;
-        let events = store.get_all();
+        let events = store.get_all()?;
 
         if events.is_empty() {
             return Ok(ActorHistory::empty());
@@ -215,7 +220,7 @@ impl<T: ReplicaSigning> Replicas<T> {
     ///
     pub async fn balance(&self, id: PublicKey) -> Result<Token> {
         debug!("Replica: Getting balance of: {:?}", id);
-        let store = match TransferStore::new(id.into(), &self.root_dir) {
+        let store = match self.open_store(id) {
             Ok(store) => store,
             // store load failed, so we return 0 balance
             Err(_) => return Ok(Token::from_nano(0)),
@@ -351,7 +356,7 @@ impl<T: ReplicaSigning> Replicas<T> {
                     Ok(store) => store,
                     Err(_) => {
                         // no key lock (hence no store), so we create one
-                        let store = TransferStore::new(id.into(), &self.root_dir)?;
+                        let store = self.open_store(id)?;
                         let locked_store = Arc::new(Mutex::new(store));
                         let _ = self.locks.insert(id, locked_store.clone());
                         let _ = self_lock.overflowing_add(0); // resolve: is a usage at end of block necessary to actually engage the lock?
@@ -399,7 +404,7 @@ impl<T: ReplicaSigning> Replicas<T> {
         store: &TransferStore<ReplicaEvent>,
         id: OwnerType,
     ) -> Result<WalletReplica> {
-        let events = store.get_all();
+        let events = store.get_all()?;
         let wallet = WalletReplica::from_history(
             id,
             self.info.id,
@@ -410,6 +415,10 @@ impl<T: ReplicaSigning> Replicas<T> {
         Ok(wallet)
     }
 
+    fn open_store(&self, id: PublicKey) -> Result<TransferStore<ReplicaEvent>> {
+        TransferStore::new(id.into(), &self.root_dir, self.encryption_key.clone())
+    }
+
     fn exists_in_chain(&self, key: &bls::PublicKey) -> bool {
         self.info
             .section_chain
@@ -426,10 +435,10 @@ impl<T: ReplicaSigning> Replicas<T> {
         let key_lock = match self.load_key_lock(id).await {
             Ok(lock) => lock,
             Err(_) => {
-                let store = match TransferStore::new(id.into(), &self.root_dir) {
+                let store = match self.open_store(id) {
                     Ok(store) => store,
                     // no key lock, so we create one for this payout...
-                    Err(_e) => TransferStore::new(id.into(), &self.root_dir)?,
+                    Err(_e) => self.open_store(id)?,
                 };
                 let locked_store = Arc::new(Mutex::new(store));
                 let _ = self.locks.insert(id, locked_store.clone());
@@ -6,7 +6,11 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
-use crate::{to_db_key::ToDbKey, utils, Error, Result};
+use crate::{
+    encryption::{self, EncryptionKey},
+    to_db_key::ToDbKey,
+    utils, Error, Result,
+};
 use pickledb::PickleDb;
 use serde::{de::DeserializeOwned, Serialize};
 use std::{
@@ -20,9 +24,13 @@ const TRANSFERS_DIR_NAME: &str = "transfers";
 const DB_EXTENSION: &str = ".db";
 
 /// Disk storage for transfers.
+///
+/// If there's an encryption key, events are stored encrypted. Events stored before encryption was
+/// enabled are still read as they are.
 pub struct TransferStore<TEvent: Debug + Serialize + DeserializeOwned> {
     db: PickleDb,
     db_path: PathBuf,
+    encryption_key: Option<EncryptionKey>,
     _phantom: PhantomData<TEvent>,
 }
 
@@ -30,13 +38,18 @@ impl<'a, TEvent: Debug + Serialize + DeserializeOwned> TransferStore<TEvent>
 where
     TEvent: 'a,
 {
-    pub fn new(id: XorName, root_dir: &Path) -> Result<Self> {
+    pub fn new(
+        id: XorName,
+        root_dir: &Path,
+        encryption_key: Option<EncryptionKey>,
+    ) -> Result<Self> {
         let db_dir = root_dir.join(Path::new(TRANSFERS_DIR_NAME));
         let db_name = format!("{}{}", id.to_db_key()?, DB_EXTENSION);
         let db_path = db_dir.join(db_name.clone());
         Ok(Self {
             db: utils::new_auto_dump_db(db_dir.as_path(), db_name)?,
             db_path,
+            encryption_key,
             _phantom: PhantomData::default(),
         })
     }
@@ -45,30 +58,26 @@ where
         std::fs::remove_file(self.db_path.as_path()).map_err(Error::Io)
     }
 
+    /// Returns all events, in the order they were stored.
     ///
-    pub fn get_all(&self) -> Vec<TEvent> {
+    /// Fails if any event can't be read, e.g. with `Error::InvalidEncryptionKey` if it was
+    /// encrypted with another key, rather than leave a gap in the history.
+    pub fn get_all(&self) -> Result<Vec<TEvent>> {
         let keys = self.db.get_all();
 
-        let mut events: Vec<(usize, TEvent)> = keys
+        let mut events = keys
             .iter()
-            .filter_map(|key| {
-                let value = self.db.get::<TEvent>(key);
-                let key = key.parse::<usize>();
-                match value {
-                    Some(v) => match key {
-                        Ok(k) => Some((k, v)),
-                        _ => None,
-                    },
-                    None => None,
-                }
+            .map(|key| {
+                let index = key
+                    .parse::<usize>()
+                    .map_err(|_| Error::Logic(format!("Invalid event key: {}", key)))?;
+                Ok((index, self.get(key)?))
             })
-            .collect();
+            .collect::<Result<Vec<(usize, TEvent)>>>()?;
 
-        events.sort_by(|(key_a, _), (key_b, _)| key_a.partial_cmp(key_b).unwrap());
+        events.sort_by_key(|(index, _)| *index);
 
-        let events: Vec<TEvent> = events.into_iter().map(|(_, val)| val).collect();
-
-        events
+        Ok(events.into_iter().map(|(_, event)| event).collect())
     }
 
     ///
@@ -80,14 +89,33 @@ where
                 key, event
             )));
         }
-        self.db.set(key, &event).map_err(Error::PickleDb)
+        match &self.encryption_key {
+            Some(encryption_key) => {
+                let value = encryption_key.encrypt(&utils::serialise(&event)?)?;
+                self.db.set(key, &value).map_err(Error::PickleDb)
+            }
+            None => self.db.set(key, &event).map_err(Error::PickleDb),
+        }
+    }
+
+    fn get(&self, key: &str) -> Result<TEvent> {
+        match self.db.get::<Vec<u8>>(key) {
+            Some(value) if encryption::is_encrypted(&value) => {
+                let serialised = encryption::decrypt(self.encryption_key.as_ref(), &value)?;
+                utils::deserialise(&serialised)
+            }
+            _ => self
+                .db
+                .get::<TEvent>(key)
+                .ok_or_else(|| Error::Logic(format!("Could not read event {}", key))),
+        }
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::TransferStore;
-    use crate::{Error, Result};
+    use crate::{encryption::EncryptionKey, Error, Result};
     use bls::SecretKeySet;
     use bls::{PublicKeySet, SecretKey, SecretKeyShare};
     use sn_data_types::{
@@ -102,7 +130,7 @@ mod test {
         let id = xor_name::XorName::random();
         let tmp_dir = TempDir::new("root")?;
         let root_dir = tmp_dir.into_path();
-        let mut store = TransferStore::new(id, &root_dir)?;
+        let mut store = TransferStore::new(id, &root_dir, None)?;
         let wallet_id = get_random_pk();
         let mut rng = rand::thread_rng();
         let bls_secret_key = SecretKeySet::random(0, &mut rng);
@@ -116,7 +144,7 @@ mod test {
             credit_proof: genesis_credit_proof.clone(),
         }))?;
 
-        let events = store.get_all();
+        let events = store.get_all()?;
         assert_eq!(events.len(), 1);
 
         match &events[0] {
@@ -134,6 +162,63 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn encrypted_history() -> Result<()> {
+        let id = xor_name::XorName::random();
+        let tmp_dir = TempDir::new("root")?;
+        let root_dir = tmp_dir.into_path();
+        let wallet_id = get_random_pk();
+        let mut rng = rand::thread_rng();
+        let bls_secret_key = SecretKeySet::random(0, &mut rng);
+        let mut credit_proofs = vec![];
+        for balance in &[10, 20] {
+            credit_proofs.push(get_credit(
+                *balance,
+                wallet_id,
+                bls_secret_key.public_keys(),
+                bls_secret_key.secret_key_share(0),
+            )?);
+        }
+
+        // An event stored before encryption was enabled, and one stored encrypted.
+        let mut store = TransferStore::new(id, &root_dir, None)?;
+        store.try_insert(ReplicaEvent::TransferPropagated(TransferPropagated {
+            credit_proof: credit_proofs[0].clone(),
+        }))?;
+        let encryption_key = EncryptionKey::from_passphrase("passphrase", &root_dir)?;
+        let mut store = TransferStore::new(id, &root_dir, Some(encryption_key.clone()))?;
+        store.try_insert(ReplicaEvent::TransferPropagated(TransferPropagated {
+            credit_proof: credit_proofs[1].clone(),
+        }))?;
+
+        let store = TransferStore::new(id, &root_dir, Some(encryption_key))?;
+        let events = store.get_all()?;
+        assert_eq!(events.len(), 2);
+        for (event, expected_proof) in events.iter().zip(&credit_proofs) {
+            match event {
+                ReplicaEvent::TransferPropagated(TransferPropagated { credit_proof, .. }) => {
+                    assert_eq!(credit_proof, expected_proof)
+                }
+                other => {
+                    return Err(Error::Logic(format!(
+                        "Incorrect Replica event: {:?}",
+                        other
+                    )))
+                }
+            }
+        }
+
+        // Without the key, or with another one, the history isn't read at all.
+        let store: TransferStore<ReplicaEvent> = TransferStore::new(id, &root_dir, None)?;
+        assert!(matches!(store.get_all(), Err(Error::InvalidEncryptionKey)));
+        let other_key = EncryptionKey::from_passphrase("other passphrase", &root_dir)?;
+        let store: TransferStore<ReplicaEvent> =
+            TransferStore::new(id, &root_dir, Some(other_key))?;
+        assert!(matches!(store.get_all(), Err(Error::InvalidEncryptionKey)));
+
+        Ok(())
+    }
+
     fn get_random_pk() -> PublicKey {
         PublicKey::from(SecretKey::random().public_key())
     }
@@ -23,18 +23,34 @@ const NODE_MODULE_NAME: &str = "sn_node";
 pub(crate) fn new_auto_dump_db<D: AsRef<Path>, N: AsRef<Path>>(
     db_dir: D,
     db_name: N,
+) -> Result<PickleDb> {
+    new_db(db_dir, db_name, || PickleDbDumpPolicy::AutoDump)
+}
+
+/// Like `new_auto_dump_db`, but only written to disk on `dump()`, for dbs changed in batches.
+pub(crate) fn new_dump_on_request_db<D: AsRef<Path>, N: AsRef<Path>>(
+    db_dir: D,
+    db_name: N,
+) -> Result<PickleDb> {
+    new_db(db_dir, db_name, || PickleDbDumpPolicy::DumpUponRequest)
+}
+
+fn new_db<D: AsRef<Path>, N: AsRef<Path>>(
+    db_dir: D,
+    db_name: N,
+    dump_policy: fn() -> PickleDbDumpPolicy,
 ) -> Result<PickleDb> {
     let db_path = db_dir.as_ref().join(db_name);
-    match PickleDb::load_bin(db_path.clone(), PickleDbDumpPolicy::AutoDump) {
+    match PickleDb::load_bin(db_path.clone(), dump_policy()) {
         Ok(db) => Ok(db),
         Err(_) => {
             fs::create_dir_all(db_dir)?;
-            let mut db = PickleDb::new_bin(db_path.clone(), PickleDbDumpPolicy::AutoDump);
+            let mut db = PickleDb::new_bin(db_path.clone(), dump_policy());
 
             // dump is needed to actually write the db to disk.
             db.dump()?;
 
-            PickleDb::load_bin(db_path, PickleDbDumpPolicy::AutoDump).map_err(Error::PickleDb)
+            PickleDb::load_bin(db_path, dump_policy()).map_err(Error::PickleDb)
         }
     }
 }